@@ -0,0 +1,238 @@
+use crate::tokenizer_data::{RelOp, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarDecl {
+    pub var: usize,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Call {
+    pub func: usize,
+    pub args: Vec<Expr>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Num(isize),
+    Var(usize),
+    Binary {
+        op: BinOp,
+        lhs: Box<Expr>,
+        rhs: Box<Expr>,
+    },
+    Call(Call),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relation {
+    pub op: RelOp,
+    pub lhs: Expr,
+    pub rhs: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicOp {
+    And,
+    Or,
+}
+
+impl LogicOp {
+    pub fn keyword(&self) -> &'static str {
+        match self {
+            LogicOp::And => "and",
+            LogicOp::Or => "or",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cond {
+    pub kind: CondKind,
+    pub span: Span,
+}
+
+// `and` and `or` only evaluate `rhs` when `lhs` does not decide the result
+#[derive(Debug, Clone, PartialEq)]
+pub enum CondKind {
+    Relation(Relation),
+    Not(Box<Cond>),
+    Logical {
+        op: LogicOp,
+        lhs: Box<Cond>,
+        rhs: Box<Cond>,
+    },
+}
+
+impl From<Relation> for Cond {
+    fn from(relation: Relation) -> Cond {
+        Cond {
+            span: relation.span,
+            kind: CondKind::Relation(relation),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Assign {
+        var: usize,
+        expr: Expr,
+    },
+    Call(Call),
+    // `elsifs` are tried in order once `cond` fails
+    If {
+        cond: Cond,
+        then_body: Vec<Stmt>,
+        elsifs: Vec<(Cond, Vec<Stmt>)>,
+        else_body: Option<Vec<Stmt>>,
+    },
+    While {
+        cond: Cond,
+        body: Vec<Stmt>,
+    },
+    // Counts `var` from `from` towards `to`, up while it is at most `to` and
+    // down while it is at least `to` when `step` is negative. `to` and `step`
+    // are evaluated once, before the first check
+    For {
+        var: usize,
+        from: Expr,
+        to: Expr,
+        step: Option<Expr>,
+        body: Vec<Stmt>,
+    },
+    // Runs `body` until `cond` holds, at least once
+    Repeat {
+        body: Vec<Stmt>,
+        cond: Cond,
+    },
+    Break,
+    Continue,
+    Return(Option<Expr>),
+}
+
+// The value of an expression made only of numbers, with the 32-bit wrapping
+// arithmetic programs run with
+pub fn const_value(expr: &Expr) -> Option<i32> {
+    match &expr.kind {
+        ExprKind::Num(num) => Some(*num as i32),
+        ExprKind::Binary { op, lhs, rhs } => {
+            let (x, y) = (const_value(lhs)?, const_value(rhs)?);
+            match op {
+                BinOp::Add => Some(x.wrapping_add(y)),
+                BinOp::Sub => Some(x.wrapping_sub(y)),
+                BinOp::Mul => Some(x.wrapping_mul(y)),
+                BinOp::Div => (y != 0).then(|| x.wrapping_div(y)),
+            }
+        }
+        ExprKind::Var(_) | ExprKind::Call(_) => None,
+    }
+}
+
+// The condition and the next value of `var` that a `for` loop checks and
+// assigns on every iteration. `to` and `step` must keep their value in the
+// loop, a step that is not constant picks the direction every time the
+// condition runs
+pub fn for_parts(var: usize, to: Expr, step: Expr, span: Span) -> (Cond, Expr) {
+    let var_expr = Expr {
+        kind: ExprKind::Var(var),
+        span,
+    };
+    let zero = Expr {
+        kind: ExprKind::Num(0),
+        span,
+    };
+    let relation = |op, lhs: &Expr, rhs: &Expr| -> Cond {
+        Relation {
+            op,
+            lhs: lhs.clone(),
+            rhs: rhs.clone(),
+            span,
+        }
+        .into()
+    };
+    let logical = |op, lhs, rhs| Cond {
+        span,
+        kind: CondKind::Logical {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+    };
+
+    let up = relation(RelOp::LessThanOrEqual, &var_expr, &to);
+    let down = relation(RelOp::GreaterThanOrEqual, &var_expr, &to);
+    let cond = match const_value(&step) {
+        Some(val) if val >= 0 => up,
+        Some(_) => down,
+        None => logical(
+            LogicOp::Or,
+            logical(
+                LogicOp::And,
+                relation(RelOp::GreaterThanOrEqual, &step, &zero),
+                up,
+            ),
+            logical(LogicOp::And, relation(RelOp::LessThan, &step, &zero), down),
+        ),
+    };
+    let next = Expr {
+        kind: ExprKind::Binary {
+            op: BinOp::Add,
+            lhs: Box::new(var_expr),
+            rhs: Box::new(step),
+        },
+        span,
+    };
+
+    (cond, next)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuncDecl {
+    pub name: usize,
+    pub is_void: bool,
+    pub params: Vec<VarDecl>,
+    pub vars: Vec<VarDecl>,
+    pub body: Vec<Stmt>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Computation {
+    pub vars: Vec<VarDecl>,
+    pub funcs: Vec<FuncDecl>,
+    pub body: Vec<Stmt>,
+}
@@ -0,0 +1,404 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use crate::{
+    ast_data::{
+        BinOp, Call, Computation, Cond, CondKind, Expr, ExprKind, FuncDecl, LogicOp, Relation,
+        Stmt, StmtKind,
+    },
+    tokenizer_data::{
+        RelOp, PREDEFINED_INPUTNUM_ID, PREDEFINED_OUTPUTNEWLINE_ID, PREDEFINED_OUTPUTNUM_ID,
+    },
+};
+
+// None if the value came from an uninitialized variable or a void call
+type Value = Option<i32>;
+
+#[derive(Debug, PartialEq)]
+pub enum AstInterpreterError {
+    DivideByZero,
+    UninitializedRead { var: Option<usize> },
+    UndeclaredVariable(usize),
+    UndefinedFunction(usize),
+    MissingArgument { func: usize, argnum: usize },
+    InvalidInput(String),
+    StepLimitExceeded,
+    Io(String),
+}
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+    Return(Value),
+}
+
+// Tracks where a value came from so errors can name the variable
+#[derive(Clone, Copy)]
+struct Operand {
+    val: Value,
+    var: Option<usize>,
+}
+
+pub struct AstInterpreter<'a> {
+    ast: &'a Computation,
+    funcs: BTreeMap<usize, &'a FuncDecl>,
+    scopes: Vec<BTreeMap<usize, Value>>,
+    step_limit: Option<usize>,
+    steps: usize,
+}
+
+impl<'a> AstInterpreter<'a> {
+    pub fn new(ast: &'a Computation) -> AstInterpreter<'a> {
+        AstInterpreter {
+            ast,
+            funcs: ast.funcs.iter().map(|func| (func.name, func)).collect(),
+            scopes: Vec::new(),
+            step_limit: None,
+            steps: 0,
+        }
+    }
+
+    pub fn step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        mut input: R,
+        mut output: W,
+    ) -> Result<(), AstInterpreterError> {
+        self.scopes = vec![self.ast.vars.iter().map(|decl| (decl.var, None)).collect()];
+
+        self.stat_sequence(&self.ast.body, &mut input, &mut output)?;
+
+        output.flush().map_err(Self::io_error)
+    }
+
+    // Helpers
+    fn io_error(err: std::io::Error) -> AstInterpreterError {
+        AstInterpreterError::Io(err.to_string())
+    }
+
+    fn step(&mut self) -> Result<(), AstInterpreterError> {
+        if self.step_limit.is_some_and(|limit| self.steps >= limit) {
+            return Err(AstInterpreterError::StepLimitExceeded);
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    // The innermost scope declaring `var`, falling back to main's globals
+    fn scope(&mut self, var: usize) -> &mut BTreeMap<usize, Value> {
+        let globals = self
+            .scopes
+            .first()
+            .is_some_and(|scope| scope.contains_key(&var));
+        let local = self
+            .scopes
+            .last()
+            .is_some_and(|scope| scope.contains_key(&var));
+
+        if globals && !local {
+            self.scopes.first_mut()
+        } else {
+            self.scopes.last_mut()
+        }
+        .expect("Scope should exist")
+    }
+
+    fn defined(operand: Operand) -> Result<i32, AstInterpreterError> {
+        operand
+            .val
+            .ok_or(AstInterpreterError::UninitializedRead { var: operand.var })
+    }
+
+    fn read_num<R: BufRead>(input: &mut R) -> Result<i32, AstInterpreterError> {
+        let mut num = String::new();
+        let mut byte = [0_u8];
+        loop {
+            match input.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) if byte[0].is_ascii_whitespace() => {
+                    if !num.is_empty() {
+                        break;
+                    }
+                }
+                Ok(_) => num.push(byte[0] as char),
+                Err(err) => return Err(Self::io_error(err)),
+            }
+        }
+
+        num.parse()
+            .map_err(|_| AstInterpreterError::InvalidInput(num))
+    }
+
+    // Evaluation
+    fn expression<R: BufRead, W: Write>(
+        &mut self,
+        expr: &Expr,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Operand, AstInterpreterError> {
+        self.step()?;
+
+        Ok(match &expr.kind {
+            ExprKind::Num(num) => Operand {
+                val: Some(*num as i32),
+                var: None,
+            },
+            ExprKind::Var(var) => Operand {
+                val: *self
+                    .scope(*var)
+                    .get(var)
+                    .ok_or(AstInterpreterError::UndeclaredVariable(*var))?,
+                var: Some(*var),
+            },
+            ExprKind::Binary { op, lhs, rhs } => {
+                let lhs = self.expression(lhs, input, output)?;
+                let rhs = self.expression(rhs, input, output)?;
+                let (x, y) = (Self::defined(lhs)?, Self::defined(rhs)?);
+
+                let val = match op {
+                    BinOp::Add => x.wrapping_add(y),
+                    BinOp::Sub => x.wrapping_sub(y),
+                    BinOp::Mul => x.wrapping_mul(y),
+                    BinOp::Div if y == 0 => return Err(AstInterpreterError::DivideByZero),
+                    BinOp::Div => x.wrapping_div(y),
+                };
+
+                Operand {
+                    val: Some(val),
+                    var: None,
+                }
+            }
+            ExprKind::Call(call) => Operand {
+                val: self.func_call(call, input, output)?,
+                var: None,
+            },
+        })
+    }
+
+    fn relation<R: BufRead, W: Write>(
+        &mut self,
+        relation: &Relation,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<bool, AstInterpreterError> {
+        let lhs = self.expression(&relation.lhs, input, output)?;
+        let rhs = self.expression(&relation.rhs, input, output)?;
+        let (x, y) = (Self::defined(lhs)?, Self::defined(rhs)?);
+
+        Ok(match relation.op {
+            RelOp::Equal => x == y,
+            RelOp::NotEqual => x != y,
+            RelOp::LessThan => x < y,
+            RelOp::LessThanOrEqual => x <= y,
+            RelOp::GreaterThan => x > y,
+            RelOp::GreaterThanOrEqual => x >= y,
+        })
+    }
+
+    // `and` and `or` skip `rhs` once `lhs` decides the result
+    fn condition<R: BufRead, W: Write>(
+        &mut self,
+        cond: &Cond,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<bool, AstInterpreterError> {
+        Ok(match &cond.kind {
+            CondKind::Relation(relation) => self.relation(relation, input, output)?,
+            CondKind::Not(cond) => !self.condition(cond, input, output)?,
+            CondKind::Logical { op, lhs, rhs } => {
+                let lhs = self.condition(lhs, input, output)?;
+                match op {
+                    LogicOp::And if !lhs => false,
+                    LogicOp::Or if lhs => true,
+                    _ => self.condition(rhs, input, output)?,
+                }
+            }
+        })
+    }
+
+    fn func_call<R: BufRead, W: Write>(
+        &mut self,
+        call: &Call,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Value, AstInterpreterError> {
+        let mut args = Vec::new();
+        for arg in call.args.iter() {
+            args.push(self.expression(arg, input, output)?);
+        }
+
+        match call.func {
+            PREDEFINED_INPUTNUM_ID => Ok(Some(Self::read_num(input)?)),
+            PREDEFINED_OUTPUTNUM_ID => {
+                let val =
+                    Self::defined(*args.first().ok_or(AstInterpreterError::MissingArgument {
+                        func: call.func,
+                        argnum: 0,
+                    })?)?;
+                write!(output, "{} ", val).map_err(Self::io_error)?;
+                Ok(None)
+            }
+            PREDEFINED_OUTPUTNEWLINE_ID => {
+                writeln!(output).map_err(Self::io_error)?;
+                Ok(None)
+            }
+            id => {
+                let func = *self
+                    .funcs
+                    .get(&id)
+                    .ok_or(AstInterpreterError::UndefinedFunction(id))?;
+
+                let mut scope = BTreeMap::new();
+                for (argnum, param) in func.params.iter().enumerate() {
+                    let arg = args
+                        .get(argnum)
+                        .ok_or(AstInterpreterError::MissingArgument { func: id, argnum })?;
+                    scope.insert(param.var, arg.val);
+                }
+                for decl in func.vars.iter() {
+                    scope.insert(decl.var, None);
+                }
+
+                self.scopes.push(scope);
+                let flow = self.stat_sequence(&func.body, input, output);
+                self.scopes.pop();
+
+                Ok(match flow? {
+                    Flow::Return(val) => val,
+                    // Sema keeps `break` and `continue` inside loops
+                    Flow::Normal | Flow::Break | Flow::Continue => None,
+                })
+            }
+        }
+    }
+
+    fn statement<R: BufRead, W: Write>(
+        &mut self,
+        stmt: &Stmt,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Flow, AstInterpreterError> {
+        self.step()?;
+
+        match &stmt.kind {
+            StmtKind::Assign { var, expr } => {
+                let val = self.expression(expr, input, output)?.val;
+                self.scope(*var).insert(*var, val);
+            }
+            StmtKind::Call(call) => {
+                self.func_call(call, input, output)?;
+            }
+            StmtKind::If {
+                cond,
+                then_body,
+                elsifs,
+                else_body,
+            } => {
+                if self.condition(cond, input, output)? {
+                    return self.stat_sequence(then_body, input, output);
+                }
+                for (cond, body) in elsifs {
+                    if self.condition(cond, input, output)? {
+                        return self.stat_sequence(body, input, output);
+                    }
+                }
+                if let Some(else_body) = else_body {
+                    return self.stat_sequence(else_body, input, output);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                while self.condition(cond, input, output)? {
+                    match self.stat_sequence(body, input, output)? {
+                        Flow::Return(val) => return Ok(Flow::Return(val)),
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => {}
+                    }
+                }
+            }
+            StmtKind::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                let val = self.expression(from, input, output)?.val;
+                self.scope(*var).insert(*var, val);
+                let to = self.expression(to, input, output)?;
+                let step = match step {
+                    Some(step) => self.expression(step, input, output)?,
+                    None => Operand {
+                        val: Some(1),
+                        var: None,
+                    },
+                };
+
+                let var_expr = Expr {
+                    kind: ExprKind::Var(*var),
+                    span: stmt.span,
+                };
+                loop {
+                    let val = Self::defined(self.expression(&var_expr, input, output)?)?;
+                    let (to, step) = (Self::defined(to)?, Self::defined(step)?);
+                    // A negative step counts down
+                    if (step >= 0 && val > to) || (step < 0 && val < to) {
+                        break;
+                    }
+
+                    match self.stat_sequence(body, input, output)? {
+                        Flow::Return(val) => return Ok(Flow::Return(val)),
+                        Flow::Break => break,
+                        Flow::Normal | Flow::Continue => {}
+                    }
+
+                    let val = Self::defined(self.expression(&var_expr, input, output)?)?;
+                    self.scope(*var).insert(*var, Some(val.wrapping_add(step)));
+                }
+            }
+            StmtKind::Repeat { body, cond } => loop {
+                match self.stat_sequence(body, input, output)? {
+                    Flow::Return(val) => return Ok(Flow::Return(val)),
+                    Flow::Break => break,
+                    Flow::Normal | Flow::Continue => {}
+                }
+                if self.condition(cond, input, output)? {
+                    break;
+                }
+            },
+            StmtKind::Break => return Ok(Flow::Break),
+            StmtKind::Continue => return Ok(Flow::Continue),
+            StmtKind::Return(expr) => {
+                let val = match expr {
+                    Some(expr) => self.expression(expr, input, output)?.val,
+                    None => None,
+                };
+                return Ok(Flow::Return(val));
+            }
+        }
+
+        Ok(Flow::Normal)
+    }
+
+    fn stat_sequence<R: BufRead, W: Write>(
+        &mut self,
+        stmts: &[Stmt],
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<Flow, AstInterpreterError> {
+        for stmt in stmts {
+            match self.statement(stmt, input, output)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+        }
+
+        Ok(Flow::Normal)
+    }
+}
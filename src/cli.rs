@@ -0,0 +1,468 @@
+use project1::passes::{DumpFormat, Passes, PASS_NAMES};
+use project1::{GraphOptions, GraphView};
+
+pub const USAGE: &str = "\
+Usage: project1 <file> [options]
+       project1 run <program.dlx>
+       project1 fuzz [--seed <n>] [--count <n>] [-O0|-O1|-O2]
+
+Compiles <file> (use - for stdin). Files ending in .ssa are read as textual IR
+and go straight to the optimization passes. With `run`, executes an encoded DLX program,
+reading program input from stdin and printing statistics to stderr. With `fuzz`, checks
+random programs optimized at the given level (default: -O2) against their unoptimized
+output and prints a shrunk reproducer for every difference.
+
+Options:
+  --emit <kinds>        Comma separated list of tokens, ast, dot, ssa, asm, dlx (default: ssa)
+  -o <path>             Write output to <path> instead of stdout. With several kinds,
+                        <path> is used as a stem and each kind adds its own extension
+  --dot-view <view>     Graph drawn by the dot emit: cfg (default) or dom for the dominator tree
+  --hide-consts         Leave BB0 and its constants out of the dot emit
+  --json                Write tokens, ast and ssa as versioned JSON, and report errors as
+                        JSON diagnostics on stderr
+  --deny-warnings       Treat warnings, such as reads of uninitialized variables, as errors
+  -O0, -O1, -O2         Optimization level (default: -O1)
+  --cse, --no-cse       Toggle common subexpression elimination
+  --phi-removal, --no-phi-removal
+                        Toggle removal of phis left redundant after CSE
+  --dce, --no-dce       Toggle dead code elimination
+  --passes <names>      Comma separated pass pipeline (cse, phi-removal, dce), overrides
+                        the options above
+  --fixpoint            Repeat the pipeline until it stops changing the IR
+  --time-passes         Print per-pass timing and instruction counts to stderr
+  --dump-after <pass>[:dot|:ssa]
+                        Print the IR to stderr after every run of <pass> (default: ssa)
+  --snapshots <stem>    Write the IR after lowering and after every pass run to
+                        <stem>.NN-<pass>.dot and <stem>.NN-<pass>.ssa
+  --snapshot-diff       Mark instructions added, removed or renamed since the previous
+                        snapshot
+  -h, --help            Print this message
+";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Dot,
+    Ssa,
+    Asm,
+    Dlx,
+}
+
+impl Emit {
+    fn from_name(name: &str) -> Option<Emit> {
+        match name {
+            "tokens" => Some(Emit::Tokens),
+            "ast" => Some(Emit::Ast),
+            "dot" => Some(Emit::Dot),
+            "ssa" => Some(Emit::Ssa),
+            "asm" => Some(Emit::Asm),
+            "dlx" => Some(Emit::Dlx),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Emit::Tokens => "tokens",
+            Emit::Ast => "ast",
+            Emit::Dot => "dot",
+            Emit::Ssa => "ssa",
+            Emit::Asm => "asm",
+            Emit::Dlx => "dlx",
+        }
+    }
+
+    // Whether the output needs the SSA form
+    pub fn needs_ir(&self) -> bool {
+        !matches!(self, Emit::Tokens | Emit::Ast)
+    }
+
+    // Whether `--json` changes the output
+    pub fn has_json(&self) -> bool {
+        matches!(self, Emit::Tokens | Emit::Ast | Emit::Ssa)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    // None reads stdin
+    pub input: Option<String>,
+    pub emit: Vec<Emit>,
+    pub output: Option<String>,
+    pub json: bool,
+    pub deny_warnings: bool,
+    pub graph: GraphOptions,
+    pub passes: Passes,
+    // Explicit pass order, replaces `passes`
+    pub pipeline: Option<Vec<String>>,
+    pub fixpoint: bool,
+    pub time_passes: bool,
+    pub dump_after: Vec<(String, DumpFormat)>,
+    pub snapshots: Option<String>,
+    pub snapshot_diff: bool,
+}
+
+impl Options {
+    // `.ssa` inputs skip the front end
+    pub fn is_ir_input(&self) -> bool {
+        self.input
+            .as_ref()
+            .is_some_and(|input| input.ends_with(".ssa"))
+    }
+
+    pub fn pipeline(&self) -> Vec<String> {
+        match &self.pipeline {
+            Some(pipeline) => pipeline.clone(),
+            None => self
+                .passes
+                .pipeline()
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+        }
+    }
+
+    // None writes to stdout
+    pub fn output_path(&self, emit: Emit) -> Option<String> {
+        let output = self.output.as_ref()?;
+        if self.emit.len() == 1 {
+            Some(output.to_string())
+        } else if self.json && emit.has_json() {
+            Some(format!("{}.{}.json", output, emit.extension()))
+        } else {
+            Some(format!("{}.{}", output, emit.extension()))
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FuzzOptions {
+    // Programs are generated from seed, seed + 1, ...
+    pub seed: u64,
+    pub count: usize,
+    pub passes: Passes,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Compile(Options),
+    Run(String),
+    Fuzz(FuzzOptions),
+    Help,
+}
+
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    if let [cmd, path] = args {
+        if cmd == "run" {
+            return Ok(Command::Run(path.to_string()));
+        }
+    }
+    if let Some((cmd, args)) = args.split_first() {
+        if cmd == "fuzz" {
+            return parse_fuzz_args(args);
+        }
+    }
+
+    let mut input = None;
+    let mut emit = Vec::new();
+    let mut output = None;
+    let mut json = false;
+    let mut deny_warnings = false;
+    let mut graph = GraphOptions::default();
+    let mut level = 1;
+    let mut pipeline = None;
+    let mut fixpoint = false;
+    let mut time_passes = false;
+    let mut dump_after = Vec::new();
+    let mut snapshots = None;
+    let mut snapshot_diff = false;
+    // Applied on top of the optimization level regardless of order
+    let mut toggles = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--emit" => {
+                let kinds = args.next().ok_or("--emit expects a value")?;
+                for kind in kinds.split(',') {
+                    emit.push(
+                        Emit::from_name(kind).ok_or(format!("Unknown emit kind `{}`", kind))?,
+                    );
+                }
+            }
+            "-o" => output = Some(args.next().ok_or("-o expects a path")?.to_string()),
+            "--json" => json = true,
+            "--deny-warnings" => deny_warnings = true,
+            "--dot-view" => {
+                graph.view = match args.next().ok_or("--dot-view expects a view")?.as_str() {
+                    "cfg" => GraphView::Cfg,
+                    "dom" => GraphView::Dominators,
+                    view => return Err(format!("Unknown dot view `{}`", view)),
+                }
+            }
+            "--hide-consts" => graph.consts = false,
+            "-O0" => level = 0,
+            "-O1" => level = 1,
+            "-O2" => level = 2,
+            flag @ ("--cse" | "--no-cse" | "--phi-removal" | "--no-phi-removal" | "--dce"
+            | "--no-dce") => toggles.push(flag),
+            "--passes" => {
+                let names = args.next().ok_or("--passes expects a value")?;
+                let names = names
+                    .split(',')
+                    .filter(|name| !name.is_empty())
+                    .map(|name| pass_name(name).map(str::to_string))
+                    .collect::<Result<Vec<_>, _>>()?;
+                pipeline = Some(names);
+            }
+            "--fixpoint" => fixpoint = true,
+            "--time-passes" => time_passes = true,
+            "--snapshots" => {
+                snapshots = Some(args.next().ok_or("--snapshots expects a stem")?.to_string())
+            }
+            "--snapshot-diff" => snapshot_diff = true,
+            "--dump-after" => {
+                let value = args.next().ok_or("--dump-after expects a pass")?;
+                let (name, format) = match value.split_once(':') {
+                    Some((name, format)) => (
+                        name,
+                        DumpFormat::from_name(format)
+                            .ok_or(format!("Unknown dump format `{}`", format))?,
+                    ),
+                    None => (value.as_str(), DumpFormat::Ssa),
+                };
+                dump_after.push((pass_name(name)?.to_string(), format));
+            }
+            "-" => input = Some(None),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option `{}`", flag)),
+            path => {
+                if input.is_some() {
+                    return Err(format!("Unexpected argument `{}`", path));
+                }
+                input = Some(Some(path.to_string()));
+            }
+        }
+    }
+
+    let mut passes = Passes::level(level);
+    for toggle in toggles {
+        match toggle {
+            "--cse" => passes.cse = true,
+            "--no-cse" => passes.cse = false,
+            "--phi-removal" => passes.phi_removal = true,
+            "--no-phi-removal" => passes.phi_removal = false,
+            "--dce" => passes.dce = true,
+            "--no-dce" => passes.dce = false,
+            _ => unreachable!(),
+        }
+    }
+
+    if emit.is_empty() {
+        emit.push(Emit::Ssa);
+    }
+
+    let options = Options {
+        input: input.ok_or("Missing input file")?,
+        emit,
+        output,
+        json,
+        deny_warnings,
+        graph,
+        passes,
+        pipeline,
+        fixpoint,
+        time_passes,
+        dump_after,
+        snapshots,
+        snapshot_diff,
+    };
+    if options.is_ir_input() && !options.emit.iter().all(Emit::needs_ir) {
+        return Err("tokens and ast need Tiny source as input".to_string());
+    }
+
+    Ok(Command::Compile(options))
+}
+
+fn parse_fuzz_args(args: &[String]) -> Result<Command, String> {
+    let mut options = FuzzOptions {
+        seed: 0,
+        count: 100,
+        passes: Passes::level(2),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--seed" => {
+                let seed = args.next().ok_or("--seed expects a value")?;
+                options.seed = seed
+                    .parse()
+                    .map_err(|_| format!("Invalid seed `{}`", seed))?;
+            }
+            "--count" => {
+                let count = args.next().ok_or("--count expects a value")?;
+                options.count = count
+                    .parse()
+                    .map_err(|_| format!("Invalid count `{}`", count))?;
+            }
+            "-O0" => options.passes = Passes::level(0),
+            "-O1" => options.passes = Passes::level(1),
+            "-O2" => options.passes = Passes::level(2),
+            arg => return Err(format!("Unknown option `{}`", arg)),
+        }
+    }
+
+    Ok(Command::Fuzz(options))
+}
+
+fn pass_name(name: &str) -> Result<&str, String> {
+    if PASS_NAMES.contains(&name) {
+        Ok(name)
+    } else {
+        Err(format!("Unknown pass `{}`", name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &str) -> Vec<String> {
+        args.split_whitespace().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn defaults() {
+        assert_eq!(
+            parse_args(&args("prog.txt")),
+            Ok(Command::Compile(Options {
+                input: Some("prog.txt".to_string()),
+                emit: vec![Emit::Ssa],
+                output: None,
+                json: false,
+                deny_warnings: false,
+                graph: GraphOptions::default(),
+                passes: Passes::default(),
+                pipeline: None,
+                fixpoint: false,
+                time_passes: false,
+                dump_after: vec![],
+                snapshots: None,
+                snapshot_diff: false,
+            }))
+        );
+        assert_eq!(
+            parse_args(&args("run a.dlx")),
+            Ok(Command::Run("a.dlx".to_string()))
+        );
+    }
+
+    #[test]
+    fn passes() {
+        let Ok(Command::Compile(options)) = parse_args(&args("--no-dce - -O2 --no-cse")) else {
+            panic!("Should parse");
+        };
+
+        assert_eq!(options.input, None);
+        assert_eq!(
+            options.passes,
+            Passes {
+                cse: false,
+                phi_removal: true,
+                dce: false,
+            }
+        );
+        assert_eq!(options.pipeline(), vec!["phi-removal"]);
+
+        let Ok(Command::Compile(options)) = parse_args(&args(
+            "a.txt --passes dce,cse --fixpoint --dump-after cse:dot",
+        )) else {
+            panic!("Should parse");
+        };
+
+        assert_eq!(options.pipeline(), vec!["dce", "cse"]);
+        assert!(options.fixpoint);
+        assert_eq!(
+            options.dump_after,
+            vec![("cse".to_string(), DumpFormat::Dot)]
+        );
+    }
+
+    #[test]
+    fn graph() {
+        let Ok(Command::Compile(options)) =
+            parse_args(&args("a.txt --emit dot --dot-view dom --hide-consts"))
+        else {
+            panic!("Should parse");
+        };
+
+        assert_eq!(
+            options.graph,
+            GraphOptions {
+                view: GraphView::Dominators,
+                consts: false,
+            }
+        );
+    }
+
+    #[test]
+    fn output_paths() {
+        let Ok(Command::Compile(options)) = parse_args(&args("a.txt --emit dot,asm -o out/a"))
+        else {
+            panic!("Should parse");
+        };
+
+        assert_eq!(
+            options.output_path(Emit::Dot),
+            Some("out/a.dot".to_string())
+        );
+        assert_eq!(
+            options.output_path(Emit::Asm),
+            Some("out/a.asm".to_string())
+        );
+
+        let Ok(Command::Compile(options)) =
+            parse_args(&args("a.txt --emit ast,asm --json -o out/a"))
+        else {
+            panic!("Should parse");
+        };
+
+        assert_eq!(
+            options.output_path(Emit::Ast),
+            Some("out/a.ast.json".to_string())
+        );
+        assert_eq!(
+            options.output_path(Emit::Asm),
+            Some("out/a.asm".to_string())
+        );
+    }
+
+    #[test]
+    fn fuzz() {
+        assert_eq!(
+            parse_args(&args("fuzz --count 10 -O1 --seed 7")),
+            Ok(Command::Fuzz(FuzzOptions {
+                seed: 7,
+                count: 10,
+                passes: Passes::level(1),
+            }))
+        );
+        assert!(parse_args(&args("fuzz --seed x")).is_err());
+        assert!(parse_args(&args("fuzz a.txt")).is_err());
+    }
+
+    #[test]
+    fn errors() {
+        assert!(parse_args(&args("a.txt --emit llvm")).is_err());
+        assert!(parse_args(&args("a.txt -O3")).is_err());
+        assert!(parse_args(&args("a.txt b.txt")).is_err());
+        assert!(parse_args(&args("--emit ast")).is_err());
+        assert!(parse_args(&args("a.txt --passes cse,licm")).is_err());
+        assert!(parse_args(&args("a.txt --dump-after cse:png")).is_err());
+        assert!(parse_args(&args("a.txt --snapshots")).is_err());
+        assert!(parse_args(&args("a.txt --dot-view loops")).is_err());
+        assert!(parse_args(&args("a.ssa --emit ssa,ast")).is_err());
+    }
+}
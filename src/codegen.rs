@@ -0,0 +1,709 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    dlx_data::{
+        DlxInst, DlxOp, REG_ARGS, REG_FP, REG_GLOBALS, REG_OFFSET, REG_RET_ADDR, REG_RET_VAL,
+        REG_SCRATCH1, REG_SCRATCH2, REG_SP, REG_ZERO, WORD_SIZE,
+    },
+    parser_data::{BlockList, IType, InstList},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Label {
+    Func(usize),
+    Block(usize),
+    // from block, to block
+    Edge(usize, usize),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum AsmItem {
+    Label(Label),
+    Inst(DlxInst),
+    Branch(DlxOp, u8, Label),
+    Call(Label),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CodegenError {
+    ConstOutOfRange(isize),
+    // Branch displacement or call target that does not fit its instruction
+    BranchOutOfRange(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DlxProgram {
+    pub insts: Vec<DlxInst>,
+    // addr -> label names
+    labels: BTreeMap<usize, Vec<String>>,
+    // addr -> branch target label
+    targets: BTreeMap<usize, String>,
+}
+
+impl DlxProgram {
+    pub fn words(&self) -> Vec<u32> {
+        self.insts.iter().map(|inst| inst.encode()).collect()
+    }
+
+    pub fn generate_binary(&self) -> Vec<u8> {
+        self.words()
+            .into_iter()
+            .flat_map(|word| word.to_be_bytes())
+            .collect()
+    }
+
+    pub fn generate_assembly(&self) -> String {
+        let mut data = String::new();
+
+        for (addr, inst) in self.insts.iter().enumerate() {
+            for label in self.labels.get(&addr).into_iter().flatten() {
+                data += format!("{}:\n", label).as_str();
+            }
+            data += match self.targets.get(&addr) {
+                Some(target) => format!("\t{:>4}: {} ; -> {}\n", addr, inst, target),
+                None => format!("\t{:>4}: {}\n", addr, inst),
+            }
+            .as_str();
+        }
+
+        data
+    }
+}
+
+pub struct CodeGen<'a> {
+    insts: &'a InstList,
+    blocks: &'a BlockList,
+    // name, start block
+    funcs: Vec<(String, usize)>,
+    items: Vec<AsmItem>,
+    // inst -> frame slot
+    slots: BTreeMap<usize, i32>,
+    // var -> slot below REG_GLOBALS
+    globals: BTreeMap<usize, i32>,
+    temp_slot: i32,
+    args: Vec<usize>,
+    is_main: bool,
+}
+
+impl<'a> CodeGen<'a> {
+    pub fn new(insts: &'a InstList, blocks: &'a BlockList, funcs: Vec<(String, usize)>) -> Self {
+        let globals = insts
+            .instructions
+            .iter()
+            .filter(|inst| inst.block().is_some())
+            .filter_map(|inst| match inst.itype() {
+                IType::Load { var } | IType::Store { var, .. } => Some(var),
+                _ => None,
+            })
+            .collect::<BTreeSet<_>>();
+
+        CodeGen {
+            insts,
+            blocks,
+            funcs,
+            items: Vec::new(),
+            slots: BTreeMap::new(),
+            globals: globals
+                .into_iter()
+                .enumerate()
+                .map(|(slot, var)| (var, slot as i32))
+                .collect(),
+            temp_slot: 0,
+            args: Vec::new(),
+            is_main: false,
+        }
+    }
+
+    pub fn generate(mut self) -> Result<DlxProgram, CodegenError> {
+        self.function(1, true)?;
+        for (_, block) in self.funcs.clone() {
+            self.function(block, false)?;
+        }
+
+        self.assemble()
+    }
+
+    fn assemble(&self) -> Result<DlxProgram, CodegenError> {
+        let mut addrs = BTreeMap::new();
+        let mut addr = 0;
+        for item in self.items.iter() {
+            match item {
+                AsmItem::Label(label) => {
+                    addrs.insert(*label, addr);
+                }
+                _ => addr += 1,
+            }
+        }
+
+        let mut insts = Vec::new();
+        let mut labels: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        let mut targets = BTreeMap::new();
+        for item in self.items.iter() {
+            let addr = insts.len();
+            match *item {
+                AsmItem::Label(label) => {
+                    labels.entry(addr).or_default().push(self.label_name(label));
+                }
+                AsmItem::Inst(inst) => insts.push(inst),
+                AsmItem::Branch(op, reg, label) => {
+                    let offset = addrs[&label] - addr as i32;
+                    if !DlxInst::fits_imm(offset as i64) {
+                        return Err(CodegenError::BranchOutOfRange(offset));
+                    }
+                    targets.insert(addr, self.label_name(label));
+                    insts.push(DlxInst::new(op, reg, 0, offset));
+                }
+                AsmItem::Call(label) => {
+                    let target = addrs[&label] * WORD_SIZE;
+                    if !DlxInst::fits_target(target as i64) {
+                        return Err(CodegenError::BranchOutOfRange(target));
+                    }
+                    targets.insert(addr, self.label_name(label));
+                    insts.push(DlxInst::new(DlxOp::Jsr, 0, 0, target));
+                }
+            }
+        }
+
+        Ok(DlxProgram {
+            insts,
+            labels,
+            targets,
+        })
+    }
+
+    fn label_name(&self, label: Label) -> String {
+        match label {
+            Label::Func(1) => "main".to_string(),
+            Label::Func(block) => self
+                .funcs
+                .iter()
+                .find(|(_, start)| *start == block)
+                .map(|(name, _)| name.clone())
+                .expect("Function should exist"),
+            Label::Block(block) => format!("bb{}", block),
+            Label::Edge(from, to) => format!("bb{}_bb{}", from, to),
+        }
+    }
+
+    // Helpers
+    fn emit(&mut self, op: DlxOp, a: u8, b: u8, c: i32) {
+        self.items.push(AsmItem::Inst(DlxInst::new(op, a, b, c)));
+    }
+
+    fn slot_offset(slot: i32) -> i32 {
+        -WORD_SIZE * (slot + 1)
+    }
+
+    fn load_imm(&mut self, reg: u8, val: isize) -> Result<(), CodegenError> {
+        let val = i32::try_from(val).map_err(|_| CodegenError::ConstOutOfRange(val))?;
+        self.load_word(reg, val);
+        Ok(())
+    }
+
+    fn load_word(&mut self, reg: u8, val: i32) {
+        if DlxInst::fits_imm(val as i64) {
+            self.emit(DlxOp::Addi, reg, REG_ZERO, val);
+        } else {
+            let low = val as i16 as i32;
+            let high = (val - low) >> 16;
+            self.emit(DlxOp::Addi, reg, REG_ZERO, high);
+            self.emit(DlxOp::Lshi, reg, reg, 16);
+            self.emit(DlxOp::Addi, reg, reg, low);
+        }
+    }
+
+    // `op` is Ldw or Stw, large frames switch to the indexed form
+    fn memory(&mut self, op: DlxOp, reg: u8, base: u8, offset: i32) {
+        if DlxInst::fits_imm(offset as i64) {
+            self.emit(op, reg, base, offset);
+        } else {
+            let indexed = match op {
+                DlxOp::Ldw => DlxOp::Ldx,
+                _ => DlxOp::Stx,
+            };
+            self.load_word(REG_OFFSET, offset);
+            self.emit(indexed, reg, base, REG_OFFSET as i32);
+        }
+    }
+
+    fn add_imm(&mut self, reg: u8, val: i32) {
+        if DlxInst::fits_imm(val as i64) {
+            self.emit(DlxOp::Addi, reg, reg, val);
+        } else {
+            self.load_word(REG_OFFSET, val);
+            self.emit(DlxOp::Add, reg, reg, REG_OFFSET as i32);
+        }
+    }
+
+    fn get_const(&self, inst: usize) -> Option<isize> {
+        match self.insts.get(inst).itype() {
+            IType::Const(val) => Some(val),
+            _ => None,
+        }
+    }
+
+    fn load(&mut self, reg: u8, inst: usize) -> Result<(), CodegenError> {
+        match self.get_const(inst) {
+            Some(val) => self.load_imm(reg, val),
+            None => {
+                let slot = *self.slots.get(&inst).expect("Value should have a slot");
+                self.memory(DlxOp::Ldw, reg, REG_FP, Self::slot_offset(slot));
+                Ok(())
+            }
+        }
+    }
+
+    fn store(&mut self, reg: u8, inst: usize) {
+        let slot = *self.slots.get(&inst).expect("Value should have a slot");
+        self.memory(DlxOp::Stw, reg, REG_FP, Self::slot_offset(slot));
+    }
+
+    fn has_value(itype: IType) -> bool {
+        matches!(
+            itype,
+            IType::Add { .. }
+                | IType::Sub { .. }
+                | IType::Mul { .. }
+                | IType::Div { .. }
+                | IType::Cmp { .. }
+                | IType::Phi { .. }
+                | IType::Read
+                | IType::Jsr { .. }
+                | IType::GetPar { .. }
+                | IType::Load { .. }
+        )
+    }
+
+    fn function_blocks(&self, start: usize) -> Vec<usize> {
+        let mut visited = BTreeSet::new();
+        let mut stack = vec![start];
+        while let Some(block) = stack.pop() {
+            if visited.insert(block) {
+                stack.extend(self.blocks.get(block).get_succs(self.insts));
+            }
+        }
+
+        // Blocks are created in source order, so this keeps fall-throughs adjacent
+        visited.into_iter().collect()
+    }
+
+    // Codegen
+    fn function(&mut self, start: usize, is_main: bool) -> Result<(), CodegenError> {
+        let blocks = self.function_blocks(start);
+
+        self.is_main = is_main;
+        self.slots.clear();
+        let mut max_phis = 0;
+        for &block in blocks.iter() {
+            for &inst in self.blocks.get(block).iter() {
+                if Self::has_value(self.insts.get(inst).itype()) {
+                    self.slots.insert(inst, self.slots.len() as i32);
+                }
+            }
+            max_phis = max_phis.max(self.blocks.get(block).get_phis(self.insts).len());
+        }
+        self.temp_slot = self.slots.len() as i32;
+        let frame_size = (self.slots.len() + max_phis) as i32 * WORD_SIZE;
+
+        // Prologue
+        self.items.push(AsmItem::Label(Label::Func(start)));
+        if !is_main {
+            self.emit(DlxOp::Psh, REG_RET_ADDR, REG_SP, -WORD_SIZE);
+            self.emit(DlxOp::Psh, REG_FP, REG_SP, -WORD_SIZE);
+        } else if !self.globals.is_empty() {
+            // Globals live at the top of memory, above main's frame
+            let size = self.globals.len() as i32 * WORD_SIZE;
+            self.add_imm(REG_SP, -size);
+        }
+        self.emit(DlxOp::Add, REG_FP, REG_ZERO, REG_SP as i32);
+        if frame_size > 0 {
+            self.add_imm(REG_SP, -frame_size);
+        }
+        // Register arguments are saved before any call can clobber them
+        for &block in blocks.iter() {
+            for &inst in self.blocks.get(block).iter() {
+                if let IType::GetPar { arg } = self.insts.get(inst).itype() {
+                    if let Some(&reg) = REG_ARGS.get(arg) {
+                        self.store(reg, inst);
+                    }
+                }
+            }
+        }
+
+        let mut edges = Vec::new();
+        for (idx, &block) in blocks.iter().enumerate() {
+            let next = blocks.get(idx + 1).copied();
+            self.block(block, next, &mut edges)?;
+        }
+
+        // Critical edges get their own copy block
+        for (from, to) in edges {
+            self.items.push(AsmItem::Label(Label::Edge(from, to)));
+            self.phi_copies(from, to)?;
+            self.items
+                .push(AsmItem::Branch(DlxOp::Beq, REG_ZERO, Label::Block(to)));
+        }
+
+        Ok(())
+    }
+
+    fn block(
+        &mut self,
+        block: usize,
+        next: Option<usize>,
+        edges: &mut Vec<(usize, usize)>,
+    ) -> Result<(), CodegenError> {
+        self.items.push(AsmItem::Label(Label::Block(block)));
+
+        let terminator = self.blocks.get(block).get_terminator(self.insts);
+        for &inst in self.blocks.get(block).insts.clone().iter() {
+            if Some(inst) == terminator && self.is_branch(inst) {
+                continue;
+            }
+            self.inst(inst)?;
+        }
+
+        let succs = self.blocks.get(block).get_succs(self.insts);
+        match terminator.map(|inst| self.insts.get(inst).itype()) {
+            Some(
+                IType::Beq { inst, .. }
+                | IType::Bne { inst, .. }
+                | IType::Ble { inst, .. }
+                | IType::Blt { inst, .. }
+                | IType::Bge { inst, .. }
+                | IType::Bgt { inst, .. },
+            ) => {
+                let op = match self.insts.get(terminator.unwrap()).itype() {
+                    IType::Beq { .. } => DlxOp::Beq,
+                    IType::Bne { .. } => DlxOp::Bne,
+                    IType::Ble { .. } => DlxOp::Ble,
+                    IType::Blt { .. } => DlxOp::Blt,
+                    IType::Bge { .. } => DlxOp::Bge,
+                    _ => DlxOp::Bgt,
+                };
+                let (fall_through, branch) = (succs[0], succs[1]);
+
+                self.load(REG_SCRATCH1, inst)?;
+                let target = if self.blocks.get(branch).get_phis(self.insts).is_empty() {
+                    Label::Block(branch)
+                } else {
+                    edges.push((block, branch));
+                    Label::Edge(block, branch)
+                };
+                self.items.push(AsmItem::Branch(op, REG_SCRATCH1, target));
+
+                self.jump(block, fall_through, next)?;
+            }
+            _ => {
+                if let Some(&succ) = succs.first() {
+                    self.jump(block, succ, next)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_branch(&self, inst: usize) -> bool {
+        matches!(
+            self.insts.get(inst).itype(),
+            IType::Bra { .. }
+                | IType::Beq { .. }
+                | IType::Bne { .. }
+                | IType::Ble { .. }
+                | IType::Blt { .. }
+                | IType::Bge { .. }
+                | IType::Bgt { .. }
+        )
+    }
+
+    fn jump(&mut self, from: usize, to: usize, next: Option<usize>) -> Result<(), CodegenError> {
+        self.phi_copies(from, to)?;
+        if next != Some(to) {
+            self.items
+                .push(AsmItem::Branch(DlxOp::Beq, REG_ZERO, Label::Block(to)));
+        }
+
+        Ok(())
+    }
+
+    fn phi_copies(&mut self, from: usize, to: usize) -> Result<(), CodegenError> {
+        let phis = self.blocks.get(to).get_phis(self.insts);
+        if phis.is_empty() {
+            return Ok(());
+        }
+
+        let pred_idx = self
+            .blocks
+            .get(to)
+            .get_preds()
+            .iter()
+            .position(|&pred| pred == from)
+            .expect("Predecessor should exist");
+
+        let copies = phis
+            .into_iter()
+            .map(|phi| (phi, self.insts.get(phi).get_phi_operand(pred_idx)))
+            .filter(|(phi, src)| phi != src)
+            .collect::<Vec<_>>();
+
+        // Phis read their operands in parallel, so go through temporaries if
+        // one copy would clobber the source of another
+        if copies
+            .iter()
+            .any(|(_, src)| copies.iter().any(|(phi, _)| phi == src))
+        {
+            for (idx, &(_, src)) in copies.iter().enumerate() {
+                self.load(REG_SCRATCH1, src)?;
+                let offset = Self::slot_offset(self.temp_slot + idx as i32);
+                self.memory(DlxOp::Stw, REG_SCRATCH1, REG_FP, offset);
+            }
+            for (idx, &(phi, _)) in copies.iter().enumerate() {
+                let offset = Self::slot_offset(self.temp_slot + idx as i32);
+                self.memory(DlxOp::Ldw, REG_SCRATCH1, REG_FP, offset);
+                self.store(REG_SCRATCH1, phi);
+            }
+        } else {
+            for (phi, src) in copies {
+                self.load(REG_SCRATCH1, src)?;
+                self.store(REG_SCRATCH1, phi);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn arithmetic(
+        &mut self,
+        inst: usize,
+        op: DlxOp,
+        op_imm: DlxOp,
+        inst1: usize,
+        inst2: usize,
+    ) -> Result<(), CodegenError> {
+        self.load(REG_SCRATCH1, inst1)?;
+        match self.get_const(inst2) {
+            Some(val) if DlxInst::fits_imm(val as i64) => {
+                self.emit(op_imm, REG_RET_VAL, REG_SCRATCH1, val as i32);
+            }
+            _ => {
+                self.load(REG_SCRATCH2, inst2)?;
+                self.emit(op, REG_RET_VAL, REG_SCRATCH1, REG_SCRATCH2 as i32);
+            }
+        }
+        self.store(REG_RET_VAL, inst);
+
+        Ok(())
+    }
+
+    fn get_par(&mut self, inst: usize, arg: usize) {
+        // Register arguments were stored by the prologue
+        let Some(stack_arg) = arg.checked_sub(REG_ARGS.len()) else {
+            return;
+        };
+        // Skip the saved frame pointer and return address
+        let offset = WORD_SIZE * (stack_arg as i32 + 2);
+        self.memory(DlxOp::Ldw, REG_RET_VAL, REG_FP, offset);
+        self.store(REG_RET_VAL, inst);
+    }
+
+    fn epilogue(&mut self) {
+        if self.is_main {
+            self.emit(DlxOp::Ret, 0, 0, REG_ZERO as i32);
+        } else {
+            self.emit(DlxOp::Add, REG_SP, REG_ZERO, REG_FP as i32);
+            self.emit(DlxOp::Pop, REG_FP, REG_SP, WORD_SIZE);
+            self.emit(DlxOp::Pop, REG_RET_ADDR, REG_SP, WORD_SIZE);
+            self.emit(DlxOp::Ret, 0, 0, REG_RET_ADDR as i32);
+        }
+    }
+
+    fn inst(&mut self, inst: usize) -> Result<(), CodegenError> {
+        match self.insts.get(inst).itype() {
+            IType::Add { inst1, inst2 } => {
+                self.arithmetic(inst, DlxOp::Add, DlxOp::Addi, inst1.0, inst2.0)?
+            }
+            IType::Sub { inst1, inst2 } => {
+                self.arithmetic(inst, DlxOp::Sub, DlxOp::Subi, inst1.0, inst2.0)?
+            }
+            IType::Mul { inst1, inst2 } => {
+                self.arithmetic(inst, DlxOp::Mul, DlxOp::Muli, inst1.0, inst2.0)?
+            }
+            IType::Div { inst1, inst2 } => {
+                self.arithmetic(inst, DlxOp::Div, DlxOp::Divi, inst1.0, inst2.0)?
+            }
+            IType::Cmp { inst1, inst2 } => {
+                self.arithmetic(inst, DlxOp::Cmp, DlxOp::Cmpi, inst1.0, inst2.0)?
+            }
+            IType::Read => {
+                self.emit(DlxOp::Rdd, REG_RET_VAL, 0, 0);
+                self.store(REG_RET_VAL, inst);
+            }
+            IType::Write { inst } => {
+                self.load(REG_SCRATCH1, inst.0)?;
+                self.emit(DlxOp::Wrd, 0, REG_SCRATCH1, 0);
+            }
+            IType::WriteNL => self.emit(DlxOp::Wrl, 0, 0, 0),
+            IType::GetPar { arg } => self.get_par(inst, arg),
+            IType::SetPar { arg, inst } => self.set_arg(arg, inst.0),
+            IType::Load { var } => {
+                let offset = Self::slot_offset(self.globals[&var]);
+                self.memory(DlxOp::Ldw, REG_RET_VAL, REG_GLOBALS, offset);
+                self.store(REG_RET_VAL, inst);
+            }
+            IType::Store { var, inst } => {
+                self.load(REG_SCRATCH1, inst.0)?;
+                let offset = Self::slot_offset(self.globals[&var]);
+                self.memory(DlxOp::Stw, REG_SCRATCH1, REG_GLOBALS, offset);
+            }
+            IType::Jsr { block } => {
+                // Stack arguments are pushed last to first so the callee
+                // finds argument n at a fixed offset from its frame pointer
+                let args = std::mem::take(&mut self.args);
+                let stack_args = args.get(REG_ARGS.len()..).unwrap_or_default();
+                for &arg in stack_args.iter().rev() {
+                    self.load(REG_SCRATCH1, arg)?;
+                    self.emit(DlxOp::Psh, REG_SCRATCH1, REG_SP, -WORD_SIZE);
+                }
+                for (&arg, &reg) in args.iter().zip(REG_ARGS.iter()) {
+                    self.load(reg, arg)?;
+                }
+                self.items.push(AsmItem::Call(Label::Func(block)));
+                if !stack_args.is_empty() {
+                    self.add_imm(REG_SP, WORD_SIZE * stack_args.len() as i32);
+                }
+                self.store(REG_RET_VAL, inst);
+            }
+            IType::Ret { inst } => {
+                if let Some(inst) = inst {
+                    self.load(REG_RET_VAL, inst.0)?;
+                }
+                self.epilogue();
+            }
+            IType::End => self.emit(DlxOp::Ret, 0, 0, REG_ZERO as i32),
+            IType::Bra { block } => {
+                self.items
+                    .push(AsmItem::Branch(DlxOp::Beq, REG_ZERO, Label::Block(block)));
+            }
+            IType::Const(_)
+            | IType::Phi { .. }
+            | IType::Beq { .. }
+            | IType::Bne { .. }
+            | IType::Ble { .. }
+            | IType::Blt { .. }
+            | IType::Bge { .. }
+            | IType::Bgt { .. }
+            | IType::Empty => {}
+        }
+
+        Ok(())
+    }
+
+    fn set_arg(&mut self, argnum: usize, inst: usize) {
+        if self.args.len() <= argnum {
+            self.args.resize(argnum + 1, inst);
+        }
+        self.args[argnum] = inst;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compile, emulator::Emulator, CompileOptions};
+
+    use super::*;
+
+    fn generate(input: &[u8]) -> DlxProgram {
+        compile(input, &CompileOptions::default())
+            .expect("Should compile")
+            .generate_dlx()
+            .expect("Should generate")
+    }
+
+    #[test]
+    fn large_const() {
+        let program = generate(
+            b"
+main
+var x;
+{
+    let x <- call InputNum();
+    call OutputNum(x + 400000000)
+}
+.
+",
+        );
+
+        assert!(program.insts.contains(&DlxInst::new(DlxOp::Lshi, 3, 3, 16)));
+    }
+
+    #[test]
+    fn large_frame() {
+        let mut input = String::from("main\nvar x;\n{\n    let x <- call InputNum();\n");
+        for i in 1..=9000 {
+            input += &format!("    let x <- x + {};\n", i);
+        }
+        input += "    call OutputNum(x)\n}\n.\n";
+        let program = generate(input.as_bytes());
+
+        assert!(program.insts.iter().any(|inst| inst.op == DlxOp::Stx));
+        let mut output = Vec::new();
+        Emulator::new(&program.words())
+            .run(&b"1"[..], &mut output)
+            .expect("Should run");
+        assert_eq!(String::from_utf8_lossy(&output), "40504501 ");
+    }
+
+    #[test]
+    fn branch_targets() {
+        let program = generate(
+            b"
+main
+var i;
+{
+    let i <- 0;
+    while i < 10 do
+        let i <- i + 1
+    od;
+    call OutputNum(i)
+}
+.
+",
+        );
+
+        // Every branch lands inside the program
+        for (addr, inst) in program.insts.iter().enumerate() {
+            if let DlxOp::Beq | DlxOp::Bne | DlxOp::Blt | DlxOp::Bge | DlxOp::Ble | DlxOp::Bgt =
+                inst.op
+            {
+                let target = addr as i32 + inst.c;
+                assert!((0..program.insts.len() as i32).contains(&target));
+            }
+        }
+        assert_eq!(
+            program.insts.last(),
+            Some(&DlxInst::new(DlxOp::Ret, 0, 0, REG_ZERO as i32))
+        );
+    }
+
+    #[test]
+    fn func_call() {
+        let program = generate(
+            b"
+main
+function add(a, b); {
+    return a + b
+};
+{
+    call OutputNum(call add(1, 2))
+}
+.
+",
+        );
+
+        let assembly = program.generate_assembly();
+        assert!(assembly.contains("add:\n"));
+        assert!(assembly.contains("; -> add"));
+        assert_eq!(program.generate_binary().len(), program.insts.len() * 4);
+    }
+}
@@ -0,0 +1,88 @@
+use std::fmt::Display;
+
+use crate::tokenizer_data::Span;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiagnosticKind {
+    Syntax,
+    Semantic,
+    // Does not stop compilation unless promoted
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.kind {
+            DiagnosticKind::Syntax => "Syntax Error",
+            DiagnosticKind::Semantic => "Semantic Error",
+            DiagnosticKind::Warning => "Warning",
+        };
+
+        match self.span {
+            Some(span) => write!(
+                f,
+                "{} at {}:{}: {}",
+                kind, span.line, span.col, self.message
+            ),
+            None => write!(f, "{}: {}", kind, self.message),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    pub fn push(&mut self, kind: DiagnosticKind, message: &str, span: Option<Span>) {
+        self.diagnostics.push(Diagnostic {
+            kind,
+            message: message.to_string(),
+            span,
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|diagnostic| diagnostic.kind != DiagnosticKind::Warning)
+    }
+
+    // Turns warnings into semantic errors
+    pub fn deny_warnings(&mut self) {
+        for diagnostic in self.diagnostics.iter_mut() {
+            if diagnostic.kind == DiagnosticKind::Warning {
+                diagnostic.kind = DiagnosticKind::Semantic;
+            }
+        }
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Diagnostic> {
+        self.diagnostics.iter()
+    }
+}
+
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for diagnostic in self.diagnostics.iter() {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,370 @@
+use std::fmt::Display;
+
+// Reserved registers
+pub const REG_ZERO: u8 = 0;
+pub const REG_RET_VAL: u8 = 1;
+pub const REG_SCRATCH1: u8 = 2;
+pub const REG_SCRATCH2: u8 = 3;
+// Offsets too large for an immediate
+pub const REG_OFFSET: u8 = 27;
+pub const REG_FP: u8 = 28;
+pub const REG_SP: u8 = 29;
+pub const REG_GLOBALS: u8 = 30;
+pub const REG_RET_ADDR: u8 = 31;
+
+pub const WORD_SIZE: i32 = 4;
+// The first arguments of a call are passed in these registers, the rest are
+// pushed on the stack
+pub const REG_ARGS: [u8; 4] = [4, 5, 6, 7];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DlxOp {
+    // Arithmetic
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Mod,
+    Cmp,
+    Or,
+    And,
+    Bic,
+    Xor,
+    Lsh,
+    Ash,
+    Chk,
+
+    // Arithmetic (immediate)
+    Addi,
+    Subi,
+    Muli,
+    Divi,
+    Modi,
+    Cmpi,
+    Ori,
+    Andi,
+    Bici,
+    Xori,
+    Lshi,
+    Ashi,
+    Chki,
+
+    // Memory
+    Ldw,
+    Ldx,
+    Pop,
+    Stw,
+    Stx,
+    Psh,
+
+    // Control
+    Beq,
+    Bne,
+    Blt,
+    Bge,
+    Ble,
+    Bgt,
+    Bsr,
+    Jsr,
+    Ret,
+
+    // I/O
+    Rdd,
+    Wrd,
+    Wrh,
+    Wrl,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DlxFormat {
+    // op a b c(imm16)
+    F1,
+    // op a b c(reg)
+    F2,
+    // op c(abs26)
+    F3,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct InvalidOpcodeError(pub u32);
+
+impl DlxOp {
+    pub fn opcode(&self) -> u32 {
+        match self {
+            DlxOp::Add => 0,
+            DlxOp::Sub => 1,
+            DlxOp::Mul => 2,
+            DlxOp::Div => 3,
+            DlxOp::Mod => 4,
+            DlxOp::Cmp => 5,
+            DlxOp::Or => 8,
+            DlxOp::And => 9,
+            DlxOp::Bic => 10,
+            DlxOp::Xor => 11,
+            DlxOp::Lsh => 12,
+            DlxOp::Ash => 13,
+            DlxOp::Chk => 14,
+            DlxOp::Addi => 16,
+            DlxOp::Subi => 17,
+            DlxOp::Muli => 18,
+            DlxOp::Divi => 19,
+            DlxOp::Modi => 20,
+            DlxOp::Cmpi => 21,
+            DlxOp::Ori => 24,
+            DlxOp::Andi => 25,
+            DlxOp::Bici => 26,
+            DlxOp::Xori => 27,
+            DlxOp::Lshi => 28,
+            DlxOp::Ashi => 29,
+            DlxOp::Chki => 30,
+            DlxOp::Ldw => 32,
+            DlxOp::Ldx => 33,
+            DlxOp::Pop => 34,
+            DlxOp::Stw => 36,
+            DlxOp::Stx => 37,
+            DlxOp::Psh => 38,
+            DlxOp::Beq => 40,
+            DlxOp::Bne => 41,
+            DlxOp::Blt => 42,
+            DlxOp::Bge => 43,
+            DlxOp::Ble => 44,
+            DlxOp::Bgt => 45,
+            DlxOp::Bsr => 46,
+            DlxOp::Jsr => 48,
+            DlxOp::Ret => 49,
+            DlxOp::Rdd => 50,
+            DlxOp::Wrd => 51,
+            DlxOp::Wrh => 52,
+            DlxOp::Wrl => 53,
+        }
+    }
+
+    pub fn from_opcode(opcode: u32) -> Result<DlxOp, InvalidOpcodeError> {
+        Ok(match opcode {
+            0 => DlxOp::Add,
+            1 => DlxOp::Sub,
+            2 => DlxOp::Mul,
+            3 => DlxOp::Div,
+            4 => DlxOp::Mod,
+            5 => DlxOp::Cmp,
+            8 => DlxOp::Or,
+            9 => DlxOp::And,
+            10 => DlxOp::Bic,
+            11 => DlxOp::Xor,
+            12 => DlxOp::Lsh,
+            13 => DlxOp::Ash,
+            14 => DlxOp::Chk,
+            16 => DlxOp::Addi,
+            17 => DlxOp::Subi,
+            18 => DlxOp::Muli,
+            19 => DlxOp::Divi,
+            20 => DlxOp::Modi,
+            21 => DlxOp::Cmpi,
+            24 => DlxOp::Ori,
+            25 => DlxOp::Andi,
+            26 => DlxOp::Bici,
+            27 => DlxOp::Xori,
+            28 => DlxOp::Lshi,
+            29 => DlxOp::Ashi,
+            30 => DlxOp::Chki,
+            32 => DlxOp::Ldw,
+            33 => DlxOp::Ldx,
+            34 => DlxOp::Pop,
+            36 => DlxOp::Stw,
+            37 => DlxOp::Stx,
+            38 => DlxOp::Psh,
+            40 => DlxOp::Beq,
+            41 => DlxOp::Bne,
+            42 => DlxOp::Blt,
+            43 => DlxOp::Bge,
+            44 => DlxOp::Ble,
+            45 => DlxOp::Bgt,
+            46 => DlxOp::Bsr,
+            48 => DlxOp::Jsr,
+            49 => DlxOp::Ret,
+            50 => DlxOp::Rdd,
+            51 => DlxOp::Wrd,
+            52 => DlxOp::Wrh,
+            53 => DlxOp::Wrl,
+            opcode => return Err(InvalidOpcodeError(opcode)),
+        })
+    }
+
+    pub fn format(&self) -> DlxFormat {
+        match self {
+            DlxOp::Add
+            | DlxOp::Sub
+            | DlxOp::Mul
+            | DlxOp::Div
+            | DlxOp::Mod
+            | DlxOp::Cmp
+            | DlxOp::Or
+            | DlxOp::And
+            | DlxOp::Bic
+            | DlxOp::Xor
+            | DlxOp::Lsh
+            | DlxOp::Ash
+            | DlxOp::Chk
+            | DlxOp::Ldx
+            | DlxOp::Stx
+            | DlxOp::Ret
+            | DlxOp::Rdd
+            | DlxOp::Wrd
+            | DlxOp::Wrh
+            | DlxOp::Wrl => DlxFormat::F2,
+            DlxOp::Jsr => DlxFormat::F3,
+            _ => DlxFormat::F1,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            DlxOp::Add => "ADD",
+            DlxOp::Sub => "SUB",
+            DlxOp::Mul => "MUL",
+            DlxOp::Div => "DIV",
+            DlxOp::Mod => "MOD",
+            DlxOp::Cmp => "CMP",
+            DlxOp::Or => "OR",
+            DlxOp::And => "AND",
+            DlxOp::Bic => "BIC",
+            DlxOp::Xor => "XOR",
+            DlxOp::Lsh => "LSH",
+            DlxOp::Ash => "ASH",
+            DlxOp::Chk => "CHK",
+            DlxOp::Addi => "ADDI",
+            DlxOp::Subi => "SUBI",
+            DlxOp::Muli => "MULI",
+            DlxOp::Divi => "DIVI",
+            DlxOp::Modi => "MODI",
+            DlxOp::Cmpi => "CMPI",
+            DlxOp::Ori => "ORI",
+            DlxOp::Andi => "ANDI",
+            DlxOp::Bici => "BICI",
+            DlxOp::Xori => "XORI",
+            DlxOp::Lshi => "LSHI",
+            DlxOp::Ashi => "ASHI",
+            DlxOp::Chki => "CHKI",
+            DlxOp::Ldw => "LDW",
+            DlxOp::Ldx => "LDX",
+            DlxOp::Pop => "POP",
+            DlxOp::Stw => "STW",
+            DlxOp::Stx => "STX",
+            DlxOp::Psh => "PSH",
+            DlxOp::Beq => "BEQ",
+            DlxOp::Bne => "BNE",
+            DlxOp::Blt => "BLT",
+            DlxOp::Bge => "BGE",
+            DlxOp::Ble => "BLE",
+            DlxOp::Bgt => "BGT",
+            DlxOp::Bsr => "BSR",
+            DlxOp::Jsr => "JSR",
+            DlxOp::Ret => "RET",
+            DlxOp::Rdd => "RDD",
+            DlxOp::Wrd => "WRD",
+            DlxOp::Wrh => "WRH",
+            DlxOp::Wrl => "WRL",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DlxInst {
+    pub op: DlxOp,
+    pub a: u8,
+    pub b: u8,
+    pub c: i32,
+}
+
+impl DlxInst {
+    pub fn new(op: DlxOp, a: u8, b: u8, c: i32) -> DlxInst {
+        DlxInst { op, a, b, c }
+    }
+
+    pub fn encode(&self) -> u32 {
+        let opcode = self.op.opcode() << 26;
+        match self.op.format() {
+            DlxFormat::F1 => {
+                opcode
+                    | (self.a as u32 & 0x1F) << 21
+                    | (self.b as u32 & 0x1F) << 16
+                    | (self.c as u32 & 0xFFFF)
+            }
+            DlxFormat::F2 => {
+                opcode
+                    | (self.a as u32 & 0x1F) << 21
+                    | (self.b as u32 & 0x1F) << 16
+                    | (self.c as u32 & 0x1F)
+            }
+            DlxFormat::F3 => opcode | (self.c as u32 & 0x03FF_FFFF),
+        }
+    }
+
+    pub fn decode(word: u32) -> Result<DlxInst, InvalidOpcodeError> {
+        let op = DlxOp::from_opcode(word >> 26)?;
+        let a = ((word >> 21) & 0x1F) as u8;
+        let b = ((word >> 16) & 0x1F) as u8;
+
+        Ok(match op.format() {
+            DlxFormat::F1 => DlxInst::new(op, a, b, (word & 0xFFFF) as u16 as i16 as i32),
+            DlxFormat::F2 => DlxInst::new(op, a, b, (word & 0x1F) as i32),
+            DlxFormat::F3 => DlxInst::new(op, 0, 0, (word & 0x03FF_FFFF) as i32),
+        })
+    }
+
+    pub fn fits_imm(val: i64) -> bool {
+        (i16::MIN as i64..=i16::MAX as i64).contains(&val)
+    }
+
+    // Absolute JSR targets are unsigned
+    pub fn fits_target(val: i64) -> bool {
+        (0..1 << 26).contains(&val)
+    }
+}
+
+impl Display for DlxInst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mnemonic = self.op.mnemonic();
+        match self.op {
+            DlxOp::Beq | DlxOp::Bne | DlxOp::Blt | DlxOp::Bge | DlxOp::Ble | DlxOp::Bgt => {
+                write!(f, "{} R{} {}", mnemonic, self.a, self.c)
+            }
+            DlxOp::Bsr | DlxOp::Jsr => write!(f, "{} {}", mnemonic, self.c),
+            DlxOp::Ret => write!(f, "{} R{}", mnemonic, self.c),
+            DlxOp::Rdd => write!(f, "{} R{}", mnemonic, self.a),
+            DlxOp::Wrd | DlxOp::Wrh => write!(f, "{} R{}", mnemonic, self.b),
+            DlxOp::Wrl => write!(f, "{}", mnemonic),
+            _ => match self.op.format() {
+                DlxFormat::F2 => write!(f, "{} R{} R{} R{}", mnemonic, self.a, self.b, self.c),
+                _ => write!(f, "{} R{} R{} {}", mnemonic, self.a, self.b, self.c),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn encode_decode() {
+        let insts = [
+            DlxInst::new(DlxOp::Addi, 1, 0, -5),
+            DlxInst::new(DlxOp::Add, 1, 2, 3),
+            DlxInst::new(DlxOp::Psh, REG_RET_ADDR, REG_SP, -WORD_SIZE),
+            DlxInst::new(DlxOp::Bge, 2, 0, -12),
+            DlxInst::new(DlxOp::Jsr, 0, 0, 400),
+            DlxInst::new(DlxOp::Ret, 0, 0, REG_RET_ADDR as i32),
+            DlxInst::new(DlxOp::Wrd, 0, 2, 0),
+        ];
+
+        for inst in insts {
+            assert_eq!(DlxInst::decode(inst.encode()), Ok(inst));
+        }
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        assert_eq!(DlxInst::decode(63 << 26), Err(InvalidOpcodeError(63)));
+    }
+}
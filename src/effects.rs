@@ -0,0 +1,453 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::ast_data::{
+    Call, Computation, Cond, CondKind, Expr, ExprKind, FuncDecl, Stmt, StmtKind,
+};
+
+// Globals a function reads and writes, including through its callees
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Effects {
+    pub reads: BTreeSet<usize>,
+    pub writes: BTreeSet<usize>,
+    // The writes made on every path through the function that returns
+    pub must_writes: BTreeSet<usize>,
+}
+
+impl Effects {
+    // Globals whose value has to be in memory when the function is called
+    pub fn touched(&self) -> BTreeSet<usize> {
+        self.reads.union(&self.writes).copied().collect()
+    }
+}
+
+// Direct effects of one function body and the functions it calls
+struct Walker<'a> {
+    globals: &'a BTreeSet<usize>,
+    // params and locals hide the globals of the same name
+    shadowed: BTreeSet<usize>,
+    effects: Effects,
+    callees: BTreeSet<usize>,
+}
+
+impl Walker<'_> {
+    fn is_global(&self, var: usize) -> bool {
+        self.globals.contains(&var) && !self.shadowed.contains(&var)
+    }
+
+    fn call(&mut self, call: &Call) {
+        self.callees.insert(call.func);
+        for arg in call.args.iter() {
+            self.expression(arg);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Num(_) => {}
+            ExprKind::Var(var) => {
+                if self.is_global(*var) {
+                    self.effects.reads.insert(*var);
+                }
+            }
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            ExprKind::Call(call) => self.call(call),
+        }
+    }
+
+    fn cond(&mut self, cond: &Cond) {
+        match &cond.kind {
+            CondKind::Relation(relation) => {
+                self.expression(&relation.lhs);
+                self.expression(&relation.rhs);
+            }
+            CondKind::Not(cond) => self.cond(cond),
+            CondKind::Logical { lhs, rhs, .. } => {
+                self.cond(lhs);
+                self.cond(rhs);
+            }
+        }
+    }
+
+    fn stat_sequence(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            match &stmt.kind {
+                StmtKind::Assign { var, expr } => {
+                    self.expression(expr);
+                    if self.is_global(*var) {
+                        self.effects.writes.insert(*var);
+                    }
+                }
+                StmtKind::Call(call) => self.call(call),
+                StmtKind::If {
+                    cond,
+                    then_body,
+                    elsifs,
+                    else_body,
+                } => {
+                    self.cond(cond);
+                    self.stat_sequence(then_body);
+                    for (cond, body) in elsifs {
+                        self.cond(cond);
+                        self.stat_sequence(body);
+                    }
+                    if let Some(else_body) = else_body {
+                        self.stat_sequence(else_body);
+                    }
+                }
+                StmtKind::While { cond, body } | StmtKind::Repeat { body, cond } => {
+                    self.cond(cond);
+                    self.stat_sequence(body);
+                }
+                StmtKind::For {
+                    var,
+                    from,
+                    to,
+                    step,
+                    body,
+                } => {
+                    self.expression(from);
+                    self.expression(to);
+                    if let Some(step) = step {
+                        self.expression(step);
+                    }
+                    // The counter is read by the loop condition
+                    if self.is_global(*var) {
+                        self.effects.reads.insert(*var);
+                        self.effects.writes.insert(*var);
+                    }
+                    self.stat_sequence(body);
+                }
+                StmtKind::Return(expr) => {
+                    if let Some(expr) = expr {
+                        self.expression(expr);
+                    }
+                }
+                StmtKind::Break | StmtKind::Continue => {}
+            }
+        }
+    }
+}
+
+// Globals assigned on every path to a point, None where no path reaches
+type Writes = Option<BTreeSet<usize>>;
+
+fn join(x: Writes, y: Writes) -> Writes {
+    match (x, y) {
+        (Some(x), Some(y)) => Some(x.intersection(&y).copied().collect()),
+        (x, None) => x,
+        (None, y) => y,
+    }
+}
+
+// Globals one function body assigns on every path that returns, given the
+// current summaries of the functions it calls
+struct MustWalker<'a> {
+    globals: &'a BTreeSet<usize>,
+    shadowed: BTreeSet<usize>,
+    // func ident -> must writes
+    summaries: &'a BTreeMap<usize, BTreeSet<usize>>,
+    // Joined states at the returns
+    returns: Writes,
+    // Joined states at the `break`s and `continue`s of the enclosing loops,
+    // innermost last
+    loops: Vec<(Writes, Writes)>,
+}
+
+impl MustWalker<'_> {
+    fn assign(&self, state: &mut Writes, var: usize) {
+        let is_global = self.globals.contains(&var) && !self.shadowed.contains(&var);
+        if let (Some(state), true) = (state.as_mut(), is_global) {
+            state.insert(var);
+        }
+    }
+
+    fn call(&mut self, state: &mut Writes, call: &Call) {
+        for arg in call.args.iter() {
+            self.expression(state, arg);
+        }
+        if let (Some(state), Some(writes)) = (state.as_mut(), self.summaries.get(&call.func)) {
+            state.extend(writes.iter().copied());
+        }
+    }
+
+    fn expression(&mut self, state: &mut Writes, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Num(_) | ExprKind::Var(_) => {}
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expression(state, lhs);
+                self.expression(state, rhs);
+            }
+            ExprKind::Call(call) => self.call(state, call),
+        }
+    }
+
+    // Short-circuiting leaves only the first relation certain to run
+    fn cond(&mut self, state: &mut Writes, cond: &Cond) {
+        match &cond.kind {
+            CondKind::Relation(relation) => {
+                self.expression(state, &relation.lhs);
+                self.expression(state, &relation.rhs);
+            }
+            CondKind::Not(cond) | CondKind::Logical { lhs: cond, .. } => self.cond(state, cond),
+        }
+    }
+
+    // The state at the end of the body, joined with its `continue`s, and at
+    // its `break`s
+    fn loop_body(&mut self, state: Writes, body: &[Stmt]) -> (Writes, Writes) {
+        self.loops.push((None, None));
+        let end = self.stat_sequence(state, body);
+        let (breaks, continues) = self.loops.pop().expect("Loop should be pushed");
+
+        (join(end, continues), breaks)
+    }
+
+    fn statement(&mut self, mut state: Writes, stmt: &Stmt) -> Writes {
+        match &stmt.kind {
+            StmtKind::Assign { var, expr } => {
+                self.expression(&mut state, expr);
+                self.assign(&mut state, *var);
+                state
+            }
+            StmtKind::Call(call) => {
+                self.call(&mut state, call);
+                state
+            }
+            StmtKind::If {
+                cond,
+                then_body,
+                elsifs,
+                else_body,
+            } => {
+                self.cond(&mut state, cond);
+                let mut then_state = self.stat_sequence(state.clone(), then_body);
+                for (cond, body) in elsifs {
+                    self.cond(&mut state, cond);
+                    then_state = join(then_state, self.stat_sequence(state.clone(), body));
+                }
+                let else_state = match else_body {
+                    Some(else_body) => self.stat_sequence(state, else_body),
+                    None => state,
+                };
+                join(then_state, else_state)
+            }
+            // The body may not run, but its returns still count
+            StmtKind::While { cond, body } => {
+                self.cond(&mut state, cond);
+                self.loop_body(state.clone(), body);
+                state
+            }
+            StmtKind::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                self.expression(&mut state, from);
+                self.assign(&mut state, *var);
+                self.expression(&mut state, to);
+                if let Some(step) = step {
+                    self.expression(&mut state, step);
+                }
+                self.loop_body(state.clone(), body);
+                state
+            }
+            StmtKind::Repeat { body, cond } => {
+                let (mut latch, breaks) = self.loop_body(state, body);
+                self.cond(&mut latch, cond);
+                join(latch, breaks)
+            }
+            StmtKind::Break => {
+                let (breaks, _) = self.loops.last_mut().expect("Break should be in a loop");
+                *breaks = join(breaks.take(), state);
+                None
+            }
+            StmtKind::Continue => {
+                let (_, continues) = self.loops.last_mut().expect("Continue should be in a loop");
+                *continues = join(continues.take(), state);
+                None
+            }
+            StmtKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(&mut state, expr);
+                }
+                self.returns = join(self.returns.take(), state);
+                None
+            }
+        }
+    }
+
+    fn stat_sequence(&mut self, mut state: Writes, stmts: &[Stmt]) -> Writes {
+        for stmt in stmts {
+            state = self.statement(state, stmt);
+        }
+        state
+    }
+}
+
+// Variables declared by a function that hide globals of the same name
+pub fn shadowed(func: &FuncDecl) -> BTreeSet<usize> {
+    func.params
+        .iter()
+        .chain(func.vars.iter())
+        .map(|decl| decl.var)
+        .collect()
+}
+
+// func ident -> globals it reads and writes
+pub fn global_effects(ast: &Computation) -> BTreeMap<usize, Effects> {
+    let globals = ast
+        .vars
+        .iter()
+        .map(|decl| decl.var)
+        .collect::<BTreeSet<_>>();
+
+    let mut effects = BTreeMap::new();
+    let mut callees = BTreeMap::new();
+    for func in ast.funcs.iter() {
+        let mut walker = Walker {
+            globals: &globals,
+            shadowed: shadowed(func),
+            effects: Effects::default(),
+            callees: BTreeSet::new(),
+        };
+        walker.stat_sequence(&func.body);
+
+        effects.insert(func.name, walker.effects);
+        callees.insert(func.name, walker.callees);
+    }
+
+    // Callers inherit the effects of their callees until nothing changes,
+    // which also covers recursion
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (func, callees) in callees.iter() {
+            let mut merged = effects[func].clone();
+            for callee in callees.iter() {
+                if let Some(callee) = effects.get(callee) {
+                    merged.reads.extend(callee.reads.iter().copied());
+                    merged.writes.extend(callee.writes.iter().copied());
+                }
+            }
+            if merged != effects[func] {
+                effects.insert(*func, merged);
+                changed = true;
+            }
+        }
+    }
+
+    // Must writes shrink from every global until nothing changes, so a
+    // recursive call assumes the summary it is part of
+    let mut summaries = ast
+        .funcs
+        .iter()
+        .map(|func| (func.name, globals.clone()))
+        .collect::<BTreeMap<_, _>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for func in ast.funcs.iter() {
+            let mut walker = MustWalker {
+                globals: &globals,
+                shadowed: shadowed(func),
+                summaries: &summaries,
+                returns: None,
+                loops: Vec::new(),
+            };
+            let end = walker.stat_sequence(Some(BTreeSet::new()), &func.body);
+            // A function that never returns writes nothing for its callers
+            let mut must = join(end, walker.returns).unwrap_or_default();
+            must.retain(|var| effects[&func.name].writes.contains(var));
+
+            if must != summaries[&func.name] {
+                summaries.insert(func.name, must);
+                changed = true;
+            }
+        }
+    }
+    for (func, must_writes) in summaries {
+        if let Some(effects) = effects.get_mut(&func) {
+            effects.must_writes = must_writes;
+        }
+    }
+
+    effects
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::Parser;
+
+    use super::*;
+
+    fn effects(input: &[u8]) -> BTreeMap<String, (Vec<String>, Vec<String>)> {
+        let mut parser = Parser::new(input);
+        let ast = parser.computation().expect("Should parse");
+        let idents = parser.idents();
+        let names = |vars: &BTreeSet<usize>| {
+            vars.iter()
+                .map(|&var| idents[var].clone())
+                .collect::<Vec<_>>()
+        };
+
+        global_effects(&ast)
+            .iter()
+            .map(|(&func, effects)| {
+                (
+                    idents[func].clone(),
+                    (names(&effects.reads), names(&effects.writes)),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn transitive() {
+        let effects = effects(
+            b"main var a, b, c;
+              function f(c); { let a <- c; return call g() };
+              function g(); { if b > 0 then let b <- b - 1; return call f(b) fi; return c };
+              void function h(); var a; { let a <- 1; call OutputNum(a) };
+              { call OutputNum(call f(1)); call h() }.",
+        );
+
+        let set = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(effects["f"], (set(&["b", "c"]), set(&["a", "b"])));
+        assert_eq!(effects["g"], (set(&["b", "c"]), set(&["a", "b"])));
+        assert_eq!(effects["h"], (vec![], vec![]));
+    }
+
+    #[test]
+    fn must_writes() {
+        let mut parser = Parser::new(
+            &b"main var a, b, c;
+              function f(n); { let a <- n; if n > 0 then let b <- n; return n fi; let c <- n; return 0 };
+              function g(n); { if n > 0 then let c <- call g(n - 1) else let c <- 0 fi; return c };
+              void function h(); { let b <- 1; call f(1) };
+              { call OutputNum(call f(1) + call g(1)); call h() }."[..],
+        );
+        let ast = parser.computation().expect("Should parse");
+        let idents = parser.idents();
+        let effects = global_effects(&ast);
+
+        let must_writes = |name: &str| {
+            let func = idents
+                .iter()
+                .position(|ident| ident == name)
+                .expect("Should exist");
+            effects[&func]
+                .must_writes
+                .iter()
+                .map(|&var| idents[var].as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(must_writes("f"), vec!["a"]);
+        assert_eq!(must_writes("g"), vec!["c"]);
+        assert_eq!(must_writes("h"), vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,469 @@
+use std::io::{BufRead, Write};
+
+use crate::dlx_data::{
+    DlxInst, DlxOp, InvalidOpcodeError, REG_FP, REG_GLOBALS, REG_RET_ADDR, REG_SP, WORD_SIZE,
+};
+
+pub const MEM_SIZE: usize = 1 << 16;
+
+#[derive(Debug, PartialEq)]
+pub enum EmulatorError {
+    InvalidOpcode { pc: usize, opcode: u32 },
+    DivideByZero { pc: usize },
+    MemoryOutOfBounds { pc: usize, addr: i32 },
+    PcOutOfBounds { pc: usize },
+    CheckFailed { pc: usize, val: i32, bound: i32 },
+    InvalidInput(String),
+    StepLimitExceeded,
+    Io(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EmulatorStats {
+    pub insts: usize,
+    pub mem_reads: usize,
+    pub mem_writes: usize,
+}
+
+impl EmulatorStats {
+    pub fn mem_accesses(&self) -> usize {
+        self.mem_reads + self.mem_writes
+    }
+}
+
+#[derive(Debug)]
+pub struct Emulator {
+    mem: Vec<i32>,
+    regs: [i32; 32],
+    pc: usize,
+    program_len: usize,
+    step_limit: Option<usize>,
+    stats: EmulatorStats,
+}
+
+impl Emulator {
+    pub fn new(program: &[u32]) -> Emulator {
+        let mut mem = vec![0; MEM_SIZE.max(program.len())];
+        for (idx, &word) in program.iter().enumerate() {
+            mem[idx] = word as i32;
+        }
+
+        let mut regs = [0; 32];
+        let top = (mem.len() * WORD_SIZE as usize) as i32;
+        regs[REG_FP as usize] = top;
+        regs[REG_SP as usize] = top;
+        regs[REG_GLOBALS as usize] = top;
+
+        Emulator {
+            mem,
+            regs,
+            pc: 0,
+            program_len: program.len(),
+            step_limit: None,
+            stats: EmulatorStats::default(),
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Emulator {
+        let words = bytes
+            .chunks(4)
+            .map(|chunk| {
+                let mut word = [0; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                u32::from_be_bytes(word)
+            })
+            .collect::<Vec<_>>();
+
+        Emulator::new(&words)
+    }
+
+    pub fn step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    // Helpers
+    fn word_addr(&self, addr: i32) -> Result<usize, EmulatorError> {
+        if addr < 0 || addr % WORD_SIZE != 0 || addr as usize / 4 >= self.mem.len() {
+            return Err(EmulatorError::MemoryOutOfBounds { pc: self.pc, addr });
+        }
+        Ok(addr as usize / 4)
+    }
+
+    fn load(&mut self, addr: i32) -> Result<i32, EmulatorError> {
+        let idx = self.word_addr(addr)?;
+        self.stats.mem_reads += 1;
+        Ok(self.mem[idx])
+    }
+
+    fn store(&mut self, addr: i32, val: i32) -> Result<(), EmulatorError> {
+        let idx = self.word_addr(addr)?;
+        self.stats.mem_writes += 1;
+        self.mem[idx] = val;
+        Ok(())
+    }
+
+    fn read_num<R: BufRead>(input: &mut R) -> Result<i32, EmulatorError> {
+        let mut num = String::new();
+        let mut byte = [0_u8];
+        loop {
+            match input.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) if byte[0].is_ascii_whitespace() => {
+                    if !num.is_empty() {
+                        break;
+                    }
+                }
+                Ok(_) => num.push(byte[0] as char),
+                Err(err) => return Err(EmulatorError::Io(err.to_string())),
+            }
+        }
+
+        num.parse().map_err(|_| EmulatorError::InvalidInput(num))
+    }
+
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        mut input: R,
+        mut output: W,
+    ) -> Result<EmulatorStats, EmulatorError> {
+        loop {
+            if self
+                .step_limit
+                .is_some_and(|limit| self.stats.insts >= limit)
+            {
+                return Err(EmulatorError::StepLimitExceeded);
+            }
+            if self.pc >= self.program_len {
+                return Err(EmulatorError::PcOutOfBounds { pc: self.pc });
+            }
+
+            let inst = DlxInst::decode(self.mem[self.pc] as u32).map_err(
+                |InvalidOpcodeError(opcode)| EmulatorError::InvalidOpcode {
+                    pc: self.pc,
+                    opcode,
+                },
+            )?;
+            self.stats.insts += 1;
+
+            let halted = self.execute(inst, &mut input, &mut output)?;
+            self.regs[0] = 0;
+
+            if halted {
+                output
+                    .flush()
+                    .map_err(|err| EmulatorError::Io(err.to_string()))?;
+                return Ok(self.stats);
+            }
+        }
+    }
+
+    fn execute<R: BufRead, W: Write>(
+        &mut self,
+        inst: DlxInst,
+        input: &mut R,
+        output: &mut W,
+    ) -> Result<bool, EmulatorError> {
+        let DlxInst { op, a, b, c } = inst;
+        let (a, b) = (a as usize, b as usize);
+        let rb = self.regs[b];
+        // F2 instructions name a register in c
+        let rc = self.regs[(c & 0x1F) as usize];
+        let mut next_pc = self.pc + 1;
+
+        match op {
+            DlxOp::Add => self.regs[a] = rb.wrapping_add(rc),
+            DlxOp::Sub => self.regs[a] = rb.wrapping_sub(rc),
+            DlxOp::Mul => self.regs[a] = rb.wrapping_mul(rc),
+            DlxOp::Div => self.regs[a] = self.div(rb, rc)?,
+            DlxOp::Mod => self.regs[a] = self.rem(rb, rc)?,
+            DlxOp::Cmp => self.regs[a] = (rb as i64 - rc as i64).signum() as i32,
+            DlxOp::Or => self.regs[a] = rb | rc,
+            DlxOp::And => self.regs[a] = rb & rc,
+            DlxOp::Bic => self.regs[a] = rb & !rc,
+            DlxOp::Xor => self.regs[a] = rb ^ rc,
+            DlxOp::Lsh => self.regs[a] = Self::lsh(rb, rc),
+            DlxOp::Ash => self.regs[a] = Self::ash(rb, rc),
+            DlxOp::Chk => self.check(self.regs[a], rc)?,
+
+            DlxOp::Addi => self.regs[a] = rb.wrapping_add(c),
+            DlxOp::Subi => self.regs[a] = rb.wrapping_sub(c),
+            DlxOp::Muli => self.regs[a] = rb.wrapping_mul(c),
+            DlxOp::Divi => self.regs[a] = self.div(rb, c)?,
+            DlxOp::Modi => self.regs[a] = self.rem(rb, c)?,
+            DlxOp::Cmpi => self.regs[a] = (rb as i64 - c as i64).signum() as i32,
+            DlxOp::Ori => self.regs[a] = rb | c,
+            DlxOp::Andi => self.regs[a] = rb & c,
+            DlxOp::Bici => self.regs[a] = rb & !c,
+            DlxOp::Xori => self.regs[a] = rb ^ c,
+            DlxOp::Lshi => self.regs[a] = Self::lsh(rb, c),
+            DlxOp::Ashi => self.regs[a] = Self::ash(rb, c),
+            DlxOp::Chki => self.check(self.regs[a], c)?,
+
+            DlxOp::Ldw => self.regs[a] = self.load(rb.wrapping_add(c))?,
+            DlxOp::Ldx => self.regs[a] = self.load(rb.wrapping_add(rc))?,
+            DlxOp::Pop => {
+                self.regs[a] = self.load(rb)?;
+                self.regs[b] = self.regs[b].wrapping_add(c);
+            }
+            DlxOp::Stw => self.store(rb.wrapping_add(c), self.regs[a])?,
+            DlxOp::Stx => self.store(rb.wrapping_add(rc), self.regs[a])?,
+            DlxOp::Psh => {
+                self.regs[b] = self.regs[b].wrapping_add(c);
+                self.store(self.regs[b], self.regs[a])?;
+            }
+
+            DlxOp::Beq | DlxOp::Bne | DlxOp::Blt | DlxOp::Bge | DlxOp::Ble | DlxOp::Bgt => {
+                let val = self.regs[a];
+                let taken = match op {
+                    DlxOp::Beq => val == 0,
+                    DlxOp::Bne => val != 0,
+                    DlxOp::Blt => val < 0,
+                    DlxOp::Bge => val >= 0,
+                    DlxOp::Ble => val <= 0,
+                    _ => val > 0,
+                };
+                if taken {
+                    next_pc = self.branch_target(c)?;
+                }
+            }
+            DlxOp::Bsr => {
+                self.regs[REG_RET_ADDR as usize] = (next_pc as i32) * WORD_SIZE;
+                next_pc = self.branch_target(c)?;
+            }
+            DlxOp::Jsr => {
+                self.regs[REG_RET_ADDR as usize] = (next_pc as i32) * WORD_SIZE;
+                next_pc = (c / WORD_SIZE) as usize;
+            }
+            DlxOp::Ret => {
+                if rc == 0 {
+                    return Ok(true);
+                }
+                if rc < 0 {
+                    return Err(EmulatorError::PcOutOfBounds { pc: self.pc });
+                }
+                next_pc = (rc / WORD_SIZE) as usize;
+            }
+
+            DlxOp::Rdd => self.regs[a] = Self::read_num(input)?,
+            DlxOp::Wrd => {
+                write!(output, "{} ", rb).map_err(|err| EmulatorError::Io(err.to_string()))?
+            }
+            DlxOp::Wrh => {
+                write!(output, "0x{:x} ", rb).map_err(|err| EmulatorError::Io(err.to_string()))?
+            }
+            DlxOp::Wrl => writeln!(output).map_err(|err| EmulatorError::Io(err.to_string()))?,
+        }
+
+        self.pc = next_pc;
+        Ok(false)
+    }
+
+    fn branch_target(&self, offset: i32) -> Result<usize, EmulatorError> {
+        usize::try_from(self.pc as i64 + offset as i64)
+            .map_err(|_| EmulatorError::PcOutOfBounds { pc: self.pc })
+    }
+
+    fn div(&self, x: i32, y: i32) -> Result<i32, EmulatorError> {
+        if y == 0 {
+            return Err(EmulatorError::DivideByZero { pc: self.pc });
+        }
+        Ok(x.wrapping_div(y))
+    }
+
+    fn rem(&self, x: i32, y: i32) -> Result<i32, EmulatorError> {
+        if y == 0 {
+            return Err(EmulatorError::DivideByZero { pc: self.pc });
+        }
+        Ok(x.wrapping_rem(y))
+    }
+
+    fn check(&self, val: i32, bound: i32) -> Result<(), EmulatorError> {
+        if val < 0 || val >= bound {
+            return Err(EmulatorError::CheckFailed {
+                pc: self.pc,
+                val,
+                bound,
+            });
+        }
+        Ok(())
+    }
+
+    fn lsh(val: i32, shift: i32) -> i32 {
+        if shift >= 0 {
+            (val as u32).checked_shl(shift as u32).unwrap_or(0) as i32
+        } else {
+            (val as u32).checked_shr(-shift as u32).unwrap_or(0) as i32
+        }
+    }
+
+    fn ash(val: i32, shift: i32) -> i32 {
+        if shift >= 0 {
+            val.checked_shl(shift as u32).unwrap_or(0)
+        } else {
+            val.checked_shr(-shift as u32)
+                .unwrap_or(if val < 0 { -1 } else { 0 })
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compile, CompileOptions};
+
+    use super::*;
+
+    fn run(input: &[u8], stdin: &[u8]) -> (String, EmulatorStats) {
+        let ir = compile(input, &CompileOptions::default()).expect("Should compile");
+        let program = ir.generate_dlx().expect("Should generate");
+
+        let mut output = Vec::new();
+        let stats = Emulator::new(&program.words())
+            .step_limit(10_000_000)
+            .run(stdin, &mut output)
+            .expect("Should run");
+
+        (String::from_utf8(output).unwrap(), stats)
+    }
+
+    #[test]
+    fn basic_if_else() {
+        let input = b"
+main
+var a, b, c, d, e;
+{
+    let a <- call InputNum();
+    let b <- a;
+    let c <- b;
+    let d <- b + c;
+    let e <- a + b;
+    if a < 0 then
+        let a <- d + e;
+    else
+        let d <- e + 1;
+    fi;
+    call OutputNum(a);
+    call OutputNum(d);
+}
+.
+";
+        assert_eq!(run(input, b"-2").0, "-8 -4 ");
+        assert_eq!(run(input, b"3").0, "3 7 ");
+    }
+
+    #[test]
+    fn copy_loop() {
+        let input = b"
+main
+var fa, fb, n, t;
+{
+    let fa <- 0;
+    let fb <- 1;
+    let n <- call InputNum();
+    while n > 0 do
+        let t <- fb;
+        let fb <- fa + fb;
+        let fa <- t;
+        let n <- n - 1;
+    od;
+    call OutputNum(fa);
+    call OutputNewLine();
+}
+.";
+        assert_eq!(run(input, b"10\n").0, "55 \n");
+    }
+
+    #[test]
+    fn fib() {
+        let input = b"
+main
+var x;
+
+function fibonacci(n); {
+    if n <= 1 then
+        return n
+    fi;
+    return call fibonacci(n - 1) + call fibonacci(n - 2)
+};
+
+{
+    let x <- call InputNum;
+    let x <- call fibonacci(x);
+    call OutputNum(x);
+    call OutputNewLine
+}
+.
+";
+        let (output, stats) = run(input, b"12");
+        assert_eq!(output, "144 \n");
+        assert!(stats.insts > 0);
+        assert!(stats.mem_reads > 0 && stats.mem_writes > 0);
+    }
+
+    #[test]
+    fn func_gcd() {
+        let input = b"
+main
+function mod(x,y); {
+    if y == 0 then
+        return x;
+    fi;
+    while x < 0 do
+        let x <- x + y;
+    od;
+    while x >= y do
+        let x <- x - y;
+    od;
+    return x;
+};
+function gcd(x,y); {
+    if x == 0 then
+        return y;
+    fi;
+    return call gcd(y, call mod(x,y));
+};
+{
+    call OutputNum(call gcd(110,121));
+    call OutputNewLine();
+}
+.
+";
+        assert_eq!(run(input, b"").0, "11 \n");
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let ir = compile(
+            &b"
+main
+var x;
+{
+    let x <- call InputNum();
+    call OutputNum(1 / x)
+}
+."[..],
+            &CompileOptions::default(),
+        )
+        .expect("Should compile");
+        let program = ir.generate_dlx().expect("Should generate");
+
+        let res = Emulator::new(&program.words()).run(&b"0"[..], std::io::sink());
+        assert!(matches!(res, Err(EmulatorError::DivideByZero { .. })));
+    }
+
+    #[test]
+    fn binary_round_trip() {
+        let ir = compile(
+            &b"main { call OutputNum(7) }."[..],
+            &CompileOptions::default(),
+        )
+        .expect("Should compile");
+        let program = ir.generate_dlx().expect("Should generate");
+
+        let mut output = Vec::new();
+        Emulator::from_bytes(&program.generate_binary())
+            .run(&b""[..], &mut output)
+            .expect("Should run");
+        assert_eq!(output, b"7 ");
+    }
+}
@@ -0,0 +1,1124 @@
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::{
+    ast_data::{
+        BinOp, Call, Computation, Cond, CondKind, Expr, ExprKind, FuncDecl, LogicOp, Relation,
+        Stmt, StmtKind, VarDecl,
+    },
+    compile,
+    interpreter::InterpreterError,
+    tokenizer_data::{
+        RelOp, Span, PREDEFINED_IDENTS, PREDEFINED_INPUTNUM_ID, PREDEFINED_OUTPUTNEWLINE_ID,
+        PREDEFINED_OUTPUTNUM_ID,
+    },
+    CompileOptions, Passes,
+};
+
+// Generated programs stay well below this, it only catches broken loops
+const STEP_LIMIT: usize = 200_000;
+// Nesting of if and loop statements
+const MAX_DEPTH: usize = 3;
+const MAX_EXPR_DEPTH: usize = 3;
+const MAX_COND_DEPTH: usize = 2;
+const MAX_ELSIFS: usize = 3;
+// Nested loops per scope, each one owns a counter variable
+const MAX_LOOPS: usize = 2;
+const MAX_TRIPS: usize = 4;
+const MAX_FUNCS: usize = 3;
+const MAX_PARAMS: usize = 6;
+const INPUT_LEN: usize = 256;
+
+// xorshift64*, enough to make every run reproducible from its seed
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        let mut x = self.0;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.0 = x;
+        x.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() >> 32) as usize % n
+    }
+
+    fn chance(&mut self, percent: usize) -> bool {
+        self.below(100) < percent
+    }
+}
+
+// Random Tiny program along with the numbers it reads from stdin
+#[derive(Debug, Clone, PartialEq)]
+pub struct Program {
+    pub idents: Vec<String>,
+    pub ast: Computation,
+    pub stdin: String,
+}
+
+// Variables visible to the statements being generated
+#[derive(Debug)]
+struct Scope {
+    // Assignable variables, initialized before any other statement
+    vars: Vec<usize>,
+    // One counter per loop nesting level, never assigned by loop bodies
+    counters: Vec<usize>,
+    loops: usize,
+    // Whether `continue` still advances the counter of each enclosing loop,
+    // only `for` loops step it after the body
+    continuable: Vec<bool>,
+}
+
+impl Scope {
+    fn readable(&self) -> Vec<usize> {
+        let mut readable = self.vars.clone();
+        readable.extend_from_slice(&self.counters[..self.loops]);
+        readable
+    }
+}
+
+#[derive(Debug)]
+struct Generator {
+    rng: Rng,
+    idents: Vec<String>,
+    // (ident, param count, is void) of the functions declared so far
+    funcs: Vec<(usize, usize, bool)>,
+}
+
+impl Generator {
+    fn ident(&mut self, name: &str) -> usize {
+        match self.idents.iter().position(|ident| ident == name) {
+            Some(id) => id,
+            None => {
+                self.idents.push(name.to_string());
+                self.idents.len() - 1
+            }
+        }
+    }
+
+    fn decls(&mut self, prefix: &str, count: usize) -> Vec<usize> {
+        (0..count)
+            .map(|idx| self.ident(format!("{}{}", prefix, idx).as_str()))
+            .collect()
+    }
+
+    fn program(mut self) -> Program {
+        // Functions only see the ones declared before them, which rules out
+        // recursion
+        let funcs = (0..self.rng.below(MAX_FUNCS + 1))
+            .map(|idx| {
+                let func = self.func(idx);
+                self.funcs
+                    .push((func.name, func.params.len(), func.is_void));
+                func
+            })
+            .collect();
+
+        let count = 2 + self.rng.below(4);
+        let mut scope = Scope {
+            vars: self.decls("v", count),
+            counters: self.decls("i", MAX_LOOPS),
+            loops: 0,
+            continuable: Vec::new(),
+        };
+
+        let mut body = self.inits(&scope.vars);
+        let count = 2 + self.rng.below(5);
+        body.extend(self.stmts(&mut scope, 0, count));
+        // Makes every final value observable
+        for &var in scope.vars.iter() {
+            body.push(stmt(StmtKind::Call(output(var_expr(var)))));
+        }
+
+        let stdin = (0..INPUT_LEN)
+            .map(|_| (self.rng.below(41) as isize - 20).to_string())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Program {
+            idents: self.idents,
+            ast: Computation {
+                vars: var_decls(scope.vars.iter().chain(scope.counters.iter())),
+                funcs,
+                body,
+            },
+            stdin,
+        }
+    }
+
+    fn func(&mut self, idx: usize) -> FuncDecl {
+        let name = self.ident(format!("f{}", idx).as_str());
+        let is_void = self.rng.chance(30);
+        let count = self.rng.below(MAX_PARAMS + 1);
+        let params = self.decls("p", count);
+        let count = 1 + self.rng.below(3);
+        let locals = self.decls("l", count);
+
+        let mut scope = Scope {
+            vars: params.iter().chain(locals.iter()).copied().collect(),
+            counters: self.decls("i", MAX_LOOPS),
+            loops: 0,
+            continuable: Vec::new(),
+        };
+
+        let mut body = self.inits(&locals);
+        let count = 1 + self.rng.below(4);
+        body.extend(self.stmts(&mut scope, 0, count));
+        if !is_void {
+            let expr = self.expr(&scope, 0);
+            body.push(stmt(StmtKind::Return(Some(expr))));
+        }
+
+        FuncDecl {
+            name,
+            is_void,
+            params: var_decls(params.iter()),
+            vars: var_decls(locals.iter().chain(scope.counters.iter())),
+            body,
+            span: Span::default(),
+        }
+    }
+
+    fn inits(&mut self, vars: &[usize]) -> Vec<Stmt> {
+        vars.iter()
+            .map(|&var| {
+                let num = self.rng.below(20) as isize;
+                assign(var, num_expr(num))
+            })
+            .collect()
+    }
+
+    fn stmts(&mut self, scope: &mut Scope, depth: usize, count: usize) -> Vec<Stmt> {
+        (0..count).flat_map(|_| self.stmt(scope, depth)).collect()
+    }
+
+    // While and repeat loops come out as two statements, the counter reset
+    // and the loop
+    fn stmt(&mut self, scope: &mut Scope, depth: usize) -> Vec<Stmt> {
+        let nested = depth < MAX_DEPTH;
+        match self.rng.below(12) {
+            0..=4 => {
+                let var = scope.vars[self.rng.below(scope.vars.len())];
+                vec![assign(var, self.expr(scope, 0))]
+            }
+            5 | 6 => vec![stmt(StmtKind::Call(output(self.expr(scope, 0))))],
+            7 | 8 if nested => {
+                let cond = self.cond(scope, 0);
+                let count = 1 + self.rng.below(3);
+                let then_body = self.stmts(scope, depth + 1, count);
+                let mut elsifs = Vec::new();
+                while elsifs.len() < MAX_ELSIFS && self.rng.chance(30) {
+                    let cond = self.cond(scope, 0);
+                    let count = 1 + self.rng.below(3);
+                    elsifs.push((cond, self.stmts(scope, depth + 1, count)));
+                }
+                let else_body = if self.rng.chance(50) {
+                    let count = 1 + self.rng.below(3);
+                    Some(self.stmts(scope, depth + 1, count))
+                } else {
+                    None
+                };
+                vec![stmt(StmtKind::If {
+                    cond,
+                    then_body,
+                    elsifs,
+                    else_body,
+                })]
+            }
+            9 | 10 if nested && scope.loops < MAX_LOOPS => self.r#loop(scope, depth),
+            11 if scope.loops > 0 && self.rng.chance(40) => {
+                let kind = if scope.continuable[scope.loops - 1] && self.rng.chance(50) {
+                    StmtKind::Continue
+                } else {
+                    StmtKind::Break
+                };
+                vec![stmt(StmtKind::If {
+                    cond: self.cond(scope, 0),
+                    then_body: vec![stmt(kind)],
+                    elsifs: Vec::new(),
+                    else_body: None,
+                })]
+            }
+            _ if !self.funcs.is_empty() && self.rng.chance(70) => {
+                let idx = self.rng.below(self.funcs.len());
+                vec![stmt(StmtKind::Call(self.call(scope, idx, 0)))]
+            }
+            _ => vec![stmt(StmtKind::Call(Call {
+                func: PREDEFINED_OUTPUTNEWLINE_ID,
+                args: Vec::new(),
+                span: Span::default(),
+            }))],
+        }
+    }
+
+    // A while, for or repeat loop running at most `MAX_TRIPS` times
+    fn r#loop(&mut self, scope: &mut Scope, depth: usize) -> Vec<Stmt> {
+        let counter = scope.counters[scope.loops];
+        let trips = 1 + self.rng.below(MAX_TRIPS);
+        let kind = self.rng.below(3);
+
+        scope.loops += 1;
+        scope.continuable.push(kind == 1);
+        let count = 1 + self.rng.below(3);
+        let mut body = self.stmts(scope, depth + 1, count);
+        scope.continuable.pop();
+        scope.loops -= 1;
+
+        let step = assign(counter, binary(BinOp::Add, var_expr(counter), num_expr(1)));
+        let cond = |op| Relation {
+            op,
+            lhs: var_expr(counter),
+            rhs: num_expr(trips as isize),
+            span: Span::default(),
+        };
+        let op = if self.rng.chance(50) {
+            RelOp::LessThan
+        } else {
+            RelOp::NotEqual
+        };
+
+        match kind {
+            0 => {
+                body.push(step);
+                vec![
+                    assign(counter, num_expr(0)),
+                    stmt(StmtKind::While {
+                        cond: cond(op).into(),
+                        body,
+                    }),
+                ]
+            }
+            1 => vec![stmt(StmtKind::For {
+                var: counter,
+                from: num_expr(1),
+                to: num_expr(trips as isize),
+                step: self.rng.chance(30).then(|| num_expr(2)),
+                body,
+            })],
+            _ => {
+                body.push(step);
+                let op = if op == RelOp::LessThan {
+                    RelOp::GreaterThanOrEqual
+                } else {
+                    RelOp::Equal
+                };
+                vec![
+                    assign(counter, num_expr(0)),
+                    stmt(StmtKind::Repeat {
+                        body,
+                        cond: cond(op).into(),
+                    }),
+                ]
+            }
+        }
+    }
+
+    fn call(&mut self, scope: &Scope, idx: usize, depth: usize) -> Call {
+        let (func, params, _) = self.funcs[idx];
+        Call {
+            func,
+            args: (0..params).map(|_| self.expr(scope, depth + 1)).collect(),
+            span: Span::default(),
+        }
+    }
+
+    // Mostly plain relations, sometimes combined with `not`, `and` or `or`
+    fn cond(&mut self, scope: &Scope, depth: usize) -> Cond {
+        if depth >= MAX_COND_DEPTH || self.rng.chance(60) {
+            return self.relation(scope).into();
+        }
+        let kind = match self.rng.below(3) {
+            0 => CondKind::Not(Box::new(self.cond(scope, depth + 1))),
+            n => CondKind::Logical {
+                op: if n == 1 { LogicOp::And } else { LogicOp::Or },
+                lhs: Box::new(self.cond(scope, depth + 1)),
+                rhs: Box::new(self.cond(scope, depth + 1)),
+            },
+        };
+        Cond {
+            kind,
+            span: Span::default(),
+        }
+    }
+
+    fn relation(&mut self, scope: &Scope) -> Relation {
+        let op = match self.rng.below(6) {
+            0 => RelOp::Equal,
+            1 => RelOp::NotEqual,
+            2 => RelOp::LessThan,
+            3 => RelOp::LessThanOrEqual,
+            4 => RelOp::GreaterThan,
+            _ => RelOp::GreaterThanOrEqual,
+        };
+        Relation {
+            op,
+            lhs: self.expr(scope, 1),
+            rhs: self.expr(scope, 1),
+            span: Span::default(),
+        }
+    }
+
+    fn expr(&mut self, scope: &Scope, depth: usize) -> Expr {
+        if depth >= MAX_EXPR_DEPTH || self.rng.chance(35) {
+            let readable = scope.readable();
+            return match self.rng.below(10) {
+                0..=5 => var_expr(readable[self.rng.below(readable.len())]),
+                6..=8 => num_expr(self.rng.below(10) as isize),
+                _ => Expr {
+                    kind: ExprKind::Call(Call {
+                        func: PREDEFINED_INPUTNUM_ID,
+                        args: Vec::new(),
+                        span: Span::default(),
+                    }),
+                    span: Span::default(),
+                },
+            };
+        }
+
+        let values = self
+            .funcs
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, _, is_void))| !is_void)
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        if !values.is_empty() && self.rng.chance(15) {
+            let idx = values[self.rng.below(values.len())];
+            return Expr {
+                kind: ExprKind::Call(self.call(scope, idx, depth)),
+                span: Span::default(),
+            };
+        }
+
+        let lhs = self.expr(scope, depth + 1);
+        match self.rng.below(4) {
+            0 => binary(BinOp::Add, lhs, self.expr(scope, depth + 1)),
+            1 => binary(BinOp::Sub, lhs, self.expr(scope, depth + 1)),
+            2 => binary(BinOp::Mul, lhs, self.expr(scope, depth + 1)),
+            // Only constant divisors, so no division by zero
+            _ => binary(BinOp::Div, lhs, num_expr(1 + self.rng.below(9) as isize)),
+        }
+    }
+}
+
+fn stmt(kind: StmtKind) -> Stmt {
+    Stmt {
+        kind,
+        span: Span::default(),
+    }
+}
+
+fn assign(var: usize, expr: Expr) -> Stmt {
+    stmt(StmtKind::Assign { var, expr })
+}
+
+fn output(expr: Expr) -> Call {
+    Call {
+        func: PREDEFINED_OUTPUTNUM_ID,
+        args: vec![expr],
+        span: Span::default(),
+    }
+}
+
+fn num_expr(num: isize) -> Expr {
+    Expr {
+        kind: ExprKind::Num(num),
+        span: Span::default(),
+    }
+}
+
+fn var_expr(var: usize) -> Expr {
+    Expr {
+        kind: ExprKind::Var(var),
+        span: Span::default(),
+    }
+}
+
+fn binary(op: BinOp, lhs: Expr, rhs: Expr) -> Expr {
+    Expr {
+        kind: ExprKind::Binary {
+            op,
+            lhs: Box::new(lhs),
+            rhs: Box::new(rhs),
+        },
+        span: Span::default(),
+    }
+}
+
+fn var_decls<'a>(vars: impl Iterator<Item = &'a usize>) -> Vec<VarDecl> {
+    vars.map(|&var| VarDecl {
+        var,
+        span: Span::default(),
+    })
+    .collect()
+}
+
+impl Program {
+    pub fn generate(seed: u64) -> Program {
+        Generator {
+            rng: Rng::new(seed),
+            idents: PREDEFINED_IDENTS.iter().map(|s| s.to_string()).collect(),
+            funcs: Vec::new(),
+        }
+        .program()
+    }
+
+    // Tiny source accepted by `Parser`
+    pub fn source(&self) -> String {
+        let mut data = "main\n".to_string();
+        data += self.vars(&self.ast.vars).as_str();
+
+        for func in self.ast.funcs.iter() {
+            let params = func
+                .params
+                .iter()
+                .map(|param| self.idents[param.var].as_str())
+                .collect::<Vec<_>>();
+            data += format!(
+                "\n{}function {}({});\n",
+                if func.is_void { "void " } else { "" },
+                self.idents[func.name],
+                params.join(", ")
+            )
+            .as_str();
+            data += self.vars(&func.vars).as_str();
+            data += format!("{{\n{}}};\n", self.stmts(&func.body, 1)).as_str();
+        }
+
+        data += format!("\n{{\n{}}}.\n", self.stmts(&self.ast.body, 1)).as_str();
+        data
+    }
+
+    fn vars(&self, vars: &[VarDecl]) -> String {
+        if vars.is_empty() {
+            return String::new();
+        }
+        let vars = vars
+            .iter()
+            .map(|decl| self.idents[decl.var].as_str())
+            .collect::<Vec<_>>();
+        format!("var {};\n", vars.join(", "))
+    }
+
+    fn stmts(&self, stmts: &[Stmt], indent: usize) -> String {
+        let pad = "    ".repeat(indent);
+        let stmts = stmts
+            .iter()
+            .map(|stmt| match &stmt.kind {
+                StmtKind::Assign { var, expr } => {
+                    format!("{}let {} <- {}", pad, self.idents[*var], self.expr(expr))
+                }
+                StmtKind::Call(call) => format!("{}{}", pad, self.call(call)),
+                StmtKind::If {
+                    cond,
+                    then_body,
+                    elsifs,
+                    else_body,
+                } => {
+                    let mut data = format!(
+                        "{}if {} then\n{}",
+                        pad,
+                        self.cond(cond),
+                        self.stmts(then_body, indent + 1)
+                    );
+                    for (cond, body) in elsifs {
+                        data += format!(
+                            "{}elsif {} then\n{}",
+                            pad,
+                            self.cond(cond),
+                            self.stmts(body, indent + 1)
+                        )
+                        .as_str();
+                    }
+                    if let Some(else_body) = else_body {
+                        data +=
+                            format!("{}else\n{}", pad, self.stmts(else_body, indent + 1)).as_str();
+                    }
+                    data + pad.as_str() + "fi"
+                }
+                StmtKind::While { cond, body } => format!(
+                    "{}while {} do\n{}{}od",
+                    pad,
+                    self.cond(cond),
+                    self.stmts(body, indent + 1),
+                    pad
+                ),
+                StmtKind::For {
+                    var,
+                    from,
+                    to,
+                    step,
+                    body,
+                } => format!(
+                    "{}for {} <- {} to {}{} do\n{}{}od",
+                    pad,
+                    self.idents[*var],
+                    self.expr(from),
+                    self.expr(to),
+                    step.as_ref()
+                        .map_or(String::new(), |step| format!(" step {}", self.expr(step))),
+                    self.stmts(body, indent + 1),
+                    pad
+                ),
+                StmtKind::Repeat { body, cond } => format!(
+                    "{}repeat\n{}{}until {}",
+                    pad,
+                    self.stmts(body, indent + 1),
+                    pad,
+                    self.cond(cond)
+                ),
+                StmtKind::Break => format!("{}break", pad),
+                StmtKind::Continue => format!("{}continue", pad),
+                StmtKind::Return(Some(expr)) => format!("{}return {}", pad, self.expr(expr)),
+                StmtKind::Return(None) => format!("{}return", pad),
+            })
+            .collect::<Vec<_>>();
+        stmts.join(";\n") + "\n"
+    }
+
+    fn call(&self, call: &Call) -> String {
+        let args = call
+            .args
+            .iter()
+            .map(|arg| self.expr(arg))
+            .collect::<Vec<_>>();
+        format!("call {}({})", self.idents[call.func], args.join(", "))
+    }
+
+    // Operands of `not`, `and` and `or` that combine conditions themselves
+    // are parenthesised
+    fn cond(&self, cond: &Cond) -> String {
+        let operand = |cond: &Cond| match cond.kind {
+            CondKind::Logical { .. } => format!("({})", self.cond(cond)),
+            _ => self.cond(cond),
+        };
+        match &cond.kind {
+            CondKind::Relation(relation) => self.relation(relation),
+            CondKind::Not(cond) => format!("not {}", operand(cond)),
+            CondKind::Logical { op, lhs, rhs } => {
+                format!("{} {} {}", operand(lhs), op.keyword(), operand(rhs))
+            }
+        }
+    }
+
+    fn relation(&self, relation: &Relation) -> String {
+        format!(
+            "{} {} {}",
+            self.expr(&relation.lhs),
+            relation.op.symbol(),
+            self.expr(&relation.rhs)
+        )
+    }
+
+    fn expr(&self, expr: &Expr) -> String {
+        match &expr.kind {
+            // Tiny has no unary minus
+            ExprKind::Num(num) if *num < 0 => format!("(0 - {})", -num),
+            ExprKind::Num(num) => num.to_string(),
+            ExprKind::Var(var) => self.idents[*var].to_string(),
+            ExprKind::Binary { op, lhs, rhs } => {
+                format!("({} {} {})", self.expr(lhs), op.symbol(), self.expr(rhs))
+            }
+            ExprKind::Call(call) => self.call(call),
+        }
+    }
+}
+
+// Observable result of running a program
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub output: String,
+    // Kind of the runtime error, the instruction ids differ between levels
+    pub error: Option<&'static str>,
+}
+
+fn error_kind(err: &InterpreterError) -> &'static str {
+    match err {
+        InterpreterError::DivideByZero { .. } => "DivideByZero",
+        InterpreterError::UninitializedRead { .. } => "UninitializedRead",
+        InterpreterError::MissingArgument { .. } => "MissingArgument",
+        InterpreterError::FellOffBlock { .. } => "FellOffBlock",
+        InterpreterError::InvalidInput(_) => "InvalidInput",
+        InterpreterError::StepLimitExceeded => "StepLimitExceeded",
+        InterpreterError::Io(_) => "Io",
+    }
+}
+
+// Compiles and interprets `program`, panics in the compiler count as errors
+fn execute(program: &Program, passes: Passes) -> Option<Outcome> {
+    let source = program.source();
+    let options = CompileOptions {
+        passes,
+        ..CompileOptions::default()
+    };
+    let res = panic::catch_unwind(AssertUnwindSafe(|| {
+        let module = compile(source.as_bytes(), &options).ok()?;
+        let mut stdout = Vec::new();
+        let res = module
+            .interpreter()
+            .step_limit(STEP_LIMIT)
+            .run(program.stdin.as_bytes(), &mut stdout);
+        Some(Outcome {
+            output: String::from_utf8_lossy(&stdout).to_string(),
+            error: res.err().as_ref().map(error_kind),
+        })
+    }));
+
+    match res {
+        Ok(outcome) => outcome,
+        Err(_) => Some(Outcome {
+            output: String::new(),
+            error: Some("Panic"),
+        }),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Divergence {
+    // Result without optimizations
+    pub expected: Outcome,
+    pub actual: Outcome,
+}
+
+// Runs `program` without optimizations and with `passes`, Some when the
+// results differ. Programs whose unoptimized run is not well defined are
+// skipped, the optimizer may legitimately change those
+pub fn check(program: &Program, passes: Passes) -> Option<Divergence> {
+    let expected = execute(program, Passes::level(0))?;
+    if matches!(
+        expected.error,
+        Some("StepLimitExceeded" | "UninitializedRead" | "Panic")
+    ) {
+        return None;
+    }
+
+    let actual = execute(program, passes).unwrap_or(Outcome {
+        output: String::new(),
+        error: Some("CompileError"),
+    });
+    (actual != expected).then_some(Divergence { expected, actual })
+}
+
+// Greedily applies the first simplification that keeps `fails` true until
+// none does
+pub fn shrink(program: &Program, fails: impl Fn(&Program) -> bool) -> Program {
+    let mut program = program.clone();
+    while let Some(smaller) = program
+        .variants()
+        .into_iter()
+        .find(|variant| fails(variant))
+    {
+        program = smaller;
+    }
+    program
+}
+
+impl Program {
+    // Every program one simplification away from this one
+    fn variants(&self) -> Vec<Program> {
+        let mut variants = Vec::new();
+        let source = self.source();
+
+        for (idx, func) in self.ast.funcs.iter().enumerate() {
+            let name = format!("call {}(", self.idents[func.name]);
+            if !source.contains(name.as_str()) {
+                let mut variant = self.clone();
+                variant.ast.funcs.remove(idx);
+                variants.push(variant);
+            }
+        }
+
+        for (idx, func) in self.ast.funcs.iter().enumerate() {
+            // Keeps the final return of functions with a value
+            let (body, ret) = match func.body.split_last() {
+                Some((ret, body)) if !func.is_void => (body, Some(ret)),
+                _ => (&func.body[..], None),
+            };
+            let min = if ret.is_some() { 0 } else { 1 };
+
+            for mut body in stmts_variants(body, min) {
+                let mut variant = self.clone();
+                body.extend(ret.cloned());
+                variant.ast.funcs[idx].body = body;
+                variants.push(variant);
+            }
+            if let Some(ret) = ret {
+                for ret in stmt_variants(ret) {
+                    let mut variant = self.clone();
+                    let body = &mut variant.ast.funcs[idx].body;
+                    body.pop();
+                    body.extend(ret);
+                    variants.push(variant);
+                }
+            }
+        }
+
+        for body in stmts_variants(&self.ast.body, 1) {
+            let mut variant = self.clone();
+            variant.ast.body = body;
+            variants.push(variant);
+        }
+
+        variants
+    }
+}
+
+// Removes a statement or simplifies one in place, keeping at least `min`
+fn stmts_variants(stmts: &[Stmt], min: usize) -> Vec<Vec<Stmt>> {
+    let mut variants = Vec::new();
+    if stmts.len() > min {
+        for idx in 0..stmts.len() {
+            let mut variant = stmts.to_vec();
+            variant.remove(idx);
+            variants.push(variant);
+        }
+    }
+
+    for (idx, stmt) in stmts.iter().enumerate() {
+        for replacement in stmt_variants(stmt) {
+            let mut variant = stmts.to_vec();
+            variant.splice(idx..idx + 1, replacement);
+            if variant.len() >= min {
+                variants.push(variant);
+            }
+        }
+    }
+    variants
+}
+
+// Replacements for a single statement
+fn stmt_variants(old: &Stmt) -> Vec<Vec<Stmt>> {
+    let mut variants = Vec::new();
+    match &old.kind {
+        StmtKind::Assign { var, expr } => {
+            for expr in expr_variants(expr) {
+                variants.push(vec![assign(*var, expr)]);
+            }
+        }
+        StmtKind::Call(call) => {
+            for call in call_variants(call) {
+                variants.push(vec![stmt(StmtKind::Call(call))]);
+            }
+        }
+        StmtKind::If {
+            cond,
+            then_body,
+            elsifs,
+            else_body,
+        } => {
+            let if_stmt = |cond: &Cond,
+                           then_body: &[Stmt],
+                           elsifs: Vec<(Cond, Vec<Stmt>)>,
+                           else_body: Option<Vec<Stmt>>| {
+                vec![stmt(StmtKind::If {
+                    cond: cond.clone(),
+                    then_body: then_body.to_vec(),
+                    elsifs,
+                    else_body,
+                })]
+            };
+
+            variants.push(then_body.clone());
+            variants.extend(elsifs.iter().map(|(_, body)| body.clone()));
+            if let Some(else_body) = else_body {
+                variants.push(else_body.clone());
+                variants.push(if_stmt(cond, then_body, elsifs.clone(), None));
+            }
+            for idx in 0..elsifs.len() {
+                let mut elsifs = elsifs.clone();
+                elsifs.remove(idx);
+                variants.push(if_stmt(cond, then_body, elsifs, else_body.clone()));
+            }
+            for cond in cond_variants(cond) {
+                variants.push(if_stmt(&cond, then_body, elsifs.clone(), else_body.clone()));
+            }
+            for then_body in stmts_variants(then_body, 1) {
+                variants.push(if_stmt(cond, &then_body, elsifs.clone(), else_body.clone()));
+            }
+            for (idx, (elsif_cond, body)) in elsifs.iter().enumerate() {
+                let arms = cond_variants(elsif_cond)
+                    .into_iter()
+                    .map(|elsif_cond| (elsif_cond, body.clone()))
+                    .chain(
+                        stmts_variants(body, 1)
+                            .into_iter()
+                            .map(|body| (elsif_cond.clone(), body)),
+                    );
+                for arm in arms {
+                    let mut elsifs = elsifs.clone();
+                    elsifs[idx] = arm;
+                    variants.push(if_stmt(cond, then_body, elsifs, else_body.clone()));
+                }
+            }
+            for else_body in stmts_variants(else_body.as_deref().unwrap_or_default(), 1) {
+                variants.push(if_stmt(cond, then_body, elsifs.clone(), Some(else_body)));
+            }
+        }
+        StmtKind::While { cond, body } => {
+            variants.push(body.clone());
+            for cond in cond_variants(cond) {
+                variants.push(vec![stmt(StmtKind::While {
+                    cond,
+                    body: body.clone(),
+                })]);
+            }
+            for body in stmts_variants(body, 1) {
+                variants.push(vec![stmt(StmtKind::While {
+                    cond: cond.clone(),
+                    body,
+                })]);
+            }
+        }
+        StmtKind::For {
+            var,
+            from,
+            to,
+            step,
+            body,
+        } => {
+            variants.push(body.clone());
+            if step.is_some() {
+                variants.push(vec![stmt(StmtKind::For {
+                    var: *var,
+                    from: from.clone(),
+                    to: to.clone(),
+                    step: None,
+                    body: body.clone(),
+                })]);
+            }
+            for body in stmts_variants(body, 1) {
+                variants.push(vec![stmt(StmtKind::For {
+                    var: *var,
+                    from: from.clone(),
+                    to: to.clone(),
+                    step: step.clone(),
+                    body,
+                })]);
+            }
+        }
+        StmtKind::Repeat { body, cond } => {
+            variants.push(body.clone());
+            for cond in cond_variants(cond) {
+                variants.push(vec![stmt(StmtKind::Repeat {
+                    body: body.clone(),
+                    cond,
+                })]);
+            }
+            for body in stmts_variants(body, 1) {
+                variants.push(vec![stmt(StmtKind::Repeat {
+                    body,
+                    cond: cond.clone(),
+                })]);
+            }
+        }
+        StmtKind::Return(Some(expr)) => {
+            for expr in expr_variants(expr) {
+                variants.push(vec![stmt(StmtKind::Return(Some(expr)))]);
+            }
+        }
+        StmtKind::Return(None) | StmtKind::Break | StmtKind::Continue => {}
+    }
+    variants
+}
+
+// Drops `not`, keeps either side of `and` and `or`, or shrinks a part
+fn cond_variants(cond: &Cond) -> Vec<Cond> {
+    let mut variants = Vec::new();
+    match &cond.kind {
+        CondKind::Relation(relation) => {
+            variants.extend(relation_variants(relation).into_iter().map(Cond::from));
+        }
+        CondKind::Not(inner) => {
+            variants.push(inner.as_ref().clone());
+            for inner in cond_variants(inner) {
+                variants.push(Cond {
+                    kind: CondKind::Not(Box::new(inner)),
+                    span: cond.span,
+                });
+            }
+        }
+        CondKind::Logical { op, lhs, rhs } => {
+            variants.push(lhs.as_ref().clone());
+            variants.push(rhs.as_ref().clone());
+            for lhs in cond_variants(lhs) {
+                variants.push(Cond {
+                    kind: CondKind::Logical {
+                        op: *op,
+                        lhs: Box::new(lhs),
+                        rhs: rhs.clone(),
+                    },
+                    span: cond.span,
+                });
+            }
+            for rhs in cond_variants(rhs) {
+                variants.push(Cond {
+                    kind: CondKind::Logical {
+                        op: *op,
+                        lhs: lhs.clone(),
+                        rhs: Box::new(rhs),
+                    },
+                    span: cond.span,
+                });
+            }
+        }
+    }
+    variants
+}
+
+fn relation_variants(relation: &Relation) -> Vec<Relation> {
+    let mut variants = Vec::new();
+    for lhs in expr_variants(&relation.lhs) {
+        variants.push(Relation {
+            lhs,
+            ..relation.clone()
+        });
+    }
+    for rhs in expr_variants(&relation.rhs) {
+        variants.push(Relation {
+            rhs,
+            ..relation.clone()
+        });
+    }
+    variants
+}
+
+fn call_variants(call: &Call) -> Vec<Call> {
+    let mut variants = Vec::new();
+    for (idx, arg) in call.args.iter().enumerate() {
+        for arg in expr_variants(arg) {
+            let mut variant = call.clone();
+            variant.args[idx] = arg;
+            variants.push(variant);
+        }
+    }
+    variants
+}
+
+// Smaller expressions computing something else, the predicate decides
+// whether the change still matters
+fn expr_variants(expr: &Expr) -> Vec<Expr> {
+    let mut variants = Vec::new();
+    match &expr.kind {
+        ExprKind::Num(0) => {}
+        ExprKind::Num(_) | ExprKind::Var(_) => variants.push(num_expr(0)),
+        ExprKind::Binary { op, lhs, rhs } => {
+            variants.push(num_expr(0));
+            variants.push(*lhs.clone());
+            variants.push(*rhs.clone());
+            for lhs in expr_variants(lhs) {
+                variants.push(binary(*op, lhs, *rhs.clone()));
+            }
+            // Constant folding divides in the compiler, keep divisors nonzero
+            if *op != BinOp::Div {
+                for rhs in expr_variants(rhs) {
+                    variants.push(binary(*op, *lhs.clone(), rhs));
+                }
+            }
+        }
+        ExprKind::Call(call) => {
+            if call.func != PREDEFINED_INPUTNUM_ID {
+                variants.push(num_expr(0));
+            }
+            for call in call_variants(call) {
+                variants.push(Expr {
+                    kind: ExprKind::Call(call),
+                    span: Span::default(),
+                });
+            }
+        }
+    }
+    variants
+}
+
+#[derive(Debug, Clone)]
+pub struct Failure {
+    pub seed: u64,
+    // Shrunk program
+    pub program: Program,
+    pub divergence: Divergence,
+}
+
+// Checks `count` programs generated from consecutive seeds against `passes`,
+// shrinking every divergence found
+pub fn fuzz(seed: u64, count: usize, passes: Passes) -> Vec<Failure> {
+    (seed..seed + count as u64)
+        .filter_map(|seed| {
+            let program = Program::generate(seed);
+            check(&program, passes)?;
+
+            let program = shrink(&program, |program| check(program, passes).is_some());
+            let divergence = check(&program, passes).expect("Shrunk program should still fail");
+            Some(Failure {
+                seed,
+                program,
+                divergence,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generate() {
+        for seed in 0..50 {
+            let program = Program::generate(seed);
+            assert_eq!(program, Program::generate(seed));
+
+            let source = program.source();
+            let module = compile(source.as_bytes(), &CompileOptions::default())
+                .unwrap_or_else(|err| panic!("Seed {} should compile:\n{}\n{}", seed, err, source));
+            let res = module
+                .interpreter()
+                .step_limit(STEP_LIMIT)
+                .run(program.stdin.as_bytes(), &mut Vec::new());
+            // Reads inside loops may run out of input, anything else is a
+            // generator bug
+            if let Err(err) = res {
+                assert!(
+                    matches!(err, InterpreterError::InvalidInput(_)),
+                    "Seed {} should run: {:?}\n{}",
+                    seed,
+                    err,
+                    source
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn no_divergence() {
+        let failures = fuzz(0, 100, Passes::level(2));
+        assert!(
+            failures.is_empty(),
+            "{}",
+            failures
+                .iter()
+                .map(|failure| format!("seed {}:\n{}", failure.seed, failure.program.source()))
+                .collect::<String>()
+        );
+    }
+
+    #[test]
+    fn shrink_program() {
+        let program = (0..)
+            .map(Program::generate)
+            .find(|program| program.source().contains(" * "))
+            .expect("Some program should multiply");
+
+        let shrunk = shrink(&program, |program| program.source().contains(" * "));
+        assert!(shrunk.source().len() < program.source().len());
+        assert_eq!(shrunk.source().matches(" * ").count(), 1);
+        assert_eq!(shrunk.ast.body.len(), 1);
+        compile(shrunk.source().as_bytes(), &CompileOptions::default()).expect("Should compile");
+    }
+}
@@ -1,14 +1,21 @@
 use std::io::BufRead;
 
+use crate::tokenizer_data::Span;
+
 #[derive(Debug, PartialEq)]
 pub struct Input<R: BufRead> {
     input: R,
     curr: Option<u8>,
+    span: Span,
 }
 
 impl<R: BufRead> Input<R> {
     pub fn new(input: R) -> Input<R> {
-        let mut res = Input { input, curr: None };
+        let mut res = Input {
+            input,
+            curr: None,
+            span: Span { line: 1, col: 0 },
+        };
         res.next();
         res
     }
@@ -25,8 +32,22 @@ impl<R: BufRead> Input<R> {
         self.curr
     }
 
+    // Position of the current char
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
     pub fn next(&mut self) -> Option<u8> {
         let old = self.curr;
+        if old == Some(b'\n') {
+            self.span = Span {
+                line: self.span.line + 1,
+                col: 1,
+            };
+        } else {
+            self.span.col += 1;
+        }
+
         self.curr = self.read_char();
         old
     }
@@ -0,0 +1,470 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use crate::parser_data::{BlockList, IType, InstList};
+
+// None if the value came from an uninitialized variable
+type Value = Option<i32>;
+
+#[derive(Debug, PartialEq)]
+pub enum InterpreterError {
+    DivideByZero { inst: usize },
+    UninitializedRead { inst: usize, var: Option<usize> },
+    MissingArgument { inst: usize, argnum: usize },
+    FellOffBlock { block: usize },
+    InvalidInput(String),
+    StepLimitExceeded,
+    Io(String),
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InterpreterStats {
+    pub insts: usize,
+    pub calls: usize,
+}
+
+#[derive(Debug)]
+struct Frame {
+    values: Vec<Value>,
+    args: Vec<Value>,
+    block: usize,
+    // index into the block's insts
+    pc: usize,
+    // jsr inst in the caller
+    ret_inst: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct Interpreter<'a> {
+    insts: &'a InstList,
+    blocks: &'a BlockList,
+    // placeholder const -> var
+    undef: BTreeMap<usize, usize>,
+    stack: Vec<Frame>,
+    pending_args: Vec<Value>,
+    // var -> value of the global in memory
+    globals: BTreeMap<usize, Value>,
+    step_limit: Option<usize>,
+    stats: InterpreterStats,
+}
+
+impl<'a> Interpreter<'a> {
+    pub fn new(
+        insts: &'a InstList,
+        blocks: &'a BlockList,
+        undef: BTreeMap<usize, usize>,
+    ) -> Interpreter<'a> {
+        Interpreter {
+            insts,
+            blocks,
+            undef,
+            stack: Vec::new(),
+            pending_args: Vec::new(),
+            globals: BTreeMap::new(),
+            step_limit: None,
+            stats: InterpreterStats::default(),
+        }
+    }
+
+    pub fn step_limit(mut self, limit: usize) -> Self {
+        self.step_limit = Some(limit);
+        self
+    }
+
+    // Helpers
+    fn frame(&self) -> &Frame {
+        self.stack.last().expect("Frame should exist")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame {
+        self.stack.last_mut().expect("Frame should exist")
+    }
+
+    fn new_frame(&self, block: usize, args: Vec<Value>, ret_inst: Option<usize>) -> Frame {
+        Frame {
+            values: vec![None; self.insts.instructions.len()],
+            args,
+            block,
+            pc: 0,
+            ret_inst,
+        }
+    }
+
+    fn value(&self, inst: usize) -> Value {
+        match self.insts.get(inst).itype() {
+            IType::Const(_) if self.undef.contains_key(&inst) => None,
+            IType::Const(val) => Some(val as i32),
+            _ => self.frame().values[inst],
+        }
+    }
+
+    fn operand(&self, user: usize, inst: (usize, Option<usize>)) -> Result<i32, InterpreterError> {
+        self.value(inst.0)
+            .ok_or(InterpreterError::UninitializedRead {
+                inst: user,
+                var: self.undef.get(&inst.0).copied().or(inst.1),
+            })
+    }
+
+    fn set(&mut self, inst: usize, val: Value) {
+        self.frame_mut().values[inst] = val;
+    }
+
+    fn read_num<R: BufRead>(input: &mut R) -> Result<i32, InterpreterError> {
+        let mut num = String::new();
+        let mut byte = [0_u8];
+        loop {
+            match input.read(&mut byte) {
+                Ok(0) => break,
+                Ok(_) if byte[0].is_ascii_whitespace() => {
+                    if !num.is_empty() {
+                        break;
+                    }
+                }
+                Ok(_) => num.push(byte[0] as char),
+                Err(err) => return Err(InterpreterError::Io(err.to_string())),
+            }
+        }
+
+        num.parse().map_err(|_| InterpreterError::InvalidInput(num))
+    }
+
+    fn io_error(err: std::io::Error) -> InterpreterError {
+        InterpreterError::Io(err.to_string())
+    }
+
+    // Control flow
+    fn enter_block(&mut self, from: usize, to: usize) {
+        let block = self.blocks.get(to);
+        let phis = block.get_phis(self.insts);
+
+        if !phis.is_empty() {
+            let pred_idx = block
+                .get_preds()
+                .iter()
+                .position(|&pred| pred == from)
+                .expect("Predecessor should exist");
+
+            // Phis read their operands in parallel
+            let vals = phis
+                .iter()
+                .map(|&phi| self.value(self.insts.get(phi).get_phi_operand(pred_idx)))
+                .collect::<Vec<_>>();
+            for (phi, val) in phis.into_iter().zip(vals) {
+                self.set(phi, val);
+            }
+        }
+
+        let frame = self.frame_mut();
+        frame.block = to;
+        frame.pc = 0;
+    }
+
+    fn fall_through(&mut self) -> Result<(), InterpreterError> {
+        let block = self.frame().block;
+        match self.blocks.get(block).get_next().0 {
+            Some(next) => {
+                self.enter_block(block, next);
+                Ok(())
+            }
+            None => Err(InterpreterError::FellOffBlock { block }),
+        }
+    }
+
+    fn branch(
+        &mut self,
+        inst: usize,
+        cmp: usize,
+        block: Option<usize>,
+        taken: fn(i32) -> bool,
+    ) -> Result<(), InterpreterError> {
+        let val = self.operand(inst, (cmp, None))?;
+        if taken(val) {
+            let from = self.frame().block;
+            let to = block
+                .or(self.blocks.get(from).get_next().1)
+                .expect("Branch target should exist");
+            self.enter_block(from, to);
+            Ok(())
+        } else {
+            self.fall_through()
+        }
+    }
+
+    fn arithmetic(
+        &mut self,
+        inst: usize,
+        inst1: (usize, Option<usize>),
+        inst2: (usize, Option<usize>),
+        op: fn(i32, i32) -> Option<i32>,
+    ) -> Result<(), InterpreterError> {
+        let x = self.operand(inst, inst1)?;
+        let y = self.operand(inst, inst2)?;
+        let val = op(x, y).ok_or(InterpreterError::DivideByZero { inst })?;
+        self.set(inst, Some(val));
+        Ok(())
+    }
+
+    pub fn run<R: BufRead, W: Write>(
+        &mut self,
+        mut input: R,
+        mut output: W,
+    ) -> Result<InterpreterStats, InterpreterError> {
+        self.stack = vec![self.new_frame(1, Vec::new(), None)];
+        self.globals.clear();
+
+        loop {
+            let Frame { block, pc, .. } = *self.frame();
+            let Some(&inst) = self.blocks.get(block).insts.get(pc) else {
+                self.fall_through()?;
+                continue;
+            };
+            self.frame_mut().pc += 1;
+
+            let itype = self.insts.get(inst).itype();
+            if matches!(itype, IType::Phi { .. } | IType::Empty) {
+                continue;
+            }
+
+            if self
+                .step_limit
+                .is_some_and(|limit| self.stats.insts >= limit)
+            {
+                return Err(InterpreterError::StepLimitExceeded);
+            }
+            self.stats.insts += 1;
+
+            match itype {
+                IType::Const(_) => {}
+                IType::Add { inst1, inst2 } => {
+                    self.arithmetic(inst, inst1, inst2, |x, y| Some(x.wrapping_add(y)))?
+                }
+                IType::Sub { inst1, inst2 } => {
+                    self.arithmetic(inst, inst1, inst2, |x, y| Some(x.wrapping_sub(y)))?
+                }
+                IType::Mul { inst1, inst2 } => {
+                    self.arithmetic(inst, inst1, inst2, |x, y| Some(x.wrapping_mul(y)))?
+                }
+                IType::Div { inst1, inst2 } => self.arithmetic(inst, inst1, inst2, |x, y| {
+                    (y != 0).then(|| x.wrapping_div(y))
+                })?,
+                IType::Cmp { inst1, inst2 } => self.arithmetic(inst, inst1, inst2, |x, y| {
+                    Some((x as i64 - y as i64).signum() as i32)
+                })?,
+
+                IType::End => {
+                    output.flush().map_err(Self::io_error)?;
+                    return Ok(self.stats);
+                }
+                IType::Bra { block: to } => self.enter_block(block, to),
+                IType::Beq { inst: cmp, block } => self.branch(inst, cmp, block, |c| c == 0)?,
+                IType::Bne { inst: cmp, block } => self.branch(inst, cmp, block, |c| c != 0)?,
+                IType::Ble { inst: cmp, block } => self.branch(inst, cmp, block, |c| c <= 0)?,
+                IType::Blt { inst: cmp, block } => self.branch(inst, cmp, block, |c| c < 0)?,
+                IType::Bge { inst: cmp, block } => self.branch(inst, cmp, block, |c| c >= 0)?,
+                IType::Bgt { inst: cmp, block } => self.branch(inst, cmp, block, |c| c > 0)?,
+
+                IType::Jsr { block } => {
+                    self.stats.calls += 1;
+                    let args = std::mem::take(&mut self.pending_args);
+                    let frame = self.new_frame(block, args, Some(inst));
+                    self.stack.push(frame);
+                }
+                IType::Ret { inst: ret } => {
+                    let val = ret.and_then(|ret| self.value(ret.0));
+                    let frame = self.stack.pop().expect("Frame should exist");
+                    match frame.ret_inst {
+                        Some(jsr) => self.set(jsr, val),
+                        // Returning from main ends the program
+                        None => {
+                            output.flush().map_err(Self::io_error)?;
+                            return Ok(self.stats);
+                        }
+                    }
+                }
+                IType::GetPar { arg } => self.get_par(inst, arg)?,
+                IType::SetPar { arg, inst } => self.set_par(arg, inst.0),
+                IType::Load { var } => {
+                    let val = self.globals.get(&var).copied().flatten();
+                    self.set(inst, val);
+                }
+                IType::Store { var, inst } => {
+                    self.globals.insert(var, self.value(inst.0));
+                }
+
+                IType::Read => {
+                    let val = Self::read_num(&mut input)?;
+                    self.set(inst, Some(val));
+                }
+                IType::Write { inst: val } => {
+                    let val = self.operand(inst, val)?;
+                    write!(output, "{} ", val).map_err(Self::io_error)?;
+                }
+                IType::WriteNL => writeln!(output).map_err(Self::io_error)?,
+
+                IType::Phi { .. } | IType::Empty => unreachable!(),
+            }
+        }
+    }
+
+    fn get_par(&mut self, inst: usize, argnum: usize) -> Result<(), InterpreterError> {
+        let val = *self
+            .frame()
+            .args
+            .get(argnum)
+            .ok_or(InterpreterError::MissingArgument { inst, argnum })?;
+        self.set(inst, val);
+        Ok(())
+    }
+
+    fn set_par(&mut self, argnum: usize, inst: usize) {
+        if self.pending_args.len() <= argnum {
+            self.pending_args.resize(argnum + 1, None);
+        }
+        self.pending_args[argnum] = self.value(inst);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compile, CompileOptions};
+
+    use super::*;
+
+    fn run(input: &[u8], stdin: &[u8]) -> Result<String, InterpreterError> {
+        let ir = compile(input, &CompileOptions::default()).expect("Should compile");
+
+        let mut output = Vec::new();
+        ir.interpreter()
+            .step_limit(10_000_000)
+            .run(stdin, &mut output)?;
+
+        Ok(String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn nested_while_if() {
+        let input = b"
+main
+var i;
+{
+    let i <- 0;
+    while i < 10 do
+        let i <- i + 1;
+        if i == 2 then
+            let i <- i + 1
+        fi
+    od;
+    call OutputNum(i)
+}
+.
+";
+        assert_eq!(run(input, b""), Ok("10 ".to_string()));
+    }
+
+    #[test]
+    fn complex_if() {
+        let input = b"
+main
+var a, b, sum, i, j;
+{
+    let a <- call InputNum();
+    let b <- call InputNum();
+    let sum <- 0;
+    let i <- 1;
+    while i <= a do
+        let j <- 1;
+        while j <= b do
+            if i * j == 3 then
+                let sum <- sum + i * j;
+            fi;
+            if i * j == 6 then
+                let sum <- sum + i * j;
+            fi;
+            let j <- j + 1;
+        od;
+        let i <- i + 1;
+    od;
+    call OutputNum(sum);
+}
+.
+";
+        // 1*3, 3*1, 2*3, 3*2
+        assert_eq!(run(input, b"3 3"), Ok("18 ".to_string()));
+    }
+
+    #[test]
+    fn func_gcd() {
+        let input = b"
+main
+function mod(x,y); {
+    if y == 0 then
+        return x;
+    fi;
+    while x >= y do
+        let x <- x - y;
+    od;
+    return x;
+};
+function gcd(x,y); {
+    if x == 0 then
+        return y;
+    fi;
+    return call gcd(y, call mod(x,y));
+};
+{
+    call OutputNum(call gcd(call InputNum, call InputNum));
+    call OutputNewLine();
+}
+.
+";
+        assert_eq!(run(input, b"110 121"), Ok("11 \n".to_string()));
+    }
+
+    #[test]
+    fn uninitialized_read() {
+        let input = b"
+main
+var x, y;
+{
+    let y <- call InputNum();
+    if y > 0 then
+        let x <- 1
+    fi;
+    if y > 0 then
+        call OutputNum(x)
+    fi;
+    call OutputNum(x + 1)
+}
+.
+";
+        let ir = compile(input, &CompileOptions::default()).expect("Should compile");
+        let res = ir.interpreter().run(&b"0"[..], std::io::sink());
+
+        assert!(matches!(
+            res,
+            Err(InterpreterError::UninitializedRead { var: Some(var), .. }) if ir.get_var(var) == "x"
+        ));
+        assert_eq!(run(input, b"1"), Ok("1 2 ".to_string()));
+    }
+
+    #[test]
+    fn divide_by_zero() {
+        let input = b"
+main
+var x;
+{
+    let x <- call InputNum();
+    call OutputNum(10 / x)
+}
+.
+";
+        assert!(matches!(
+            run(input, b"0"),
+            Err(InterpreterError::DivideByZero { .. })
+        ));
+        assert_eq!(run(input, b"3"), Ok("3 ".to_string()));
+    }
+}
@@ -0,0 +1,759 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    diagnostics::{DiagnosticKind, Diagnostics},
+    module::Module,
+    parser_data::{BlockList, IType, Inst, InstList},
+    tokenizer_data::{Span, PREDEFINED_IDENTS},
+};
+
+// Reads the textual IR written by `Module::instructions`:
+//
+//   const:
+//   bb0:
+//     1: const #2
+//
+//   main:
+//   bb1: dom bb0 -> bb2, bb3
+//     0: read
+//     ...
+//
+// Block headers list the dominator, the predecessors in phi operand order and
+// the fall-through and branch successors. Removed instruction ids may be
+// skipped.
+pub fn parse_ir(text: &str) -> Result<Module, Diagnostics> {
+    let mut parser = IrParser::new();
+    for (idx, line) in text.lines().enumerate() {
+        parser.line(idx + 1, line)?;
+    }
+    parser.finish()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Word<'a> {
+    text: &'a str,
+    span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Const,
+    Main,
+    Function(usize),
+}
+
+#[derive(Debug)]
+struct BlockHeader {
+    id: usize,
+    dom: Option<usize>,
+    preds: Vec<usize>,
+    next: (Option<usize>, Option<usize>),
+    insts: Vec<usize>,
+    span: Span,
+}
+
+struct IrParser {
+    idents: Vec<String>,
+    section: Option<Section>,
+    // Whether the current section already has its entry block
+    has_entry: bool,
+    blocks: Vec<BlockHeader>,
+    insts: BTreeMap<usize, (IType, Span)>,
+    func_map: BTreeMap<usize, usize>,
+    undef_map: BTreeMap<usize, usize>,
+    main_block: Option<usize>,
+}
+
+fn error(kind: DiagnosticKind, span: Span, message: &str) -> Diagnostics {
+    let mut diagnostics = Diagnostics::new();
+    diagnostics.push(kind, message, Some(span));
+    diagnostics
+}
+
+fn syntax_error(span: Span, message: &str) -> Diagnostics {
+    error(DiagnosticKind::Syntax, span, message)
+}
+
+// Splits on whitespace and commas, keeping the column of every word
+fn words(line: usize, text: &str) -> Vec<Word<'_>> {
+    let text = match text.find("//") {
+        Some(idx) => &text[..idx],
+        None => text,
+    };
+
+    let mut words = Vec::new();
+    let mut start = None;
+    for (idx, c) in text.char_indices().chain([(text.len(), ' ')]) {
+        if c.is_whitespace() || c == ',' {
+            if let Some(start) = start.take() {
+                words.push(Word {
+                    text: &text[start..idx],
+                    span: Span {
+                        line,
+                        col: start + 1,
+                    },
+                });
+            }
+        } else if start.is_none() {
+            start = Some(idx);
+        }
+    }
+    words
+}
+
+impl IrParser {
+    fn new() -> IrParser {
+        IrParser {
+            idents: PREDEFINED_IDENTS.iter().map(|s| s.to_string()).collect(),
+            section: None,
+            has_entry: false,
+            blocks: Vec::new(),
+            insts: BTreeMap::new(),
+            func_map: BTreeMap::new(),
+            undef_map: BTreeMap::new(),
+            main_block: None,
+        }
+    }
+
+    fn intern(&mut self, name: &str) -> usize {
+        match self.idents.iter().position(|ident| ident == name) {
+            Some(id) => id,
+            None => {
+                self.idents.push(name.to_string());
+                self.idents.len() - 1
+            }
+        }
+    }
+
+    fn line(&mut self, line: usize, text: &str) -> Result<(), Diagnostics> {
+        let words = words(line, text);
+        let Some(first) = words.first() else {
+            return Ok(());
+        };
+
+        match first.text {
+            "const:" => self.section(Section::Const, &words[1..]),
+            "main:" => self.section(Section::Main, &words[1..]),
+            "function" => {
+                let name = words
+                    .get(1)
+                    .and_then(|word| word.text.strip_suffix(':'))
+                    .ok_or(syntax_error(first.span, "expected `function <name>:`"))?;
+                let func = self.intern(name);
+                self.section(Section::Function(func), &words[2..])
+            }
+            text if text.starts_with("bb") && text.ends_with(':') => self.block_header(&words),
+            text if text.ends_with(':') => self.inst(&words),
+            text => Err(syntax_error(first.span, &format!("unexpected `{}`", text))),
+        }
+    }
+
+    fn section(&mut self, section: Section, rest: &[Word]) -> Result<(), Diagnostics> {
+        expect_end(rest)?;
+        self.section = Some(section);
+        self.has_entry = false;
+        Ok(())
+    }
+
+    fn block_header(&mut self, words: &[Word]) -> Result<(), Diagnostics> {
+        let span = words[0].span;
+        let id = parse_block(Word {
+            text: words[0].text.trim_end_matches(':'),
+            span,
+        })?;
+
+        let mut dom = None;
+        let mut preds = Vec::new();
+        let mut next = (None, None);
+
+        let mut rest = words[1..].iter().peekable();
+        while let Some(word) = rest.next() {
+            match word.text {
+                "dom" => dom = Some(parse_block(*rest.next().ok_or(end_error(*word))?)?),
+                "preds" => {
+                    while let Some(pred) = rest.next_if(|word| word.text.starts_with("bb")) {
+                        preds.push(parse_block(*pred)?);
+                    }
+                }
+                "->" => {
+                    let fall_through = rest.next().ok_or(end_error(*word))?;
+                    if fall_through.text != "_" {
+                        next.0 = Some(parse_block(*fall_through)?);
+                    }
+                    if let Some(follow) = rest.next() {
+                        next.1 = Some(parse_block(*follow)?);
+                    }
+                }
+                text => return Err(syntax_error(word.span, &format!("unexpected `{}`", text))),
+            }
+        }
+
+        match self.section {
+            None => return Err(syntax_error(span, "block outside of a section")),
+            Some(Section::Const) if id != 0 => {
+                return Err(syntax_error(span, "the const section only holds bb0"))
+            }
+            Some(Section::Main) if !self.has_entry => self.main_block = Some(id),
+            Some(Section::Function(func)) if !self.has_entry => {
+                self.func_map.insert(func, id);
+            }
+            _ => {}
+        }
+        self.has_entry = true;
+
+        self.blocks.push(BlockHeader {
+            id,
+            dom,
+            preds,
+            next,
+            insts: Vec::new(),
+            span,
+        });
+        Ok(())
+    }
+
+    fn inst(&mut self, words: &[Word]) -> Result<(), Diagnostics> {
+        let span = words[0].span;
+        let id = words[0]
+            .text
+            .trim_end_matches(':')
+            .parse::<usize>()
+            .map_err(|_| syntax_error(span, "expected an instruction id"))?;
+        let op = *words.get(1).ok_or(end_error(words[0]))?;
+        let args = &words[2..];
+
+        let itype = match op.text {
+            "const" => {
+                let [val] = args else {
+                    return Err(syntax_error(op.span, "expected `const #<value>`"));
+                };
+                let val = val
+                    .text
+                    .strip_prefix('#')
+                    .and_then(|val| val.parse::<isize>().ok())
+                    .ok_or(syntax_error(val.span, "expected `#<value>`"))?;
+                IType::Const(val)
+            }
+            "undef" => {
+                let [var] = args else {
+                    return Err(syntax_error(op.span, "expected `undef <var>`"));
+                };
+                let var = self.intern(var.text);
+                self.undef_map.insert(var, id);
+                IType::Const(0)
+            }
+            "add" | "sub" | "mul" | "div" | "cmp" => {
+                let [inst1, inst2] = args else {
+                    return Err(syntax_error(op.span, "expected two operands"));
+                };
+                let inst1 = (parse_inst(*inst1)?, None);
+                let inst2 = (parse_inst(*inst2)?, None);
+                match op.text {
+                    "add" => IType::Add { inst1, inst2 },
+                    "sub" => IType::Sub { inst1, inst2 },
+                    "mul" => IType::Mul { inst1, inst2 },
+                    "div" => IType::Div { inst1, inst2 },
+                    _ => IType::Cmp { inst1, inst2 },
+                }
+            }
+            text if text.starts_with('(') && text.ends_with(')') => {
+                let [phi, insts @ ..] = args else {
+                    return Err(syntax_error(
+                        op.span,
+                        "expected `(<var>) phi (<a>) (<b>) ...`",
+                    ));
+                };
+                if phi.text != "phi" {
+                    return Err(syntax_error(phi.span, "expected `phi`"));
+                }
+                if insts.is_empty() {
+                    return Err(syntax_error(
+                        phi.span,
+                        "expected an operand per predecessor",
+                    ));
+                }
+                let var = self.intern(&text[1..text.len() - 1]);
+                IType::Phi {
+                    insts: insts
+                        .iter()
+                        .map(|&inst| Ok((parse_inst(inst)?, Some(var))))
+                        .collect::<Result<_, Diagnostics>>()?,
+                    var,
+                }
+            }
+            "bra" | "jsr" => {
+                let [block] = args else {
+                    return Err(syntax_error(op.span, "expected a target block"));
+                };
+                let block = parse_block(*block)?;
+                match op.text {
+                    "bra" => IType::Bra { block },
+                    _ => IType::Jsr { block },
+                }
+            }
+            "bne" | "beq" | "ble" | "blt" | "bge" | "bgt" => {
+                let (inst, block) = match args {
+                    [inst] => (parse_inst(*inst)?, None),
+                    [inst, block] => (parse_inst(*inst)?, Some(parse_block(*block)?)),
+                    _ => return Err(syntax_error(op.span, "expected `(<cmp>) [bb<id>]`")),
+                };
+                match op.text {
+                    "bne" => IType::Bne { inst, block },
+                    "beq" => IType::Beq { inst, block },
+                    "ble" => IType::Ble { inst, block },
+                    "blt" => IType::Blt { inst, block },
+                    "bge" => IType::Bge { inst, block },
+                    _ => IType::Bgt { inst, block },
+                }
+            }
+            "ret" => match args {
+                [] => IType::Ret { inst: None },
+                [inst] => IType::Ret {
+                    inst: Some((parse_inst(*inst)?, None)),
+                },
+                _ => return Err(syntax_error(op.span, "expected `ret [(<value>)]`")),
+            },
+            text if text.starts_with("setpar") || text == "write" => {
+                let [inst] = args else {
+                    return Err(syntax_error(op.span, "expected one operand"));
+                };
+                let inst = (parse_inst(*inst)?, None);
+                match parse_par(op, "setpar")? {
+                    Some(arg) => IType::SetPar { arg, inst },
+                    None => IType::Write { inst },
+                }
+            }
+            text if text.starts_with("getpar") => {
+                expect_end(args)?;
+                IType::GetPar {
+                    arg: parse_par(op, "getpar")?.expect("Should start with getpar"),
+                }
+            }
+            "load" => {
+                let [var] = args else {
+                    return Err(syntax_error(op.span, "expected `load <var>`"));
+                };
+                IType::Load {
+                    var: self.intern(var.text),
+                }
+            }
+            "store" => {
+                let [var, inst] = args else {
+                    return Err(syntax_error(op.span, "expected `store <var> (<value>)`"));
+                };
+                IType::Store {
+                    var: self.intern(var.text),
+                    inst: (parse_inst(*inst)?, None),
+                }
+            }
+            "end" | "read" | "writeNL" | "empty" => {
+                expect_end(args)?;
+                match op.text {
+                    "end" => IType::End,
+                    "read" => IType::Read,
+                    "writeNL" => IType::WriteNL,
+                    _ => IType::Empty,
+                }
+            }
+            text => {
+                return Err(syntax_error(
+                    op.span,
+                    &format!("unknown instruction `{}`", text),
+                ))
+            }
+        };
+
+        let block = self
+            .blocks
+            .last_mut()
+            .ok_or(syntax_error(span, "instruction outside of a block"))?;
+        if self.insts.insert(id, (itype, span)).is_some() {
+            return Err(error(
+                DiagnosticKind::Semantic,
+                span,
+                &format!("instruction {} is defined twice", id),
+            ));
+        }
+        block.insts.push(id);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Module, Diagnostics> {
+        let semantic_error =
+            |span, message: String| error(DiagnosticKind::Semantic, span, &message);
+
+        self.blocks.sort_by_key(|block| block.id);
+        for (idx, block) in self.blocks.iter().enumerate() {
+            if block.id != idx {
+                return Err(semantic_error(
+                    block.span,
+                    format!(
+                        "expected bb{}, blocks are numbered from bb0 without gaps",
+                        idx
+                    ),
+                ));
+            }
+            if block.dom.is_some_and(|dom| dom >= block.id) {
+                return Err(semantic_error(
+                    block.span,
+                    "a block's dominator must have a smaller id".to_string(),
+                ));
+            }
+            let refs = block
+                .preds
+                .iter()
+                .chain(block.next.0.iter())
+                .chain(block.next.1.iter());
+            if let Some(target) = refs.copied().find(|&target| target >= self.blocks.len()) {
+                return Err(semantic_error(
+                    block.span,
+                    format!("unknown block bb{}", target),
+                ));
+            }
+        }
+        if self.main_block != Some(1) {
+            let mut diagnostics = Diagnostics::new();
+            diagnostics.push(DiagnosticKind::Semantic, "main must start at bb1", None);
+            return Err(diagnostics);
+        }
+
+        for (&id, (itype, span)) in self.insts.iter() {
+            let span = *span;
+            if let Some(operand) = itype
+                .operands()
+                .into_iter()
+                .find(|op| !self.insts.contains_key(op))
+            {
+                return Err(semantic_error(
+                    span,
+                    format!("instruction {} reads undefined instruction {}", id, operand),
+                ));
+            }
+            let target = match *itype {
+                IType::Bra { block } | IType::Jsr { block } => Some(block),
+                IType::Bne { block, .. }
+                | IType::Beq { block, .. }
+                | IType::Ble { block, .. }
+                | IType::Blt { block, .. }
+                | IType::Bge { block, .. }
+                | IType::Bgt { block, .. } => block,
+                _ => None,
+            };
+            if let Some(target) = target.filter(|&target| target >= self.blocks.len()) {
+                return Err(semantic_error(span, format!("unknown block bb{}", target)));
+            }
+        }
+
+        // Skipped ids stay as unplaced placeholders, like instructions removed by a pass
+        let len = self.insts.keys().last().map_or(0, |&id| id + 1);
+        let mut insts = InstList::new();
+        insts.instructions = (0..len)
+            .map(|id| Inst::new(id, IType::Empty, None, None))
+            .collect();
+        insts.inst_count = len;
+
+        // Dominators come first, so their op chains are complete when copied
+        let mut blocks = BlockList::new();
+        for header in self.blocks.iter() {
+            let block = match header.dom {
+                Some(dom) => blocks.add_from(dom),
+                None => blocks.add(),
+            };
+
+            for &inst in header.insts.iter() {
+                let (itype, span) = &self.insts[&inst];
+                if let IType::Phi { insts, .. } = itype {
+                    if insts.len() != header.preds.len() {
+                        return Err(semantic_error(
+                            *span,
+                            format!(
+                                "phi {} has {} operands for {} predecessors",
+                                inst,
+                                insts.len(),
+                                header.preds.len()
+                            ),
+                        ));
+                    }
+                }
+                let dom = blocks.get(block).get_dom_inst(itype);
+                insts.instructions[inst] = Inst::new(inst, itype.clone(), dom, Some(block));
+                blocks.get_mut(block).add_inst(inst, itype);
+            }
+            for &pred in header.preds.iter() {
+                blocks.get_mut(block).add_pred(pred);
+            }
+            if let Some(fall_through) = header.next.0 {
+                blocks.get_mut(block).set_fall_through(fall_through);
+            }
+            if let Some(follow) = header.next.1 {
+                blocks.get_mut(block).set_follow(follow);
+            }
+            blocks.get_mut(block).seal();
+        }
+
+        let mut module = Module::new(self.idents, self.func_map, self.undef_map, blocks, insts);
+        module.fill_empty();
+        Ok(module)
+    }
+}
+
+fn end_error(word: Word) -> Diagnostics {
+    syntax_error(
+        word.span,
+        &format!("unexpected end of line after `{}`", word.text),
+    )
+}
+
+fn expect_end(rest: &[Word]) -> Result<(), Diagnostics> {
+    match rest.first() {
+        Some(word) => Err(syntax_error(
+            word.span,
+            &format!("unexpected `{}`", word.text),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn parse_block(word: Word) -> Result<usize, Diagnostics> {
+    word.text
+        .strip_prefix("bb")
+        .and_then(|id| id.parse().ok())
+        .ok_or(syntax_error(
+            word.span,
+            &format!("expected a block, found `{}`", word.text),
+        ))
+}
+
+// Argument number of `setpar2` and the like counted from 0, None for other
+// words
+fn parse_par(word: Word, prefix: &str) -> Result<Option<usize>, Diagnostics> {
+    let Some(num) = word.text.strip_prefix(prefix) else {
+        return Ok(None);
+    };
+    num.parse::<usize>()
+        .ok()
+        .filter(|&num| num > 0)
+        .map(|num| Some(num - 1))
+        .ok_or(syntax_error(
+            word.span,
+            &format!("unknown instruction `{}`", word.text),
+        ))
+}
+
+fn parse_inst(word: Word) -> Result<usize, Diagnostics> {
+    word.text
+        .strip_prefix('(')
+        .and_then(|id| id.strip_suffix(')'))
+        .and_then(|id| id.parse().ok())
+        .ok_or(syntax_error(
+            word.span,
+            &format!("expected an operand, found `{}`", word.text),
+        ))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        compile,
+        passes::{CommonSubexpressionElimination, Pass, PhiRemoval},
+        CompileOptions, Passes,
+    };
+
+    use super::*;
+
+    fn run(module: &Module, stdin: &[u8]) -> String {
+        let mut stdout = Vec::new();
+        module
+            .interpreter()
+            .step_limit(100_000)
+            .run(stdin, &mut stdout)
+            .expect("Should run");
+        String::from_utf8(stdout).expect("Should be utf8")
+    }
+
+    #[test]
+    fn round_trip() {
+        let programs: [&[u8]; 5] = [
+            b"main var a, b; { let a <- call InputNum(); let b <- 1;
+              while b < a do let b <- b * 2 od; call OutputNum(b) }.",
+            b"main var x; function fib(n); { if n < 2 then return n fi;
+              return call fib(n - 1) + call fib(n - 2) };
+              { let x <- call fib(call InputNum()); call OutputNum(x) }.",
+            b"main var a, b, c; { let a <- call InputNum();
+              if a > 0 then let c <- a * 2 else let c <- b fi; call OutputNum(c) }.",
+            b"main var n; void function inc(); { let n <- n + 1 };
+              { let n <- call InputNum(); call inc(); call OutputNum(n) }.",
+            b"main var a, b; { let a <- call InputNum(); let b <- 0;
+              if a < 3 then let b <- 1 elsif a < 6 then let b <- 2 elsif a < 9 then let b <- a
+              fi; call OutputNum(b) }.",
+        ];
+
+        for input in programs {
+            for level in 0..3 {
+                let options = CompileOptions {
+                    passes: Passes::level(level),
+                    ..CompileOptions::default()
+                };
+                let module = compile(input, &options).expect("Should compile");
+                let text = module.instructions();
+
+                let parsed = parse_ir(&text).expect("Should parse");
+                assert_eq!(parsed.instructions(), text);
+                assert_eq!(run(&parsed, b"6"), run(&module, b"6"));
+            }
+        }
+    }
+
+    #[test]
+    fn hand_written() {
+        let text = "
+const:
+bb0:
+  1: const #2
+
+main:
+bb1: dom bb0 -> bb2, bb3
+  0: read
+  2: mul (0) (1)
+  3: cmp (0) (1)
+  4: ble (3) bb3
+bb2: dom bb1 preds bb1 -> bb3
+  5: mul (0) (1) // same as 2
+  6: bra bb3
+bb3: dom bb1 preds bb2, bb1
+  7: (x) phi (5) (2)
+  8: write (7)
+  9: end
+";
+        let mut module = parse_ir(text).expect("Should parse");
+        assert_eq!(run(&module, b"5"), "10 ");
+
+        assert!(CommonSubexpressionElimination.run(&mut module));
+        assert_eq!(module.inst(5).block(), None);
+        assert!(matches!(
+            module.inst(7).itype(),
+            IType::Phi { insts, .. } if insts.iter().map(|inst| inst.0).eq([2, 2])
+        ));
+        assert!(module
+            .instructions()
+            .contains("bb2: dom bb1 preds bb1 -> bb3\n  6: bra bb3"));
+    }
+
+    #[test]
+    fn nary_phis() {
+        let text = "
+const:
+bb0:
+  1: const #1
+  2: const #2
+  3: const #3
+
+main:
+bb1: dom bb0 -> bb2, bb3
+  0: read
+  4: cmp (0) (1)
+  5: bne (4) bb3
+bb2: dom bb1 preds bb1 -> bb5
+  6: bra bb5
+bb3: dom bb1 preds bb1 -> bb4, bb5
+  7: cmp (0) (2)
+  8: beq (7) bb5
+bb4: dom bb3 preds bb3 -> bb5
+  9: mul (0) (3)
+bb5: dom bb1 preds bb2, bb3, bb4
+  10: (y) phi (2) (2) (2)
+  11: (x) phi (1) (0) (9)
+  12: write (11)
+  13: write (10)
+  14: end
+";
+        let mut module = parse_ir(text).expect("Should parse");
+        let printed = module.instructions();
+        assert!(printed.contains("  10: (y) phi (2) (2) (2)\n  11: (x) phi (1) (0) (9)"));
+        assert_eq!(
+            parse_ir(&printed).expect("Should parse").instructions(),
+            printed
+        );
+        assert!(module.graph().contains("11: (x) phi (1:x) (0:x) (9:x)"));
+        for (stdin, stdout) in [("1", "1 2 "), ("2", "2 2 "), ("5", "15 2 ")] {
+            assert_eq!(run(&module, stdin.as_bytes()), stdout);
+        }
+
+        // Only the phi whose operands all agree goes away
+        assert!(PhiRemoval.run(&mut module));
+        assert_eq!(module.inst(10).block(), None);
+        assert_eq!(module.inst(11).block(), Some(5));
+        assert!(module.instructions().contains("  13: write (2)"));
+        assert_eq!(run(&module, b"5"), "15 2 ");
+    }
+
+    #[test]
+    fn self_referencing_phis() {
+        let text = "
+const:
+bb0:
+  1: const #0
+  2: const #1
+
+main:
+bb1: dom bb0 -> bb2
+  0: read
+bb2: dom bb1 preds bb1, bb3 -> bb3, bb4
+  3: (x) phi (0) (3)
+  4: (i) phi (1) (7)
+  5: cmp (4) (3)
+  6: bge (5) bb4
+bb3: dom bb2 preds bb2 -> bb2
+  7: add (4) (2)
+  8: bra bb2
+bb4: dom bb2 preds bb2
+  9: write (4)
+  10: write (3)
+  11: end
+";
+        let mut module = parse_ir(text).expect("Should parse");
+        assert_eq!(run(&module, b"3"), "3 3 ");
+
+        // `x` only ever holds its value from before the loop
+        assert!(PhiRemoval.run(&mut module));
+        assert_eq!(module.inst(3).block(), None);
+        assert_eq!(module.inst(4).block(), Some(2));
+        assert!(module.instructions().contains("  5: cmp (4) (0)"));
+        assert_eq!(run(&module, b"3"), "3 3 ");
+    }
+
+    #[test]
+    fn errors() {
+        let err = |text: &str| {
+            let diagnostics = parse_ir(text).expect_err("Should fail");
+            let diagnostic = diagnostics.iter().next().expect("Should exist").clone();
+            (
+                diagnostic.kind,
+                diagnostic.span.map(|span| (span.line, span.col)),
+            )
+        };
+
+        assert_eq!(
+            err("const:\nbb0:\n  0: cosnt #1"),
+            (DiagnosticKind::Syntax, Some((3, 6)))
+        );
+        assert_eq!(
+            err("const:\nbb0:\nmain:\nbb1: dom bb0\n  0: write (4)\n  1: end"),
+            (DiagnosticKind::Semantic, Some((5, 3)))
+        );
+        assert_eq!(
+            err("const:\nbb0:\nmain:\nbb2: dom bb0\n  0: end"),
+            (DiagnosticKind::Semantic, Some((4, 1)))
+        );
+        assert_eq!(
+            err("const:\nbb0:\nmain:\nbb1: dom bb0\n  0: getpar0\n  1: end"),
+            (DiagnosticKind::Syntax, Some((5, 6)))
+        );
+        // One phi operand per predecessor
+        assert_eq!(
+            err("const:\nbb0:\nmain:\nbb1: dom bb0 -> bb2\n  0: read\n\
+                 bb2: dom bb1 preds bb1\n  1: (x) phi (0) (0)\n  2: end"),
+            (DiagnosticKind::Semantic, Some((7, 3)))
+        );
+    }
+}
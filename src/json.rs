@@ -0,0 +1,656 @@
+use std::fmt::Display;
+
+use crate::{
+    ast_data::{
+        Call, Computation, Cond, CondKind, Expr, ExprKind, FuncDecl, Relation, Stmt, StmtKind,
+        VarDecl,
+    },
+    diagnostics::{DiagnosticKind, Diagnostics},
+    module::Module,
+    parser_data::IType,
+    tokenizer::Tokenizer,
+    tokenizer_data::{Span, Token},
+};
+
+// Bumped whenever a field is renamed, removed or changes meaning. Adding
+// fields keeps the version
+pub const SCHEMA_VERSION: usize = 1;
+
+// Minimal JSON value, objects keep their insertion order so output is stable
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Num(isize),
+    Str(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields
+                .iter()
+                .find(|(field, _)| field == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // Two space indented, one field or element per line
+    pub fn pretty(&self) -> String {
+        let mut data = String::new();
+        self.write(&mut data, Some(0));
+        data
+    }
+
+    fn write(&self, data: &mut String, indent: Option<usize>) {
+        let (open, close, items): (char, char, Vec<(Option<&str>, &Json)>) = match self {
+            Json::Null => return *data += "null",
+            Json::Bool(val) => return *data += val.to_string().as_str(),
+            Json::Num(val) => return *data += val.to_string().as_str(),
+            Json::Str(val) => return write_str(data, val),
+            Json::Array(items) => ('[', ']', items.iter().map(|item| (None, item)).collect()),
+            Json::Object(fields) => (
+                '{',
+                '}',
+                fields
+                    .iter()
+                    .map(|(key, value)| (Some(key.as_str()), value))
+                    .collect(),
+            ),
+        };
+
+        data.push(open);
+        if items.is_empty() {
+            data.push(close);
+            return;
+        }
+
+        let inner = indent.map(|indent| indent + 1);
+        for (idx, (key, value)) in items.into_iter().enumerate() {
+            if idx > 0 {
+                data.push(',');
+            }
+            if let Some(inner) = inner {
+                data.push('\n');
+                *data += "  ".repeat(inner).as_str();
+            }
+            if let Some(key) = key {
+                write_str(data, key);
+                data.push(':');
+                if inner.is_some() {
+                    data.push(' ');
+                }
+            }
+            value.write(data, inner);
+        }
+        if let Some(indent) = indent {
+            data.push('\n');
+            *data += "  ".repeat(indent).as_str();
+        }
+        data.push(close);
+    }
+}
+
+fn write_str(data: &mut String, val: &str) {
+    data.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => *data += "\\\"",
+            '\\' => *data += "\\\\",
+            '\n' => *data += "\\n",
+            '\t' => *data += "\\t",
+            c if c.is_control() => *data += format!("\\u{:04x}", c as u32).as_str(),
+            c => data.push(c),
+        }
+    }
+    data.push('"');
+}
+
+// Compact, on a single line
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut data = String::new();
+        self.write(&mut data, None);
+        write!(f, "{}", data)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(val: usize) -> Json {
+        Json::Num(val as isize)
+    }
+}
+
+impl From<isize> for Json {
+    fn from(val: isize) -> Json {
+        Json::Num(val)
+    }
+}
+
+impl From<bool> for Json {
+    fn from(val: bool) -> Json {
+        Json::Bool(val)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(val: &str) -> Json {
+        Json::Str(val.to_string())
+    }
+}
+
+impl From<String> for Json {
+    fn from(val: String) -> Json {
+        Json::Str(val)
+    }
+}
+
+impl From<Span> for Json {
+    fn from(span: Span) -> Json {
+        Json::object(vec![("line", span.line.into()), ("col", span.col.into())])
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(val: Option<T>) -> Json {
+        val.map_or(Json::Null, Into::into)
+    }
+}
+
+impl<T: Into<Json>> From<Vec<T>> for Json {
+    fn from(val: Vec<T>) -> Json {
+        Json::Array(val.into_iter().map(Into::into).collect())
+    }
+}
+
+// Top level object shared by every export
+fn document(kind: &str, mut fields: Vec<(&str, Json)>) -> Json {
+    fields.insert(0, ("version", SCHEMA_VERSION.into()));
+    fields.insert(1, ("kind", kind.into()));
+    Json::object(fields)
+}
+
+pub fn tokens(source: &[u8]) -> Json {
+    let mut tokenizer = Tokenizer::new(source);
+    let mut tokens = Vec::new();
+
+    while let Some(token) = tokenizer.peek() {
+        let span = tokenizer.span().into();
+        tokens.push(match token {
+            Token::Ident(id) => Json::object(vec![
+                ("kind", "Ident".into()),
+                ("name", tokenizer.get_var(id).into()),
+                ("span", span),
+            ]),
+            Token::Number(num) => Json::object(vec![
+                ("kind", "Number".into()),
+                ("value", num.into()),
+                ("span", span),
+            ]),
+            Token::RelOp(op) => Json::object(vec![
+                ("kind", "RelOp".into()),
+                ("op", op.symbol().into()),
+                ("span", span),
+            ]),
+            token => Json::object(vec![
+                ("kind", format!("{:?}", token).into()),
+                ("span", span),
+            ]),
+        });
+        tokenizer.next();
+    }
+
+    document("tokens", vec![("tokens", Json::Array(tokens))])
+}
+
+pub fn ast(ast: &Computation, idents: &[String]) -> Json {
+    let writer = AstWriter { idents };
+    document(
+        "ast",
+        vec![
+            ("vars", writer.decls(&ast.vars)),
+            (
+                "funcs",
+                Json::Array(ast.funcs.iter().map(|func| writer.func(func)).collect()),
+            ),
+            ("body", writer.stmts(&ast.body)),
+        ],
+    )
+}
+
+struct AstWriter<'a> {
+    idents: &'a [String],
+}
+
+impl AstWriter<'_> {
+    fn name(&self, ident: usize) -> Json {
+        self.idents[ident].as_str().into()
+    }
+
+    fn decls(&self, decls: &[VarDecl]) -> Json {
+        Json::Array(
+            decls
+                .iter()
+                .map(|decl| {
+                    Json::object(vec![
+                        ("name", self.name(decl.var)),
+                        ("span", decl.span.into()),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    fn func(&self, func: &FuncDecl) -> Json {
+        Json::object(vec![
+            ("name", self.name(func.name)),
+            ("void", func.is_void.into()),
+            ("params", self.decls(&func.params)),
+            ("vars", self.decls(&func.vars)),
+            ("body", self.stmts(&func.body)),
+            ("span", func.span.into()),
+        ])
+    }
+
+    fn stmts(&self, stmts: &[Stmt]) -> Json {
+        Json::Array(stmts.iter().map(|stmt| self.stmt(stmt)).collect())
+    }
+
+    fn stmt(&self, stmt: &Stmt) -> Json {
+        let (kind, mut fields) = match &stmt.kind {
+            StmtKind::Assign { var, expr } => (
+                "Assign",
+                vec![("var", self.name(*var)), ("expr", self.expr(expr))],
+            ),
+            StmtKind::Call(call) => ("Call", vec![("call", self.call(call))]),
+            StmtKind::If {
+                cond,
+                then_body,
+                elsifs,
+                else_body,
+            } => (
+                "If",
+                vec![
+                    ("cond", self.cond(cond)),
+                    ("then", self.stmts(then_body)),
+                    (
+                        "elsif",
+                        Json::Array(
+                            elsifs
+                                .iter()
+                                .map(|(cond, body)| {
+                                    Json::object(vec![
+                                        ("cond", self.cond(cond)),
+                                        ("then", self.stmts(body)),
+                                    ])
+                                })
+                                .collect(),
+                        ),
+                    ),
+                    (
+                        "else",
+                        else_body
+                            .as_ref()
+                            .map_or(Json::Null, |body| self.stmts(body)),
+                    ),
+                ],
+            ),
+            StmtKind::While { cond, body } => (
+                "While",
+                vec![("cond", self.cond(cond)), ("body", self.stmts(body))],
+            ),
+            StmtKind::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => (
+                "For",
+                vec![
+                    ("var", self.name(*var)),
+                    ("from", self.expr(from)),
+                    ("to", self.expr(to)),
+                    (
+                        "step",
+                        step.as_ref().map_or(Json::Null, |step| self.expr(step)),
+                    ),
+                    ("body", self.stmts(body)),
+                ],
+            ),
+            StmtKind::Repeat { body, cond } => (
+                "Repeat",
+                vec![("body", self.stmts(body)), ("cond", self.cond(cond))],
+            ),
+            StmtKind::Break => ("Break", vec![]),
+            StmtKind::Continue => ("Continue", vec![]),
+            StmtKind::Return(expr) => (
+                "Return",
+                vec![(
+                    "expr",
+                    expr.as_ref().map_or(Json::Null, |expr| self.expr(expr)),
+                )],
+            ),
+        };
+        fields.insert(0, ("kind", kind.into()));
+        fields.push(("span", stmt.span.into()));
+        Json::object(fields)
+    }
+
+    fn call(&self, call: &Call) -> Json {
+        Json::object(vec![
+            ("func", self.name(call.func)),
+            (
+                "args",
+                Json::Array(call.args.iter().map(|arg| self.expr(arg)).collect()),
+            ),
+            ("span", call.span.into()),
+        ])
+    }
+
+    // Relations keep their own shape, `not`, `and` and `or` wrap them
+    fn cond(&self, cond: &Cond) -> Json {
+        match &cond.kind {
+            CondKind::Relation(relation) => self.relation(relation),
+            CondKind::Not(inner) => Json::object(vec![
+                ("op", "not".into()),
+                ("cond", self.cond(inner)),
+                ("span", cond.span.into()),
+            ]),
+            CondKind::Logical { op, lhs, rhs } => Json::object(vec![
+                ("op", op.keyword().into()),
+                ("lhs", self.cond(lhs)),
+                ("rhs", self.cond(rhs)),
+                ("span", cond.span.into()),
+            ]),
+        }
+    }
+
+    fn relation(&self, relation: &Relation) -> Json {
+        Json::object(vec![
+            ("op", relation.op.symbol().into()),
+            ("lhs", self.expr(&relation.lhs)),
+            ("rhs", self.expr(&relation.rhs)),
+            ("span", relation.span.into()),
+        ])
+    }
+
+    fn expr(&self, expr: &Expr) -> Json {
+        let (kind, mut fields) = match &expr.kind {
+            ExprKind::Num(num) => ("Num", vec![("value", (*num).into())]),
+            ExprKind::Var(var) => ("Var", vec![("name", self.name(*var))]),
+            ExprKind::Binary { op, lhs, rhs } => (
+                "Binary",
+                vec![
+                    ("op", op.symbol().into()),
+                    ("lhs", self.expr(lhs)),
+                    ("rhs", self.expr(rhs)),
+                ],
+            ),
+            ExprKind::Call(call) => ("Call", vec![("call", self.call(call))]),
+        };
+        fields.insert(0, ("kind", kind.into()));
+        fields.push(("span", expr.span.into()));
+        Json::object(fields)
+    }
+}
+
+pub fn module(module: &Module) -> Json {
+    let mut functions = vec![("main", module.main_block())];
+    functions.extend(module.functions());
+    let function = |block: usize| {
+        functions
+            .iter()
+            .find(|(_, entry)| *entry == module.owner(block))
+            .map(|(name, _)| *name)
+    };
+
+    let blocks = module
+        .blocks()
+        .iter()
+        .map(|block| {
+            Json::object(vec![
+                ("id", block.id().into()),
+                // null for the constant block
+                ("function", function(block.id()).into()),
+                ("dom", block.dom().into()),
+                ("preds", block.get_preds().to_vec().into()),
+                ("succs", block.get_succs(module.insts()).into()),
+                (
+                    "insts",
+                    Json::Array(block.iter().map(|&inst| inst_json(module, inst)).collect()),
+                ),
+            ])
+        })
+        .collect();
+
+    document(
+        "module",
+        vec![
+            (
+                "functions",
+                Json::Array(
+                    functions
+                        .iter()
+                        .map(|&(name, entry)| {
+                            Json::object(vec![("name", name.into()), ("entry", entry.into())])
+                        })
+                        .collect(),
+                ),
+            ),
+            ("blocks", Json::Array(blocks)),
+        ],
+    )
+}
+
+fn inst_json(module: &Module, inst: usize) -> Json {
+    let itype = module.inst(inst).itype();
+    let var = |var: Option<usize>| var.map(|var| module.get_var(var));
+
+    let (operands, target) = match itype {
+        IType::Add { inst1, inst2 }
+        | IType::Sub { inst1, inst2 }
+        | IType::Mul { inst1, inst2 }
+        | IType::Div { inst1, inst2 }
+        | IType::Cmp { inst1, inst2 } => (vec![inst1, inst2], None),
+        IType::Phi { ref insts, .. } => (insts.clone(), None),
+        IType::Bne { inst, block }
+        | IType::Beq { inst, block }
+        | IType::Ble { inst, block }
+        | IType::Blt { inst, block }
+        | IType::Bge { inst, block }
+        | IType::Bgt { inst, block } => (vec![(inst, None)], block),
+        IType::Bra { block } | IType::Jsr { block } => (vec![], Some(block)),
+        IType::Ret { inst } => (inst.into_iter().collect(), None),
+        IType::SetPar { inst, .. } | IType::Store { inst, .. } | IType::Write { inst } => {
+            (vec![inst], None)
+        }
+        _ => (vec![], None),
+    };
+
+    let mut fields = vec![
+        ("id", inst.into()),
+        ("op", itype.mnemonic().into()),
+        (
+            "operands",
+            Json::Array(
+                operands
+                    .into_iter()
+                    .map(|(inst, annotation)| {
+                        Json::object(vec![("inst", inst.into()), ("var", var(annotation).into())])
+                    })
+                    .collect(),
+            ),
+        ),
+    ];
+    match itype {
+        IType::Const(val) => {
+            fields.push(("value", val.into()));
+            // Placeholder for reads of an uninitialized variable
+            fields.push(("undef", module.undef_var(inst).into()));
+        }
+        IType::Phi { var: ident, .. }
+        | IType::Load { var: ident }
+        | IType::Store { var: ident, .. } => fields.push(("var", var(Some(ident)).into())),
+        _ => {}
+    }
+    if target.is_some() {
+        fields.push(("target", target.into()));
+    }
+    fields.push(("span", module.span(inst).into()));
+    Json::object(fields)
+}
+
+pub fn diagnostics(diagnostics: &Diagnostics) -> Json {
+    let diagnostics = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            let kind = match diagnostic.kind {
+                DiagnosticKind::Syntax => "Syntax",
+                DiagnosticKind::Semantic => "Semantic",
+                DiagnosticKind::Warning => "Warning",
+            };
+            Json::object(vec![
+                ("kind", kind.into()),
+                ("message", diagnostic.message.as_str().into()),
+                ("span", diagnostic.span.into()),
+            ])
+        })
+        .collect();
+
+    document(
+        "diagnostics",
+        vec![("diagnostics", Json::Array(diagnostics))],
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{compile, CompileOptions, Parser};
+
+    use super::*;
+
+    #[test]
+    fn format() {
+        let json = Json::object(vec![
+            ("a", vec![1_usize, 2].into()),
+            ("b", "x\"\n".into()),
+            ("c", Json::Array(vec![])),
+            ("d", Option::<usize>::None.into()),
+        ]);
+
+        assert_eq!(
+            json.to_string(),
+            r#"{"a":[1,2],"b":"x\"\n","c":[],"d":null}"#
+        );
+        assert_eq!(
+            json.pretty(),
+            "{\n  \"a\": [\n    1,\n    2\n  ],\n  \"b\": \"x\\\"\\n\",\n  \"c\": [],\n  \"d\": null\n}"
+        );
+    }
+
+    #[test]
+    fn token_stream() {
+        assert_eq!(
+            tokens(b"let x <- 1").to_string(),
+            concat!(
+                r#"{"version":1,"kind":"tokens","tokens":["#,
+                r#"{"kind":"Let","span":{"line":1,"col":1}},"#,
+                r#"{"kind":"Ident","name":"x","span":{"line":1,"col":5}},"#,
+                r#"{"kind":"Assignment","span":{"line":1,"col":7}},"#,
+                r#"{"kind":"Number","value":1,"span":{"line":1,"col":10}}]}"#
+            )
+        );
+    }
+
+    #[test]
+    fn ast_nodes() {
+        let mut parser = Parser::new(&b"main var x; { let x <- 1 + x }."[..]);
+        let computation = parser.computation().expect("Should parse");
+        let json = ast(&computation, parser.idents());
+
+        assert_eq!(
+            json.get("body").map(Json::to_string),
+            Some(
+                concat!(
+                    r#"[{"kind":"Assign","var":"x","expr":{"kind":"Binary","op":"+","#,
+                    r#""lhs":{"kind":"Num","value":1,"span":{"line":1,"col":24}},"#,
+                    r#""rhs":{"kind":"Var","name":"x","span":{"line":1,"col":28}},"#,
+                    r#""span":{"line":1,"col":24}},"span":{"line":1,"col":15}}]"#
+                )
+                .to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn module_insts() {
+        let module = compile(
+            b"main var x, y;\nfunction f(a); { return a + y };\n{ let x <- call InputNum();\n  call OutputNum(call f(x)) }.",
+            &CompileOptions::default(),
+        )
+        .expect("Should compile");
+        let json = super::module(&module);
+
+        assert_eq!(
+            json.get("functions").map(Json::to_string),
+            Some(r#"[{"name":"main","entry":1},{"name":"f","entry":2}]"#.to_string())
+        );
+
+        let Some(Json::Array(blocks)) = json.get("blocks") else {
+            panic!("Should have blocks");
+        };
+        let insts = blocks
+            .iter()
+            .flat_map(|block| match block.get("insts") {
+                Some(Json::Array(insts)) => insts.clone(),
+                _ => vec![],
+            })
+            .map(|inst| inst.to_string())
+            .collect::<Vec<_>>();
+
+        assert!(insts.contains(
+            &r#"{"id":6,"op":"const","operands":[],"value":0,"undef":"y","span":null}"#.to_string()
+        ));
+        assert!(insts.contains(
+            &r#"{"id":1,"op":"load","operands":[],"var":"y","span":{"line":2,"col":1}}"#
+                .to_string()
+        ));
+        assert!(insts.iter().any(|inst| inst.contains(
+            r#""op":"add","operands":[{"inst":0,"var":"a"},{"inst":1,"var":"y"}],"span":{"line":2,"col":25}"#
+        )));
+        assert!(insts
+            .iter()
+            .any(|inst| inst.contains(r#""op":"jsr","operands":[],"target":2"#)));
+        assert_eq!(
+            blocks[1].get("function").map(Json::to_string),
+            Some(r#""main""#.to_string())
+        );
+    }
+
+    #[test]
+    fn diagnostic_list() {
+        let diagnostics =
+            compile(b"main {\n  let x <- }.", &CompileOptions::default()).expect_err("Should fail");
+
+        assert_eq!(
+            super::diagnostics(&diagnostics).to_string(),
+            concat!(
+                r#"{"version":1,"kind":"diagnostics","diagnostics":["#,
+                r#"{"kind":"Syntax","message":"unexpected token","span":{"line":2,"col":12}}]}"#
+            )
+        );
+    }
+}
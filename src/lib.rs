@@ -0,0 +1,143 @@
+pub mod ast_data;
+pub mod ast_interpreter;
+pub mod codegen;
+pub mod diagnostics;
+pub mod dlx_data;
+pub mod effects;
+pub mod emulator;
+pub mod fuzz;
+mod input;
+pub mod interpreter;
+pub mod ir_parser;
+pub mod json;
+pub mod lower;
+pub mod module;
+pub mod parser;
+pub mod parser_data;
+pub mod passes;
+pub mod sema;
+pub mod snapshot;
+pub mod tokenizer;
+pub mod tokenizer_data;
+pub mod uninit;
+
+pub use diagnostics::{Diagnostic, DiagnosticKind, Diagnostics};
+pub use ir_parser::parse_ir;
+pub use module::{GraphOptions, GraphView, Module};
+pub use parser::Parser;
+pub use parser_data::{Block, BlockList, IType, Inst, InstList};
+pub use passes::{Pass, PassManager, Passes};
+pub use tokenizer::Tokenizer;
+
+use lower::Lowerer;
+use sema::Checker;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CompileOptions {
+    pub passes: Passes,
+    // Fail on warnings such as reads of uninitialized variables
+    pub deny_warnings: bool,
+}
+
+// Parses, checks and lowers `source` to SSA
+pub fn compile(source: &[u8], options: &CompileOptions) -> Result<Module, Diagnostics> {
+    let mut parser = Parser::new(source);
+    let Some(ast) = parser.computation() else {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(
+            DiagnosticKind::Syntax,
+            "unexpected token",
+            Some(parser.span()),
+        );
+        return Err(diagnostics);
+    };
+
+    let mut diagnostics = Checker::new(parser.idents()).check(&ast);
+    if options.deny_warnings {
+        diagnostics.deny_warnings();
+    }
+    if diagnostics.has_errors() {
+        return Err(diagnostics);
+    }
+
+    let Some(mut module) = Lowerer::new(parser.idents()).computation(&ast) else {
+        let mut diagnostics = Diagnostics::new();
+        diagnostics.push(DiagnosticKind::Semantic, "unable to lower program", None);
+        return Err(diagnostics);
+    };
+
+    PassManager::from(options.passes).run(&mut module);
+    Ok(module)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compile_module() {
+        let module = compile(
+            b"main var x; function f(a); { return a + 1 }; { let x <- call f(2); call OutputNum(x) }.",
+            &CompileOptions::default(),
+        )
+        .expect("Should compile");
+
+        assert_eq!(module.functions().collect::<Vec<_>>(), vec![("f", 2)]);
+        assert!(module
+            .block(module.main_block())
+            .iter()
+            .any(|&inst| matches!(module.inst(inst).itype(), IType::Jsr { block: 2 })));
+    }
+
+    #[test]
+    fn graph_views() {
+        let module = compile(
+            b"main var a; function f(x); { return x * 2 }; { let a <- 1;
+              while a < 10 do let a <- call f(a) od; call OutputNum(a) }.",
+            &CompileOptions::default(),
+        )
+        .expect("Should compile");
+
+        let cfg = module.graph();
+        assert!(cfg.contains("subgraph cluster_bb2 {\n\t\tlabel=\"f\";"));
+        assert!(cfg.contains("mul (0:x) (1)"));
+        assert!(cfg.contains("\tbb4:s -> bb3:n [color=red, style=bold];"));
+        assert!(!cfg.contains("bb0:s"));
+
+        let dom = module.graph_with(&GraphOptions {
+            view: GraphView::Dominators,
+            consts: false,
+        });
+        assert!(!dom.contains("bb0"));
+        assert!(dom.contains("\tbb3:s -> bb5:n [color=blue, style=dotted];"));
+        assert!(!dom.contains("fall-through"));
+    }
+
+    #[test]
+    fn deny_warnings() {
+        let input = b"main var x, y; { let x <- y; call OutputNum(x) }.";
+        compile(input, &CompileOptions::default()).expect("Should compile");
+
+        let options = CompileOptions {
+            deny_warnings: true,
+            ..CompileOptions::default()
+        };
+        let diagnostics = compile(input, &options).expect_err("Should fail");
+        let diagnostic = diagnostics.iter().next().expect("Should exist");
+        assert_eq!(diagnostic.kind, DiagnosticKind::Semantic);
+        assert_eq!(diagnostic.message, "`y` is definitely uninitialized");
+    }
+
+    #[test]
+    fn syntax_error() {
+        let diagnostics =
+            compile(b"main {\n  let x <- }.", &CompileOptions::default()).expect_err("Should fail");
+
+        let diagnostic = diagnostics.iter().next().expect("Should exist");
+        assert_eq!(diagnostic.kind, DiagnosticKind::Syntax);
+        assert_eq!(
+            diagnostic.span,
+            Some(tokenizer_data::Span { line: 2, col: 12 })
+        );
+    }
+}
@@ -0,0 +1,2131 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ast_data::{
+        self, BinOp, Call, Computation, Cond, CondKind, Expr, ExprKind, FuncDecl, LogicOp,
+        Relation, Stmt, StmtKind, VarDecl,
+    },
+    effects::{self, Effects},
+    module::Module,
+    parser_data::{BlockList, IType, InstList, Operand},
+    tokenizer_data::{
+        RelOp, Span, PREDEFINED_INPUTNUM_ID, PREDEFINED_OUTPUTNEWLINE_ID, PREDEFINED_OUTPUTNUM_ID,
+    },
+};
+
+// Blocks ending in a `break` or `continue`, wired up once the loop is lowered
+#[derive(Debug, Default, PartialEq)]
+struct LoopExits {
+    breaks: Vec<usize>,
+    continues: Vec<usize>,
+}
+
+// Where control leaves a condition, the instruction of a `Branch` edge is
+// added once its target is known
+#[derive(Debug, Clone)]
+enum Edge {
+    FallThrough(usize),
+    Branch(usize, IType),
+}
+
+impl Edge {
+    fn block(&self) -> usize {
+        match *self {
+            Edge::FallThrough(block) | Edge::Branch(block, _) => block,
+        }
+    }
+}
+
+// Edges taken when a condition holds and when it fails
+#[derive(Debug)]
+struct CondExits {
+    trues: Vec<Edge>,
+    falses: Vec<Edge>,
+}
+
+// Builds SSA from the AST produced by `Parser`
+#[derive(Debug, PartialEq)]
+pub struct Lowerer {
+    idents: Vec<String>,
+    const_map: BTreeMap<isize, usize>,
+    // func ident -> block id
+    func_map: BTreeMap<usize, usize>,
+    // var ident -> placeholder const for uninitialized reads
+    undef_map: BTreeMap<usize, usize>,
+    // jsr inst -> func ident, for calls lowered before their callee
+    pending_calls: Vec<(usize, usize)>,
+    // func ident -> globals it reads and writes
+    effects: BTreeMap<usize, Effects>,
+    // None while lowering main
+    curr_func: Option<usize>,
+    // Globals hidden by the current function's params and locals
+    shadowed: BTreeSet<usize>,
+    // Innermost last
+    loops: Vec<LoopExits>,
+    // removed trivial phi -> the value that replaced it
+    replaced: BTreeMap<usize, usize>,
+    curr_block: usize,
+    // Source of the instructions being added
+    span: Option<Span>,
+    spans: BTreeMap<usize, Span>,
+    blocks: BlockList,
+    insts: InstList,
+}
+
+impl Lowerer {
+    pub fn new(idents: &[String]) -> Lowerer {
+        let mut block_list = BlockList::new();
+        let init_block = block_list.add();
+        let start_block = block_list.add_from(init_block);
+        block_list.get_mut(start_block).seal();
+
+        Lowerer {
+            idents: idents.to_vec(),
+            const_map: BTreeMap::new(),
+            func_map: BTreeMap::new(),
+            undef_map: BTreeMap::new(),
+            pending_calls: Vec::new(),
+            effects: BTreeMap::new(),
+            curr_func: None,
+            shadowed: BTreeSet::new(),
+            loops: Vec::new(),
+            replaced: BTreeMap::new(),
+            curr_block: start_block,
+            span: None,
+            spans: BTreeMap::new(),
+            blocks: block_list,
+            insts: InstList::new(),
+        }
+    }
+
+    pub fn get_var(&self, var: usize) -> String {
+        self.idents
+            .get(var)
+            .expect("Ident should exist")
+            .to_string()
+    }
+
+    // Helper
+    fn op_to_inst(&mut self, operand: Operand) -> (usize, Option<usize>) {
+        match operand {
+            Operand::Const(val) => (self.get_const(val), None),
+            Operand::Inst(inst) => (inst, None),
+            Operand::Var(id, inst) => (inst, Some(id)),
+        }
+    }
+
+    fn add_inst(&mut self, block: usize, itype: IType) -> usize {
+        let dom = self.blocks.get(block).get_dom_inst(&itype);
+        let inst = self.insts.add(itype.clone(), dom, Some(block));
+
+        self.blocks.get_mut(block).add_inst(inst, &itype);
+        // Constants are shared by every use
+        if let (Some(span), true) = (self.span, block != 0) {
+            self.spans.insert(inst, span);
+        }
+        inst
+    }
+
+    pub fn get_const(&mut self, val: isize) -> usize {
+        if let Some(inst) = self.const_map.get(&val) {
+            inst.to_owned()
+        } else {
+            let inst = self.add_inst(0, IType::Const(val));
+            self.const_map.insert(val, inst);
+            inst
+        }
+    }
+
+    fn get_undef(&mut self, var: usize) -> usize {
+        if let Some(inst) = self.undef_map.get(&var) {
+            inst.to_owned()
+        } else {
+            // Not shared with const_map so uninitialized reads stay distinguishable
+            let inst = self.add_inst(0, IType::Const(0));
+            self.undef_map.insert(var, inst);
+            inst
+        }
+    }
+
+    fn compute(&mut self, op: BinOp, x: Operand, y: Operand) -> Operand {
+        match (x, y) {
+            // Folds with the 32-bit wrapping arithmetic of the interpreters and
+            // DLX, a division by zero is left to fail when it runs
+            (Operand::Const(val1), Operand::Const(val2))
+                if !(op == BinOp::Div && val2 as i32 == 0) =>
+            {
+                let (x, y) = (val1 as i32, val2 as i32);
+                let val = match op {
+                    BinOp::Add => x.wrapping_add(y),
+                    BinOp::Sub => x.wrapping_sub(y),
+                    BinOp::Mul => x.wrapping_mul(y),
+                    BinOp::Div => x.wrapping_div(y),
+                };
+                Operand::Const(val as isize)
+            }
+            (x, y) => {
+                let inst1 = self.op_to_inst(x);
+                let inst2 = self.op_to_inst(y);
+
+                let inst = match op {
+                    BinOp::Add => self.add_inst(self.curr_block, IType::Add { inst1, inst2 }),
+                    BinOp::Sub => self.add_inst(self.curr_block, IType::Sub { inst1, inst2 }),
+                    BinOp::Mul => self.add_inst(self.curr_block, IType::Mul { inst1, inst2 }),
+                    BinOp::Div => self.add_inst(self.curr_block, IType::Div { inst1, inst2 }),
+                };
+
+                Operand::Inst(inst)
+            }
+        }
+    }
+
+    fn load_arg(&mut self, inst: (usize, Option<usize>), arg: usize) -> usize {
+        self.add_inst(self.curr_block, IType::SetPar { arg, inst })
+    }
+
+    fn declare_arg(&mut self, arg: usize) -> usize {
+        self.add_inst(self.curr_block, IType::GetPar { arg })
+    }
+
+    // Lowering
+    fn expression(&mut self, expr: &Expr) -> Option<Operand> {
+        // Instructions take the span of the innermost expression computing them
+        let outer = self.span.replace(expr.span);
+        let operand = match &expr.kind {
+            ExprKind::Num(num) => Operand::Const(*num),
+            // Read now, a call in a later operand may reload the variable
+            ExprKind::Var(var) => Operand::Var(*var, self.read_var(self.curr_block, *var)),
+            ExprKind::Binary { op, lhs, rhs } => {
+                let x = self.expression(lhs)?;
+                let y = self.expression(rhs)?;
+                self.span = Some(expr.span);
+                self.compute(*op, x, y)
+            }
+            ExprKind::Call(call) => self.func_call(call)?,
+        };
+        self.span = outer;
+        Some(operand)
+    }
+
+    // The branch taken when the relation fails, or holds with `negate`
+    fn relation(&mut self, relation: &Relation, negate: bool) -> Option<IType> {
+        let expr1 = self.expression(&relation.lhs)?;
+        let expr2 = self.expression(&relation.rhs)?;
+
+        let inst1 = self.op_to_inst(expr1);
+        let inst2 = self.op_to_inst(expr2);
+
+        let cmp_inst = self.add_inst(self.curr_block, IType::Cmp { inst1, inst2 });
+
+        let op = if negate {
+            relation.op.negate()
+        } else {
+            relation.op
+        };
+        let instruction = match op {
+            RelOp::Equal => IType::Bne {
+                inst: cmp_inst,
+                block: None,
+            },
+            RelOp::NotEqual => IType::Beq {
+                inst: cmp_inst,
+                block: None,
+            },
+            RelOp::LessThan => IType::Bge {
+                inst: cmp_inst,
+                block: None,
+            },
+            RelOp::LessThanOrEqual => IType::Bgt {
+                inst: cmp_inst,
+                block: None,
+            },
+            RelOp::GreaterThan => IType::Ble {
+                inst: cmp_inst,
+                block: None,
+            },
+            RelOp::GreaterThanOrEqual => IType::Blt {
+                inst: cmp_inst,
+                block: None,
+            },
+        };
+
+        Some(instruction)
+    }
+
+    // SSA construction after Braun et al., "Simple and Efficient Construction of SSA Form"
+    fn write_var(&mut self, block: usize, var: usize, inst: usize) {
+        self.blocks.get_mut(block).assign_var(var, inst, None);
+    }
+
+    fn read_var(&mut self, block: usize, var: usize) -> usize {
+        let inst = match self.blocks.get(block).get_var_inst(var) {
+            Some(inst) => inst,
+            None => self.read_var_recursive(block, var),
+        };
+
+        self.resolve(inst)
+    }
+
+    fn read_var_recursive(&mut self, block: usize, var: usize) -> usize {
+        let preds = self.blocks.get(block).get_preds().to_vec();
+
+        let inst = if !self.blocks.get(block).is_sealed() {
+            let phi = self.add_phi(block, var);
+            self.blocks.get_mut(block).add_incomplete_phi(var, phi);
+            phi
+        } else if let [pred] = preds[..] {
+            self.read_var(pred, var)
+        } else if preds.is_empty() {
+            // Reported by `uninit::check`
+            self.get_undef(var)
+        } else {
+            // Break cycles through loops before visiting the predecessors
+            let phi = self.add_phi(block, var);
+            self.write_var(block, var, phi);
+            self.add_phi_operands(block, var, phi)
+        };
+
+        self.write_var(block, var, inst);
+        inst
+    }
+
+    fn add_phi(&mut self, block: usize, var: usize) -> usize {
+        // Operands are added once the predecessors are known
+        let phi = self.insts.add(
+            IType::Phi {
+                insts: Vec::new(),
+                var,
+            },
+            None,
+            Some(block),
+        );
+
+        self.blocks.get_mut(block).add_phi(phi, &self.insts);
+        phi
+    }
+
+    fn add_phi_operands(&mut self, block: usize, var: usize, phi: usize) -> usize {
+        let preds = self.blocks.get(block).get_preds().to_vec();
+        for (pred_idx, &pred) in preds.iter().enumerate() {
+            let inst = self.read_var(pred, var);
+            self.insts.get_mut(phi).set_phi_operand(pred_idx, inst);
+        }
+
+        self.try_remove_trivial_phi(phi)
+    }
+
+    fn try_remove_trivial_phi(&mut self, phi: usize) -> usize {
+        let IType::Phi { insts, var } = self.insts.get(phi).itype() else {
+            unreachable!()
+        };
+
+        let mut same = None;
+        for (op, _) in insts {
+            if Some(op) == same || op == phi {
+                continue;
+            }
+            if same.is_some() {
+                return phi;
+            }
+            same = Some(op);
+        }
+        // Only reachable through itself
+        let same = same.unwrap_or_else(|| self.get_undef(var));
+
+        let users = self
+            .insts
+            .instructions
+            .iter()
+            .filter(|inst| {
+                inst.id() != phi
+                    && inst.block().is_some()
+                    && matches!(inst.itype(), IType::Phi { .. })
+                    && inst.itype().operands().contains(&phi)
+            })
+            .map(|inst| inst.id())
+            .collect::<Vec<_>>();
+
+        self.insts.rename_inst(phi, same);
+        for block in self.blocks.blocks.iter_mut() {
+            block.rename_var_defs(phi, same);
+        }
+        let block = self.insts.get(phi).block().expect("Phi should be placed");
+        self.blocks.get_mut(block).delete_inst(phi);
+        self.insts.remove_block(phi);
+        self.replaced.insert(phi, same);
+
+        for user in users {
+            if self.insts.get(user).block().is_some() {
+                self.try_remove_trivial_phi(user);
+            }
+        }
+
+        self.resolve(same)
+    }
+
+    fn resolve(&self, mut inst: usize) -> usize {
+        while let Some(&next) = self.replaced.get(&inst) {
+            inst = next;
+        }
+        inst
+    }
+
+    fn seal_block(&mut self, block: usize) {
+        for (var, phi) in self.blocks.get_mut(block).seal() {
+            self.add_phi_operands(block, var, phi);
+        }
+    }
+
+    fn assignment(&mut self, var: usize, expr: &Expr) -> Option<()> {
+        let expr = self.expression(expr)?;
+        let dep_var = match expr {
+            Operand::Var(dep_var, _) => Some(dep_var),
+            _ => None,
+        };
+        let inst = self.op_to_inst(expr);
+
+        self.blocks
+            .get_mut(self.curr_block)
+            .assign_var(var, inst.0, dep_var);
+
+        Some(())
+    }
+
+    fn func_call(&mut self, call: &Call) -> Option<Operand> {
+        let mut args = Vec::new();
+        for arg in call.args.iter() {
+            let expr = self.expression(arg)?;
+            args.push(self.op_to_inst(expr));
+        }
+
+        let inst = match call.func {
+            PREDEFINED_INPUTNUM_ID => self.add_inst(self.curr_block, IType::Read),
+            PREDEFINED_OUTPUTNUM_ID => {
+                let inst = *args.first()?;
+                self.add_inst(self.curr_block, IType::Write { inst })
+            }
+            PREDEFINED_OUTPUTNEWLINE_ID => self.add_inst(self.curr_block, IType::WriteNL),
+            id => {
+                for (idx, &inst) in args.iter().enumerate() {
+                    self.load_arg(inst, idx);
+                }
+                let effects = self.effects.get(&id).cloned().unwrap_or_default();
+                self.store_globals(&effects.touched());
+
+                // Later functions get their target once every body is lowered
+                let block = self.func_map.get(&id).copied();
+                let inst = self.add_inst(
+                    self.curr_block,
+                    IType::Jsr {
+                        block: block.unwrap_or_default(),
+                    },
+                );
+                if block.is_none() {
+                    self.pending_calls.push((inst, id));
+                }
+
+                self.load_globals(&effects.writes);
+                inst
+            }
+        };
+
+        Some(Operand::Inst(inst))
+    }
+
+    // Globals of `vars` that are SSA values in the current function
+    fn visible(&self, vars: &BTreeSet<usize>) -> Vec<usize> {
+        vars.difference(&self.shadowed).copied().collect()
+    }
+
+    fn load_globals(&mut self, vars: &BTreeSet<usize>) {
+        for var in self.visible(vars) {
+            let inst = self.add_inst(self.curr_block, IType::Load { var });
+            self.write_var(self.curr_block, var, inst);
+        }
+    }
+
+    fn store_globals(&mut self, vars: &BTreeSet<usize>) {
+        for var in self.visible(vars) {
+            let inst = (self.read_var(self.curr_block, var), Some(var));
+            self.add_inst(self.curr_block, IType::Store { var, inst });
+        }
+    }
+
+    // The caller loads the globals written by the function after the call
+    fn add_ret(&mut self, inst: Option<(usize, Option<usize>)>) {
+        if let Some(func) = self.curr_func {
+            let writes = self.effects[&func].writes.clone();
+            self.store_globals(&writes);
+        }
+        self.add_inst(self.curr_block, IType::Ret { inst });
+    }
+
+    // Short-circuit evaluation starting in the current block, `negate` lowers
+    // `not cond` instead
+    fn condition(&mut self, cond: &Cond, negate: bool) -> Option<CondExits> {
+        match &cond.kind {
+            CondKind::Relation(relation) => {
+                let block = self.curr_block;
+                let branch_itype = self.relation(relation, negate)?;
+
+                Some(CondExits {
+                    trues: vec![Edge::FallThrough(block)],
+                    falses: vec![Edge::Branch(block, branch_itype)],
+                })
+            }
+            CondKind::Not(cond) => self.condition(cond, !negate),
+            // `not (a and b)` is lowered as `not a or not b`, and the other
+            // way around
+            CondKind::Logical { op, lhs, rhs } if (*op == LogicOp::And) != negate => {
+                let lhs = self.condition(lhs, negate)?;
+                self.curr_block = self.land(&lhs.trues);
+                let mut rhs = self.condition(rhs, negate)?;
+
+                rhs.falses.splice(0..0, lhs.falses);
+                Some(rhs)
+            }
+            CondKind::Logical { lhs, rhs, .. } => {
+                // Falls through to `rhs` when `lhs` fails
+                let lhs = self.condition(lhs, !negate)?;
+                self.curr_block = self.land(&lhs.trues);
+                let mut rhs = self.condition(rhs, negate)?;
+
+                rhs.trues.splice(0..0, lhs.falses);
+                Some(rhs)
+            }
+        }
+    }
+
+    fn connect(&mut self, edge: &Edge, block: usize) {
+        let from = match *edge {
+            Edge::FallThrough(from) => {
+                self.blocks.get_mut(from).set_fall_through(block);
+                from
+            }
+            Edge::Branch(from, ref branch_itype) => {
+                self.blocks.get_mut(from).set_follow(block);
+                self.add_inst(from, branch_itype.branch_block(block));
+                from
+            }
+        };
+        self.blocks.get_mut(block).add_pred(from);
+    }
+
+    // Nearest block dominating both
+    fn common_dom(&self, mut block: usize, other: usize) -> usize {
+        while !self.blocks.dominates(block, other) {
+            block = self
+                .blocks
+                .get(block)
+                .dom()
+                .expect("Blocks should share a dominator");
+        }
+        block
+    }
+
+    // A new sealed block that every edge leads to
+    fn land(&mut self, edges: &[Edge]) -> usize {
+        let (first, rest) = edges.split_first().expect("Condition should have an exit");
+        let dom = rest.iter().fold(first.block(), |dom, edge| {
+            self.common_dom(dom, edge.block())
+        });
+
+        let block = self.blocks.add_from(dom);
+        for edge in edges {
+            self.connect(edge, block);
+        }
+        self.seal_block(block);
+
+        block
+    }
+
+    // Like `land`, into a block that has other predecessors
+    fn land_in(&mut self, edges: &[Edge], block: usize) {
+        for edge in edges {
+            self.connect(edge, block);
+        }
+    }
+
+    // Each `elsif` condition is checked in a block of its own once the
+    // previous one fails, and every arm ends in the same join block
+    fn r#if(
+        &mut self,
+        cond: &Cond,
+        then_body: &[Stmt],
+        elsifs: &[(Cond, Vec<Stmt>)],
+        else_body: Option<&[Stmt]>,
+    ) -> Option<()> {
+        let curr_block = self.curr_block;
+
+        let exits = self.condition(cond, false)?;
+        let fall_through_block = self.land(&exits.trues);
+
+        let join_block = self.blocks.add_from(curr_block);
+
+        self.curr_block = fall_through_block;
+        self.stat_sequence(then_body)?;
+        let mut falses = exits.falses;
+
+        for (cond, body) in elsifs {
+            let arm_end = self.curr_block;
+            self.curr_block = self.land(&falses);
+            self.skip_arms(arm_end, join_block);
+
+            let exits = self.condition(cond, false)?;
+            self.curr_block = self.land(&exits.trues);
+            self.stat_sequence(body)?;
+            falses = exits.falses;
+        }
+
+        let arm_end = self.curr_block;
+        if let Some(else_body) = else_body {
+            self.curr_block = self.land(&falses);
+            self.skip_arms(arm_end, join_block);
+
+            self.stat_sequence(else_body)?;
+            self.fall_into(self.curr_block, join_block);
+        } else {
+            self.fall_into(arm_end, join_block);
+            self.land_in(&falses, join_block);
+        }
+
+        self.seal_block(join_block);
+        self.curr_block = join_block;
+
+        Some(())
+    }
+
+    // The end of an arm branches over the arms after it
+    fn skip_arms(&mut self, arm_end: usize, join_block: usize) {
+        if self.fall_into(arm_end, join_block) {
+            self.add_inst(arm_end, IType::Bra { block: join_block });
+        }
+    }
+
+    // Blocks are reached through their predecessors, apart from the entry
+    // blocks of main and the functions
+    fn is_reachable(&self, block: usize) -> bool {
+        !self.blocks.get(block).get_preds().is_empty()
+            || block == 1
+            || self.func_map.values().any(|&start| start == block)
+    }
+
+    // Adds the edge unless nothing reaches `from`, which keeps code after a
+    // `break` or `continue` out of the phis
+    fn fall_into(&mut self, from: usize, to: usize) -> bool {
+        if !self.is_reachable(from) {
+            return false;
+        }
+
+        self.blocks.get_mut(from).set_fall_through(to);
+        self.blocks.get_mut(to).add_pred(from);
+        true
+    }
+
+    // Lowers `body` with its `break` and `continue` edges collected
+    fn loop_body(&mut self, body: &[Stmt]) -> Option<LoopExits> {
+        self.loops.push(LoopExits::default());
+        let res = self.stat_sequence(body);
+        let exits = self.loops.pop().expect("Loop should be pushed");
+        res?;
+
+        Some(exits)
+    }
+
+    // A sealed block without predecessors for code after a jump. Nothing
+    // reaches it, so it is vacuously dominated by the current block, which
+    // keeps it with its function when printed
+    fn unreachable_block(&mut self) -> usize {
+        let block = self.blocks.add_from(self.curr_block);
+        self.seal_block(block);
+        block
+    }
+
+    // A join block for the edges out of `blocks`, the first one falls into
+    // it and the others branch. Unreachable blocks are left out, with none
+    // left the result is a new unreachable block
+    fn join_edges(&mut self, blocks: &[usize]) -> usize {
+        let blocks = blocks
+            .iter()
+            .copied()
+            .filter(|&block| self.is_reachable(block))
+            .collect::<Vec<_>>();
+        let (first, rest) = match blocks[..] {
+            [] => return self.unreachable_block(),
+            [block] => return block,
+            [first, ref rest @ ..] => (first, rest),
+        };
+
+        let dom = rest
+            .iter()
+            .fold(first, |dom, &block| self.common_dom(dom, block));
+        let join_block = self.blocks.add_from(dom);
+
+        self.fall_into(first, join_block);
+        for &block in rest {
+            self.fall_into(block, join_block);
+            self.add_inst(block, IType::Bra { block: join_block });
+        }
+
+        self.seal_block(join_block);
+        join_block
+    }
+
+    // `step` runs at the end of every iteration, after any `continue`
+    fn r#while(&mut self, cond: &Cond, body: &[Stmt], step: Option<&Stmt>) -> Option<()> {
+        let curr_block = self.curr_block;
+
+        // The back edge is unknown until the body is lowered, so the header stays unsealed
+        let join_block = self.blocks.add_from(self.curr_block);
+
+        self.blocks.get_mut(curr_block).set_fall_through(join_block);
+        self.blocks.get_mut(join_block).add_pred(curr_block);
+
+        self.curr_block = join_block;
+        let exits = self.condition(cond, false)?;
+        self.curr_block = self.land(&exits.trues);
+        let loop_exits = self.loop_body(body)?;
+
+        let mut latch = vec![self.curr_block];
+        latch.extend(loop_exits.continues);
+        self.curr_block = self.join_edges(&latch);
+        if let (Some(step), true) = (step, self.is_reachable(self.curr_block)) {
+            self.statement(step)?;
+        }
+        let fall_through_block = self.curr_block;
+
+        if self.fall_into(fall_through_block, join_block) {
+            self.add_inst(fall_through_block, IType::Bra { block: join_block });
+        }
+        self.seal_block(join_block);
+
+        let follow_block = self.land(&exits.falses);
+
+        let mut exit = vec![follow_block];
+        exit.extend(loop_exits.breaks);
+        self.curr_block = self.join_edges(&exit);
+
+        Some(())
+    }
+
+    // `expr` evaluated once, as a number when it is constant and otherwise
+    // through a variable no source name refers to
+    fn loop_invariant(&mut self, name: &str, expr: &Expr, span: Span) -> Option<Expr> {
+        if let Some(val) = ast_data::const_value(expr) {
+            return Some(Expr {
+                kind: ExprKind::Num(val as isize),
+                span,
+            });
+        }
+
+        let var = self.idents.len();
+        self.idents.push(format!("{}.{}", name, var));
+        self.assignment(var, expr)?;
+        Some(Expr {
+            kind: ExprKind::Var(var),
+            span,
+        })
+    }
+
+    // Lowered as `let var <- from; while var <= to do body; let var <- var + step od`
+    // with `to` and `step` evaluated before the loop
+    fn r#for(
+        &mut self,
+        var: usize,
+        from: &Expr,
+        to: &Expr,
+        step: Option<&Expr>,
+        body: &[Stmt],
+        span: Span,
+    ) -> Option<()> {
+        self.assignment(var, from)?;
+        let to = self.loop_invariant("to", to, span)?;
+        let step = match step {
+            Some(step) => self.loop_invariant("step", step, span)?,
+            None => Expr {
+                kind: ExprKind::Num(1),
+                span,
+            },
+        };
+
+        let (cond, next) = ast_data::for_parts(var, to, step, span);
+        let step = Stmt {
+            kind: StmtKind::Assign { var, expr: next },
+            span,
+        };
+
+        self.r#while(&cond, body, Some(&step))
+    }
+
+    fn repeat(&mut self, body: &[Stmt], cond: &Cond) -> Option<()> {
+        let curr_block = self.curr_block;
+
+        // Unsealed until the condition at the bottom adds the back edge
+        let join_block = self.blocks.add_from(curr_block);
+
+        self.blocks.get_mut(curr_block).set_fall_through(join_block);
+        self.blocks.get_mut(join_block).add_pred(curr_block);
+
+        self.curr_block = join_block;
+        let loop_exits = self.loop_body(body)?;
+
+        // `continue` skips to the condition
+        let mut latch = vec![self.curr_block];
+        latch.extend(loop_exits.continues);
+        self.curr_block = self.join_edges(&latch);
+        let cond_block = self.curr_block;
+
+        // Loops back while the condition fails
+        let fall_through_block = if self.is_reachable(cond_block) {
+            let exits = self.condition(cond, false)?;
+            self.land_in(&exits.falses, join_block);
+            self.land(&exits.trues)
+        } else {
+            self.unreachable_block()
+        };
+        self.seal_block(join_block);
+
+        let mut exit = vec![fall_through_block];
+        exit.extend(loop_exits.breaks);
+        self.curr_block = self.join_edges(&exit);
+
+        Some(())
+    }
+
+    // Ends the current block with an edge out of the innermost loop, the
+    // statements after it go to an unreachable block
+    fn leave_loop(&mut self, is_break: bool) -> Option<()> {
+        let exits = self.loops.last_mut()?;
+        if is_break {
+            exits.breaks.push(self.curr_block);
+        } else {
+            exits.continues.push(self.curr_block);
+        }
+
+        self.curr_block = self.unreachable_block();
+
+        Some(())
+    }
+
+    fn r#return(&mut self, expr: Option<&Expr>) -> Option<()> {
+        let inst = match expr {
+            Some(expr) => {
+                let op = self.expression(expr)?;
+                Some(self.op_to_inst(op))
+            }
+            None => None,
+        };
+
+        self.add_ret(inst);
+
+        Some(())
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Option<()> {
+        self.span = Some(stmt.span);
+        match &stmt.kind {
+            StmtKind::Assign { var, expr } => self.assignment(*var, expr),
+            StmtKind::Call(call) => {
+                self.func_call(call)?;
+                Some(())
+            }
+            StmtKind::If {
+                cond,
+                then_body,
+                elsifs,
+                else_body,
+            } => self.r#if(cond, then_body, elsifs, else_body.as_deref()),
+            StmtKind::While { cond, body } => self.r#while(cond, body, None),
+            StmtKind::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => self.r#for(*var, from, to, step.as_ref(), body, stmt.span),
+            StmtKind::Repeat { body, cond } => self.repeat(body, cond),
+            StmtKind::Break => self.leave_loop(true),
+            StmtKind::Continue => self.leave_loop(false),
+            StmtKind::Return(expr) => self.r#return(expr.as_ref()),
+        }
+    }
+
+    fn stat_sequence(&mut self, stmts: &[Stmt]) -> Option<()> {
+        for stmt in stmts {
+            self.statement(stmt)?;
+            // Nothing after a `break` or `continue` runs
+            if !self.is_reachable(self.curr_block) {
+                break;
+            }
+        }
+
+        Some(())
+    }
+
+    fn var_decl(&mut self, vars: &[VarDecl]) {
+        for decl in vars {
+            self.blocks.get_mut(self.curr_block).declare_var(decl.var);
+        }
+    }
+
+    fn func_decl(&mut self, func: &FuncDecl) -> Option<()> {
+        let start_block = self.blocks.add_from(0);
+        self.func_map.insert(func.name, start_block);
+        self.seal_block(start_block);
+
+        self.curr_block = start_block;
+        self.curr_func = Some(func.name);
+        self.shadowed = effects::shadowed(func);
+        self.span = Some(func.span);
+
+        for (argnum, param) in func.params.iter().enumerate() {
+            let getpar_inst = self.declare_arg(argnum);
+
+            self.blocks
+                .get_mut(self.curr_block)
+                .assign_var(param.var, getpar_inst, None);
+        }
+        // Stored by the caller before the call
+        let touched = self.effects[&func.name].touched();
+        self.load_globals(&touched);
+
+        self.var_decl(&func.vars);
+        self.stat_sequence(&func.body)?;
+        if !matches!(
+            self.blocks
+                .get(self.curr_block)
+                .get_last_inst()
+                .map(|inst| self.insts.get(inst).itype()),
+            Some(IType::Ret { .. }),
+        ) {
+            self.add_ret(None);
+        }
+
+        self.curr_block = 1;
+        self.curr_func = None;
+        self.shadowed.clear();
+
+        Some(())
+    }
+
+    pub fn computation(mut self, ast: &Computation) -> Option<Module> {
+        self.effects = effects::global_effects(ast);
+        self.var_decl(&ast.vars);
+
+        for func in ast.funcs.iter() {
+            self.func_decl(func)?;
+        }
+
+        self.stat_sequence(&ast.body)?;
+
+        self.add_inst(self.curr_block, IType::End);
+
+        for &(inst, func) in self.pending_calls.iter() {
+            let block = *self.func_map.get(&func)?;
+            self.insts.get_mut(inst).set_jsr_target(block);
+        }
+
+        let mut module = Module::new(
+            self.idents,
+            self.func_map,
+            self.undef_map,
+            self.blocks,
+            self.insts,
+        )
+        .with_spans(self.spans);
+        module.fill_empty();
+        Some(module)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        ast_interpreter::AstInterpreter,
+        emulator::Emulator,
+        parser::Parser,
+        passes::{PassManager, Passes},
+    };
+
+    use super::*;
+
+    fn lower_with(input: &[u8], passes: Passes) -> Module {
+        let mut parser = Parser::new(input);
+        let ast = parser.computation().expect("Should parse");
+
+        let mut module = Lowerer::new(parser.idents())
+            .computation(&ast)
+            .expect("Should lower");
+        PassManager::from(passes).run(&mut module);
+        module
+    }
+
+    fn lower(input: &[u8]) -> Module {
+        lower_with(input, Passes::default())
+    }
+
+    // Every placed phi has one operand per predecessor of its block
+    fn assert_phi_arity(ir: &Module) {
+        for block in ir.blocks().iter() {
+            for phi in block.get_phis(ir.insts()) {
+                assert_eq!(
+                    ir.insts().get(phi).itype().operands().len(),
+                    block.get_preds().len()
+                );
+            }
+        }
+    }
+
+    // Runs the program through the AST interpreter and checks that the SSA
+    // interpreter and the DLX emulator agree with it
+    fn assert_matches_oracle(input: &[u8], stdin: &[u8]) {
+        let ast = Parser::new(input).computation().expect("Should parse");
+        let mut expected = Vec::new();
+        let expected_res = AstInterpreter::new(&ast)
+            .step_limit(1_000_000)
+            .run(stdin, &mut expected);
+
+        let ir = lower(input);
+        let mut actual = Vec::new();
+        let actual_res = ir
+            .interpreter()
+            .step_limit(1_000_000)
+            .run(stdin, &mut actual);
+
+        assert_eq!(
+            String::from_utf8_lossy(&actual),
+            String::from_utf8_lossy(&expected)
+        );
+        assert_eq!(actual_res.is_ok(), expected_res.is_ok());
+
+        if expected_res.is_ok() {
+            let program = ir.generate_dlx().expect("Should generate");
+            let mut actual = Vec::new();
+            Emulator::new(&program.words())
+                .run(stdin, &mut actual)
+                .expect("Should run");
+            assert_eq!(
+                String::from_utf8_lossy(&actual),
+                String::from_utf8_lossy(&expected)
+            );
+        }
+    }
+
+    #[test]
+    fn basic_var_assignment() {
+        let input = b"
+    main
+    var x;
+    {
+        let x <- 10
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+
+        // x = 3
+        assert_eq!(ir.insts().get(0).itype(), IType::Const(10));
+        assert_eq!(ir.blocks().get(1).get_var_inst(3).unwrap(), 0);
+
+        assert_eq!(ir.insts().get(1).itype(), IType::End);
+        assert_eq!(ir.blocks().get(1).get_last_inst().unwrap(), 1);
+    }
+
+    #[test]
+    fn multiple_vars() {
+        let input = b"
+    main
+    var x, y;
+    {
+        let x <- 10;
+        let y <- 12
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+
+        // x = 3, y = 4
+        assert_eq!(ir.insts().get(0).itype(), IType::Const(10));
+        assert_eq!(ir.blocks().get(1).get_var_inst(3).unwrap(), 0);
+
+        assert_eq!(ir.insts().get(1).itype(), IType::Const(12));
+        assert_eq!(ir.blocks().get(1).get_var_inst(4).unwrap(), 1);
+    }
+
+    #[test]
+    fn uninitialized_var() {
+        let input = b"
+    main
+    var x, y;
+    {
+        let x <- y;
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+
+        assert_eq!(ir.insts().get(0).itype(), IType::Const(0));
+        assert_eq!(ir.blocks().get(1).get_var_inst(3).unwrap(), 0);
+        assert_eq!(ir.blocks().get(1).get_var_inst(4).unwrap(), 0);
+    }
+
+    #[test]
+    fn func_call() {
+        let input = b"
+    main
+    {
+        call InputNum();
+        call InputNum
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"1 2");
+        // dbg!(&ir);
+
+        assert_eq!(ir.insts().get(0).itype(), IType::Read);
+        assert_eq!(ir.insts().get(1).itype(), IType::Read);
+    }
+
+    #[test]
+    fn arithmetic() {
+        let input = b"
+    main
+    var x, y, z, w, h;
+    {
+        let x <- 1;
+        let y <- 2;
+        let z <- 3;
+        let w <- 4;
+        let h <- x * (z - w / y);
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+
+        // x
+        assert_eq!(ir.insts().get(0).itype(), IType::Const(1));
+        assert_eq!(ir.blocks().get(1).get_var_inst(3).unwrap(), 0);
+
+        // y
+        assert_eq!(ir.insts().get(1).itype(), IType::Const(2));
+        assert_eq!(ir.blocks().get(1).get_var_inst(4).unwrap(), 1);
+
+        // z
+        assert_eq!(ir.insts().get(2).itype(), IType::Const(3));
+        assert_eq!(ir.blocks().get(1).get_var_inst(5).unwrap(), 2);
+
+        // w
+        assert_eq!(ir.insts().get(3).itype(), IType::Const(4));
+        assert_eq!(ir.blocks().get(1).get_var_inst(6).unwrap(), 3);
+
+        // h: Mul(x, Sub(z, Div(w, y)))
+        assert!(matches!(ir.insts().get(4).itype(), IType::Div { .. }));
+        assert!(matches!(ir.insts().get(5).itype(), IType::Sub { .. }));
+        assert!(matches!(ir.insts().get(6).itype(), IType::Mul { .. }));
+        assert_eq!(ir.blocks().get(1).get_var_inst(7).unwrap(), 6);
+    }
+
+    #[test]
+    fn constant_folding() {
+        let input = b"
+    main
+    {
+        call OutputNum(70000 * 70000);
+        call OutputNum(3037000500 * 3037000500);
+        call OutputNum(2147483647 + 1)
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"");
+
+        // Folded with 32-bit wrapping
+        assert!(ir.instructions().contains("const #605032704"));
+        assert!(ir.instructions().contains("const #-2147483648"));
+
+        // Division by zero is kept for the program to fail
+        let input = b"
+    main
+    {
+        call OutputNum(1 / 0)
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"");
+        assert!(ir.instructions().contains(": div (0) (1)"));
+    }
+
+    #[test]
+    fn basic_if_else() {
+        let input = b"
+    main
+    var a, b, c, d, e;
+    {
+        let a <- call InputNum();
+        let b <- a;
+        let c <- b;
+        let d <- b + c;
+        let e <- a + b;
+        if a < 0 then
+            let a <- d + e;
+        else
+            let d <- e + 1;
+        fi;
+        call OutputNum(a);
+        call OutputNum(d);
+    }
+    .
+    ";
+        assert_matches_oracle(input, b"-5");
+        // dbg!(&ir);
+
+        // // a, b, c
+        // assert_eq!(ir.insts().get(0).itype(), IType::Read);
+        // assert_eq!(ir.blocks().get(1).get_var_inst(3).unwrap(), 0);
+        // assert_eq!(ir.blocks().get(1).get_var_inst(4).unwrap(), 0);
+        // assert_eq!(ir.blocks().get(1).get_var_inst(5).unwrap(), 0);
+        //
+        // // d, e
+        // assert!(matches!(ir.insts().get(4).itype(), IType::Add { .. }));
+        // assert_eq!(ir.blocks().get(1).get_var_inst(6).unwrap(), 4);
+        // assert_eq!(ir.blocks().get(1).get_var_inst(7).unwrap(), 4);
+        //
+        // // if
+        // assert_eq!(ir.insts().get(7).itype(), IType::Const(0));
+        // assert!(matches!(ir.insts().get(8).itype(), IType::Cmp { .. }));
+        // assert!(matches!(ir.insts().get(12).itype(), IType::Bge { .. }));
+        //
+        // // a
+        // assert!(matches!(ir.insts().get(9).itype(), IType::Add { .. }));
+        // assert_eq!(ir.blocks().get(2).get_var_inst(3).unwrap(), 9);
+        //
+        // // endif
+        // assert!(matches!(ir.insts().get(13).itype(), IType::Bra { .. }));
+        // assert_eq!(ir.blocks().get(2).get_last_inst().unwrap(), 13);
+        //
+        // // else
+        // assert!(matches!(ir.insts().get(15).itype(), IType::Add { .. }));
+        // assert_eq!(ir.blocks().get(4).get_var_inst(6).unwrap(), 15);
+        //
+        // // Phi: a
+        // assert!(matches!(ir.insts().get(11).itype(), IType::Phi { .. }));
+        // assert_eq!(ir.blocks().get(3).get_var_inst(3).unwrap(), 11);
+        //
+        // // Phi: d
+        // assert!(matches!(ir.insts().get(17).itype(), IType::Phi { .. }));
+        // assert_eq!(ir.blocks().get(3).get_var_inst(6).unwrap(), 17);
+    }
+
+    #[test]
+    fn basic_while() {
+        let input = b"
+    main
+    var x, y, i, j;
+    {
+        let i <- call InputNum();
+        let x <- 0;
+        let y <- 0;
+        let j <- i;
+
+        while x < 10
+        do
+            let x <- i + 1;
+            let y <- j + 1;
+            let i <- i + 1;
+        od;
+
+        call OutputNum(x);
+        call OutputNum(y);
+        call OutputNum(i);
+        call OutputNum(j);
+    }
+    .
+";
+        assert_matches_oracle(input, b"3");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn minimal_phis() {
+        let input = b"
+    main
+    var a, b, i;
+    {
+        let a <- call InputNum();
+        let b <- a + 1;
+        let i <- 0;
+        while i < a do
+            if i == b then
+                call OutputNum(b)
+            fi;
+            let i <- i + 1
+        od;
+        call OutputNum(i)
+    }
+    .
+    ";
+        let ir = lower(input);
+        assert_matches_oracle(input, b"3");
+
+        // Only i changes inside the loop, and nothing is assigned in the if
+        let phis = ir
+            .blocks()
+            .iter()
+            .flat_map(|block| block.get_phis(ir.insts()))
+            .collect::<Vec<_>>();
+        assert_eq!(phis.len(), 1);
+        assert!(matches!(
+            ir.insts().get(phis[0]).itype(),
+            IType::Phi { var: 5, .. }
+        ));
+    }
+
+    #[test]
+    fn dead_code() {
+        let input = b"
+    main
+    var a, b;
+    {
+        let a <- call InputNum();
+        let b <- a * 2;
+        let b <- a / 2;
+        let a <- a + 1;
+        call OutputNum(a)
+    }
+    .
+    ";
+        let ir = lower_with(input, Passes::level(2));
+
+        let live = ir
+            .blocks()
+            .get(1)
+            .iter()
+            .map(|&inst| ir.insts().get(inst).itype());
+        // The unused mul goes, the div stays since it may trap
+        assert!(!live.clone().any(|itype| matches!(itype, IType::Mul { .. })));
+        assert!(live.clone().any(|itype| matches!(itype, IType::Div { .. })));
+        assert!(live.clone().any(|itype| matches!(itype, IType::Add { .. })));
+    }
+
+    #[test]
+    fn nested_while() {
+        let input = b"
+    main
+    var x, y, i, j;
+    {
+        let i <- call InputNum();
+        let x <- 0;
+        let y <- 0;
+        let j <- i;
+
+        while x < 10
+        do
+            let x <- x + 1;
+            let y <- j + 1;
+
+            while j < 10
+            do
+                let x <- j + 1;
+                let y <- i + 1;
+                let j <- i + 1;
+            od;
+
+            let i <- i + 1;
+        od;
+
+        call OutputNum(x);
+    }
+    .
+    ";
+        assert_matches_oracle(input, b"3");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn nested_while2() {
+        let input = b"
+    main
+    var x, y, i, j;
+    {
+        let i <- call InputNum();
+        let x <- 0;
+        let y <- 0;
+        let j <- i;
+
+        while x < 10
+        do
+            let x <- i + 1;
+            let y <- j + 1;
+
+            while j < 10
+            do
+                let x <- j + 1;
+                let y <- i + 1;
+                let j <- j + 1;
+            od;
+
+            let i <- i + 1;
+        od;
+
+        call OutputNum(x);
+    }
+    .
+";
+        assert_matches_oracle(input, b"3");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn nested_while_if() {
+        let input = b"
+    main
+    var i;
+    {
+        let i <- 0;
+        while i < 10 do
+            let i <- i + 1;
+            if i == 2 then
+                let i <- i + 1
+            fi
+        od;
+        call OutputNum(i)
+    }
+    .
+";
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn nested_if_while() {
+        let input = b"
+    main
+    var i;
+    {
+        let i <- 0;
+        if i == 2 then
+            while i < 10 do
+                let i <- i + 1;
+                let i <- i + 1
+            od;
+        fi;
+        call OutputNum(i)
+    }
+    .
+";
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn nested_big() {
+        let input = b"
+main
+var x,k,j,m;
+{
+let x <- call InputNum();
+let k <- call InputNum();
+while x < 10 do
+    let j <- 0;
+    let m <- 0;
+
+    let x <- x + 1;
+    if k > 5 then
+        while j < 15 do
+            let j <- j + 1;
+        od;
+        let k <- 0;
+    else
+        let k <- k + 1;
+        while m < 20 do
+            let m <- m * 2;
+        od;
+    fi;
+od;
+call OutputNum(x);
+call OutputNum(k);
+}
+.
+";
+        assert_matches_oracle(input, b"3 7");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn nested_ifs() {
+        let input = b"
+main
+var a, b;
+{
+    let a <- call InputNum();
+    let b <- call InputNum();
+    if a > 0 then
+        if b > 0 then
+            call OutputNum(0)
+        else
+            call OutputNum(1)
+        fi
+    else
+        if b > 0 then
+            call OutputNum(1)
+        else
+            call OutputNum(0)
+        fi
+    fi
+}.
+";
+        assert_matches_oracle(input, b"1 -1");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn test_cse() {
+        let input = b"
+main
+var a,b,c,d,e;
+{
+    let a <- call InputNum();
+    let b <- a;
+    let c <- b;
+    let d <- b + c;
+    let e <- a + b;
+    if a < 0 then
+        let d <-  d + e;
+        let a <- d
+    else
+        let d <- e
+    fi;
+    call OutputNum(a)
+}.
+";
+        assert_matches_oracle(input, b"4");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn copy_loop() {
+        let input = b"
+main
+var fa, fb, n, t;
+{
+    let fa <- 0;
+    let fb <- 1;
+    let t <- fa + fb;
+    let n <- call InputNum();
+    let t <- n - 1;
+    while n > 0 do
+        let t <- fb;
+        let fb <- fa + fb;
+        let fa <- t;
+        let n <- n - 1;
+    od;
+    call OutputNum(fa);
+    call OutputNewLine();
+}
+.";
+        assert_matches_oracle(input, b"10");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn complex_phi() {
+        let input = b"
+main
+var x,k,j,m;
+{
+let x <- call InputNum;
+let k <- call InputNum();
+while x < 10 do
+    let j <- 0;
+    let m <- 1;
+
+    let x <- x + 1;
+    call OutputNum(111);
+    call OutputNewLine();
+    if k > 5 then
+        while j < 15 do
+            let j <- j + k;
+            while m < 20 do
+                let k <- k * 1;
+                let m <- m + 1
+            od
+        od;
+        let k <- k - 1;
+        call OutputNum(k);
+        call OutputNewLine
+    else
+        while m < 20 do
+            let m <- m + k
+        od;
+        let k <- k + 1;
+        call OutputNum(k);
+        call OutputNewLine
+    fi;
+    call OutputNum(k);
+    call OutputNewLine
+od;
+call OutputNum(x);
+call OutputNewLine;
+call OutputNum(k);
+call OutputNewLine
+}
+.
+";
+        assert_matches_oracle(input, b"8 6");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn complex_if() {
+        let input = b"
+main
+var a, b, sum, i, j;
+{
+    let a <- call InputNum();
+    let b <- call InputNum();
+    let sum <- 0;
+    let i <- 1;
+    while i <= a do
+        let j <- 1;
+        while j <= b do
+            if i * j == 3 then
+                let sum <- sum + i * j;
+            fi;
+            if i * j == 6 then
+                let sum <- sum + i * j;
+            fi;
+            if i * j == 9 then
+                let sum <- sum + i * j;
+            fi;
+            let j <- j + 1;
+        od;
+        let i <- i + 1;
+    od;
+    call OutputNum(sum);
+}
+.
+";
+        assert_matches_oracle(input, b"3 3");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn basic_func() {
+        let input = b"
+main
+var x;
+
+function add(a, b); {
+    return a + b
+};
+
+void function println(n); {
+    call OutputNum(n);
+    call OutputNewLine
+};
+
+{
+    let x <- call add(1, 1);
+    call println(x)
+}
+.
+";
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn many_params() {
+        let input = b"
+main
+var x;
+
+function poly(a, b, c, d, e, f); {
+    return a - b * 2 + c * 3 - d * 4 + e * 5 - f * 6
+};
+
+function nested(a, b, c, d, e); {
+    return call poly(e, d, c, b, a, call poly(a, a, a, a, a, a))
+};
+
+{
+    let x <- call InputNum();
+    call OutputNum(call poly(x, 2, 3, 4, 5, 6));
+    call OutputNum(call nested(1, 2, 3, 4, x))
+}
+.
+";
+        assert_matches_oracle(input, b"7");
+
+        let ir = lower(input);
+        assert!(ir
+            .blocks()
+            .iter()
+            .flat_map(|block| block.iter())
+            .any(|&inst| ir.inst(inst).itype() == IType::GetPar { arg: 5 }));
+        assert!(ir.instructions().contains(": getpar6\n"));
+    }
+
+    #[test]
+    fn mutual_recursion() {
+        let input = b"
+main
+var x;
+
+function isEven(n); {
+    if n == 0 then return 1 fi;
+    return call isOdd(n - 1)
+};
+
+function isOdd(n); {
+    if n == 0 then return 0 fi;
+    return call isEven(n - 1)
+};
+
+{
+    let x <- call InputNum();
+    call OutputNum(call isEven(x));
+    call OutputNum(call isOdd(x))
+}
+.
+";
+        assert_matches_oracle(input, b"7");
+
+        let ir = lower(input);
+        let (_, is_odd) = ir
+            .functions()
+            .find(|&(name, _)| name == "isOdd")
+            .expect("Should exist");
+        assert!(ir
+            .blocks()
+            .iter()
+            .flat_map(|block| block.iter())
+            .any(|&inst| ir.inst(inst).itype() == IType::Jsr { block: is_odd }));
+    }
+
+    #[test]
+    fn loop_exits() {
+        let input = b"
+main
+var i, j, n, sum;
+{
+    let n <- call InputNum();
+    let sum <- 0;
+    for i <- 1 to n step 2 do
+        if i == 5 then continue fi;
+        if sum > 40 then break fi;
+        let j <- 0;
+        repeat
+            let j <- j + 1;
+            if j == 2 then continue fi;
+            let sum <- sum + i * j;
+            if sum > 30 then break fi
+        until j >= 3;
+        call OutputNum(sum)
+    od;
+    call OutputNum(i);
+    call OutputNum(sum)
+}
+.
+";
+        for stdin in [&b"0"[..], b"3", b"7", b"20"] {
+            assert_matches_oracle(input, stdin);
+        }
+
+        let ir = lower(input);
+        assert_phi_arity(&ir);
+    }
+
+    #[test]
+    fn for_bounds() {
+        let input = b"
+main
+var i, n, s;
+{
+    for i <- 1 to call InputNum() do
+        call OutputNum(i)
+    od;
+    for i <- 10 to 1 step 0 - 3 do
+        call OutputNum(i)
+    od;
+    let s <- call InputNum();
+    for i <- 4 to 0 step s do
+        call OutputNum(i)
+    od;
+    let n <- 3;
+    for i <- 1 to n do
+        let n <- n + 1
+    od;
+    call OutputNum(i)
+}
+.
+";
+        // The bound and the step are read once, a negative step counts down
+        let ir = lower(input);
+        let mut output = Vec::new();
+        ir.interpreter()
+            .run(&b"3 -2 100 100"[..], &mut output)
+            .expect("Should run");
+        assert_eq!(String::from_utf8_lossy(&output), "1 2 3 10 7 4 1 4 2 0 4 ");
+
+        for stdin in [&b"3 -2"[..], b"0 1", b"2 0"] {
+            assert_matches_oracle(input, stdin);
+        }
+    }
+
+    #[test]
+    fn short_circuit() {
+        let input = b"
+main
+var a, b, i, n;
+
+function check(x); {
+    call OutputNum(x);
+    return x
+};
+
+{
+    let a <- call InputNum();
+    let b <- call InputNum();
+    if a > 0 and call check(b) > 0 then call OutputNum(1) else call OutputNum(2) fi;
+    if not (a > 0 or call check(b) > 0) then call OutputNum(3) fi;
+    call OutputNewLine();
+    let i <- 0;
+    let n <- 0;
+    while i < 5 and not (i == a or i == b) do
+        let i <- i + 1
+    od;
+    repeat
+        let n <- n + 1
+    until n >= 4 or a > 0 and n == b;
+    call OutputNum(i);
+    call OutputNum(n)
+}
+.
+";
+        for stdin in [&b"0 0"[..], b"1 0", b"0 2", b"3 2", b"-1 -1"] {
+            assert_matches_oracle(input, stdin);
+        }
+
+        // The exits of the while condition land in one block
+        let ir = lower(input);
+        assert_phi_arity(&ir);
+        assert!(ir.blocks().iter().any(|block| block.get_preds().len() == 3));
+
+        // A negated relation branches on its opposite instead of adding blocks
+        let negated = lower(
+            b"main var a; { let a <- call InputNum(); if not a < 0 then call OutputNum(a) fi }.",
+        );
+        let plain = lower(
+            b"main var a; { let a <- call InputNum(); if a >= 0 then call OutputNum(a) fi }.",
+        );
+        assert_eq!(negated.blocks(), plain.blocks());
+        assert_eq!(negated.insts(), plain.insts());
+    }
+
+    #[test]
+    fn elsif_chain() {
+        let input = b"
+main
+var a, b, c;
+{
+    let a <- call InputNum();
+    let b <- 0;
+    let c <- a;
+    if a < 0 then
+        let b <- 1
+    elsif a == 0 then
+        let b <- 2;
+        let c <- 5
+    elsif a < 10 and a != 5 then
+        let b <- 3
+    elsif a == 5 then
+        let c <- 7
+    else
+        let b <- 4
+    fi;
+    call OutputNum(b);
+    call OutputNum(c)
+}
+.
+";
+        for stdin in [&b"-3"[..], b"0", b"5", b"7", b"12"] {
+            assert_matches_oracle(input, stdin);
+        }
+
+        // One join block after the chain, with a phi operand per arm
+        let ir = lower_with(input, Passes::level(0));
+        assert_phi_arity(&ir);
+        let join = ir
+            .blocks()
+            .iter()
+            .find(|block| block.get_preds().len() == 5)
+            .expect("Join block should exist");
+        let phis = join.get_phis(ir.insts());
+        assert_eq!(phis.len(), 2);
+        for phi in phis {
+            assert!(matches!(
+                ir.insts().get(phi).itype(),
+                IType::Phi { insts, .. } if insts.len() == 5
+            ));
+        }
+    }
+
+    #[test]
+    fn globals() {
+        let input = b"
+main
+var count, total, x;
+
+void function add(n); {
+    let count <- count + 1;
+    let total <- total + n
+};
+
+function twice(n); {
+    call add(n);
+    call add(n);
+    return count
+};
+
+function bump(); {
+    let x <- x + 1;
+    return x
+};
+
+void function reset(); var count; {
+    let count <- 5;
+    let total <- count
+};
+
+{
+    let count <- 0;
+    let total <- 0;
+    let x <- call InputNum();
+    while x > 0 do
+        call add(x);
+        let x <- x - 1
+    od;
+    call OutputNum(count);
+    call OutputNum(total);
+    call OutputNum(call twice(10));
+    call OutputNum(total);
+    let x <- 1;
+    call OutputNum(x + call bump());
+    call OutputNum(x * call bump());
+    if x < call bump() then
+        call OutputNum(x)
+    fi;
+    call reset();
+    call OutputNum(count);
+    call OutputNum(total)
+}
+.
+";
+        assert_matches_oracle(input, b"4");
+
+        let ir = lower(input);
+        let mut output = Vec::new();
+        ir.interpreter()
+            .run(&b"4"[..], &mut output)
+            .expect("Should run");
+        assert_eq!(String::from_utf8_lossy(&output), "4 10 6 30 3 6 4 6 5 ");
+
+        let ssa = ir.instructions();
+        assert!(ssa.contains(": load count\n"));
+        assert!(ssa.contains(": store total ("));
+        // reset's local count hides the global
+        let reset = ssa.split("function reset:").nth(1).expect("Should exist");
+        assert!(!reset.contains("count"));
+    }
+
+    #[test]
+    fn fib() {
+        let input = b"
+main
+var x;
+
+function fibonacci(n); {
+    if n <= 1 then
+        return n
+    fi;
+    return call fibonacci(n - 1) + call fibonacci(n - 2)
+};
+
+{
+    let x <- call InputNum;
+    let x <- call fibonacci(x);
+    call OutputNum(x);
+    call OutputNewLine
+}
+.
+";
+        assert_matches_oracle(input, b"10");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn complex_func() {
+        let input = b"
+main
+var a, six, g, red;
+
+
+function retfunc(x);
+{
+
+    return x;
+};
+
+void function emptyfunc();
+var trippy, ball, count;
+{
+
+    let trippy <- 25;
+    if 10 > 3 then
+    let trippy <- 40;
+    let ball <- 30;
+    else
+    let ball <- 4;
+    fi;
+
+    let count <- 0;
+    while count <= 5 do
+    let count <- count + 1;
+    od;
+
+
+let count <- 0;
+    while count <= 5 do
+    let count <- count + 1;
+    od;
+
+};
+
+
+
+
+{
+let red <- 12;
+
+
+
+
+call emptyfunc();
+
+
+let a <- 900;
+
+let six <- call retfunc(9);
+
+let g <- call retfunc(9 + 9);
+
+
+
+
+
+}
+.
+";
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn func_gcd() {
+        let input = b"
+main
+function mod(x,y); {
+    if y == 0 then
+        return x;
+    fi;
+    while x < 0 do
+        let x <- x + y;
+    od;
+    while x >= y do
+        let x <- x - y;
+    od;
+    return x;
+};
+function gcd(x,y); {
+    if x == 0 then
+        return y;
+    fi;
+    return call gcd(y, call mod(x,y));
+};
+{
+    call OutputNum(call gcd(110,121));
+    call OutputNewLine();
+}
+.
+";
+        assert_matches_oracle(input, b"");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+
+    #[test]
+    fn triple_while() {
+        let input = b"
+main
+var count, i, j, k, ilim, jlim, klim;
+{
+    let ilim <- call InputNum();
+    let jlim <- call InputNum();
+    let klim <- call InputNum();
+
+    let count <- 0;
+    let i <- 0;
+    while i < ilim do
+        let j <- 0;
+
+        while j < jlim do
+            let k <- 0;
+
+            while k < klim do
+                let k <- k + 1;
+
+                if (i + j + k) / 100 < 50 then
+                    let count <- count + 1;
+                fi;
+            od;
+
+            let j <- j + 1;
+        od;
+
+        let i <- i + 1;
+    od;
+
+    call OutputNum(count);
+}
+.
+";
+        assert_matches_oracle(input, b"2 3 4");
+        // dbg!(&ir);
+        // assert!(false);
+    }
+}
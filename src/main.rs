@@ -1,22 +1,264 @@
-use std::io::stdin;
+use std::io::{stdin, stdout, Read, Write};
 
-use parser::Parser;
+use cli::{Command, Emit, FuzzOptions, Options, USAGE};
+use project1::emulator::Emulator;
+use project1::fuzz::fuzz;
+use project1::json;
+use project1::lower::Lowerer;
+use project1::sema::Checker;
+use project1::snapshot::Snapshot;
+use project1::tokenizer_data::Token;
+use project1::{parse_ir, DiagnosticKind, Diagnostics, Parser, PassManager, Tokenizer};
 
-mod input;
-mod parser;
-mod parser_data;
-mod tokenizer;
-mod tokenizer_data;
+mod cli;
+
+// Pipeline repetitions allowed by --fixpoint
+const FIXPOINT_LIMIT: usize = 16;
 
 fn main() {
-    let mut parser = Parser::new(stdin().lock());
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let code = match cli::parse_args(&args) {
+        Ok(Command::Help) => {
+            print!("{}", USAGE);
+            0
+        }
+        Ok(Command::Run(path)) => run(&path),
+        Ok(Command::Compile(options)) => compile(&options),
+        Ok(Command::Fuzz(options)) => run_fuzz(&options),
+        Err(err) => {
+            eprintln!("Error: {}\n\n{}", err, USAGE);
+            2
+        }
+    };
+
+    std::process::exit(code);
+}
+
+fn run(path: &str) -> i32 {
+    let program = match std::fs::read(path) {
+        Ok(program) => program,
+        Err(err) => {
+            eprintln!("Error: Unable to read {}: {}", path, err);
+            return 1;
+        }
+    };
+
+    let mut emulator = Emulator::from_bytes(&program);
+    match emulator.run(stdin().lock(), stdout().lock()) {
+        Ok(stats) => {
+            eprintln!(
+                "[Stats] {} instructions, {} memory accesses ({} reads, {} writes)",
+                stats.insts,
+                stats.mem_accesses(),
+                stats.mem_reads,
+                stats.mem_writes
+            );
+            0
+        }
+        Err(err) => {
+            eprintln!("Runtime Error: {:?}", err);
+            1
+        }
+    }
+}
+
+fn run_fuzz(options: &FuzzOptions) -> i32 {
+    let failures = fuzz(options.seed, options.count, options.passes);
+    for failure in failures.iter() {
+        let divergence = &failure.divergence;
+        println!(
+            "// seed {}\n// stdin: {}\n// expected: {:?} {:?}\n// actual: {:?} {:?}\n{}",
+            failure.seed,
+            failure.program.stdin,
+            divergence.expected.output,
+            divergence.expected.error,
+            divergence.actual.output,
+            divergence.actual.error,
+            failure.program.source()
+        );
+    }
 
-    if let Some(()) = parser.computation() {
-        // println!("{:?}", parser);
-        parser.generate_graph("./tests/main.dot");
-        parser.generate_instructions("./tests/main.ssa");
+    eprintln!(
+        "[Fuzz] {} programs, {} divergences",
+        options.count,
+        failures.len()
+    );
+    if failures.is_empty() {
+        0
     } else {
-        println!("Syntax Error");
-        dbg!(&parser);
+        1
     }
 }
+
+fn compile(options: &Options) -> i32 {
+    let mut source = Vec::new();
+    let read = match &options.input {
+        Some(path) => std::fs::File::open(path).and_then(|mut file| file.read_to_end(&mut source)),
+        None => stdin().read_to_end(&mut source),
+    };
+    if let Err(err) = read {
+        eprintln!("Error: Unable to read input: {}", err);
+        return 1;
+    }
+
+    let mut ast = None;
+    let mut idents = Vec::new();
+    let mut ir = None;
+    if options.is_ir_input() {
+        match parse_ir(&String::from_utf8_lossy(&source)) {
+            Ok(module) => ir = Some(module),
+            Err(diagnostics) => {
+                report(options, &diagnostics);
+                return 1;
+            }
+        }
+    } else {
+        let mut parser = Parser::new(&source[..]);
+        let Some(computation) = parser.computation() else {
+            let mut diagnostics = Diagnostics::new();
+            diagnostics.push(
+                DiagnosticKind::Syntax,
+                "unexpected token",
+                Some(parser.span()),
+            );
+            report(options, &diagnostics);
+            return 1;
+        };
+
+        let mut diagnostics = Checker::new(parser.idents()).check(&computation);
+        if options.deny_warnings {
+            diagnostics.deny_warnings();
+        }
+        if !diagnostics.is_empty() {
+            report(options, &diagnostics);
+        }
+        if diagnostics.has_errors() {
+            return 1;
+        }
+
+        if options.emit.iter().any(Emit::needs_ir) || options.snapshots.is_some() {
+            let Some(module) = Lowerer::new(parser.idents()).computation(&computation) else {
+                let mut diagnostics = Diagnostics::new();
+                diagnostics.push(DiagnosticKind::Semantic, "unable to lower program", None);
+                report(options, &diagnostics);
+                return 1;
+            };
+            ir = Some(module);
+        }
+        ast = Some(computation);
+        idents = parser.idents().to_vec();
+    }
+
+    if let Some(module) = ir.as_mut() {
+        let mut manager = PassManager::from_names(&options.pipeline())
+            .expect("Pass names should be checked by the argument parser");
+        if options.fixpoint {
+            manager = manager.fixpoint(FIXPOINT_LIMIT);
+        }
+        for (pass, format) in options.dump_after.iter() {
+            manager = manager.dump_after(pass, *format);
+        }
+        manager = manager.record_snapshots(options.snapshots.is_some());
+        manager.run(module);
+
+        if let Some(stem) = &options.snapshots {
+            if let Err(err) = write_snapshots(manager.snapshots(), stem, options.snapshot_diff) {
+                eprintln!("Error: Unable to write snapshot: {}", err);
+                return 1;
+            }
+        }
+
+        for dump in manager.dumps() {
+            eprintln!("// after {} (run {})\n{}", dump.pass, dump.run, dump.data);
+        }
+        if options.time_passes {
+            eprint!("{}", manager.report());
+        }
+    }
+
+    for &emit in options.emit.iter() {
+        let data = match emit {
+            Emit::Tokens if options.json => (json::tokens(&source).pretty() + "\n").into_bytes(),
+            Emit::Ast if options.json => {
+                let ast = ast.as_ref().expect("AST should exist");
+                (json::ast(ast, &idents).pretty() + "\n").into_bytes()
+            }
+            Emit::Ssa if options.json => {
+                let module = ir.as_ref().expect("IR should exist");
+                (json::module(module).pretty() + "\n").into_bytes()
+            }
+            Emit::Tokens => tokens(&source),
+            Emit::Ast => format!("{:#?}\n", ast.as_ref().expect("AST should exist")).into_bytes(),
+            Emit::Dot => {
+                let module = ir.as_ref().expect("IR should exist");
+                (module.graph_with(&options.graph) + "\n").into_bytes()
+            }
+            Emit::Ssa => (ir.as_ref().expect("IR should exist").instructions() + "\n").into_bytes(),
+            Emit::Asm | Emit::Dlx => match ir.as_ref().expect("IR should exist").generate_dlx() {
+                Ok(program) if emit == Emit::Asm => program.generate_assembly().into_bytes(),
+                Ok(program) => program.generate_binary(),
+                Err(err) => {
+                    eprintln!("Codegen Error: {:?}", err);
+                    return 1;
+                }
+            },
+        };
+
+        let res = match options.output_path(emit) {
+            Some(path) => std::fs::write(path, data),
+            None => stdout().write_all(&data),
+        };
+        if let Err(err) = res {
+            eprintln!("Error: Unable to write output: {}", err);
+            return 1;
+        }
+    }
+
+    0
+}
+
+fn report(options: &Options, diagnostics: &Diagnostics) {
+    if options.json {
+        eprintln!("{}", json::diagnostics(diagnostics).pretty());
+    } else {
+        eprint!("{}", diagnostics);
+    }
+}
+
+fn write_snapshots(snapshots: &[Snapshot], stem: &str, diff: bool) -> std::io::Result<()> {
+    for (idx, snapshot) in snapshots.iter().enumerate() {
+        let prev = if diff && idx > 0 {
+            snapshots.get(idx - 1)
+        } else {
+            None
+        };
+
+        std::fs::write(
+            snapshot.file_name(stem, idx, "dot"),
+            snapshot.dot(prev) + "\n",
+        )?;
+        std::fs::write(
+            snapshot.file_name(stem, idx, "ssa"),
+            snapshot.ssa(prev) + "\n",
+        )?;
+    }
+    Ok(())
+}
+
+fn tokens(source: &[u8]) -> Vec<u8> {
+    let mut tokenizer = Tokenizer::new(source);
+    let mut data = String::new();
+
+    while let Some(token) = tokenizer.peek() {
+        let span = tokenizer.span();
+        let token = match token {
+            Token::Ident(id) => format!("Ident({})", tokenizer.get_var(id)),
+            token => format!("{:?}", token),
+        };
+        data += format!("{}:{}\t{}\n", span.line, span.col, token).as_str();
+        tokenizer.next();
+    }
+
+    data.into_bytes()
+}
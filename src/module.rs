@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    codegen::{CodeGen, CodegenError, DlxProgram},
+    interpreter::Interpreter,
+    parser_data::{Block, BlockList, IType, Inst, InstList},
+    tokenizer_data::Span,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum GraphView {
+    // Blocks with their instructions and control flow edges
+    #[default]
+    Cfg,
+    // Blocks only, with an edge from every block to its immediate dominator
+    Dominators,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphOptions {
+    pub view: GraphView,
+    // Whether to draw BB0 with the constants
+    pub consts: bool,
+}
+
+impl Default for GraphOptions {
+    fn default() -> Self {
+        GraphOptions {
+            view: GraphView::Cfg,
+            consts: true,
+        }
+    }
+}
+
+// SSA form of a whole program, produced by `Lowerer`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    idents: Vec<String>,
+    // func ident -> block id
+    func_map: BTreeMap<usize, usize>,
+    // var ident -> placeholder const for uninitialized reads
+    undef_map: BTreeMap<usize, usize>,
+    // inst -> source it was lowered from
+    spans: BTreeMap<usize, Span>,
+    pub(crate) blocks: BlockList,
+    pub(crate) insts: InstList,
+}
+
+impl Module {
+    pub fn new(
+        idents: Vec<String>,
+        func_map: BTreeMap<usize, usize>,
+        undef_map: BTreeMap<usize, usize>,
+        blocks: BlockList,
+        insts: InstList,
+    ) -> Module {
+        Module {
+            idents,
+            func_map,
+            undef_map,
+            spans: BTreeMap::new(),
+            blocks,
+            insts,
+        }
+    }
+
+    pub fn with_spans(mut self, spans: BTreeMap<usize, Span>) -> Self {
+        self.spans = spans;
+        self
+    }
+
+    pub fn idents(&self) -> &[String] {
+        &self.idents
+    }
+
+    pub fn insts(&self) -> &InstList {
+        &self.insts
+    }
+
+    pub fn blocks(&self) -> &BlockList {
+        &self.blocks
+    }
+
+    pub fn inst(&self, inst: usize) -> &Inst {
+        self.insts.get(inst)
+    }
+
+    pub fn block(&self, block: usize) -> &Block {
+        self.blocks.get(block)
+    }
+
+    pub fn span(&self, inst: usize) -> Option<Span> {
+        self.spans.get(&inst).copied()
+    }
+
+    // Name of the variable an uninitialized read placeholder stands for
+    pub fn undef_var(&self, inst: usize) -> Option<&str> {
+        self.undef_map
+            .iter()
+            .find(|(_, &undef)| undef == inst)
+            .map(|(&var, _)| self.idents[var].as_str())
+    }
+
+    // Instructions still placed in a block
+    pub fn live_inst_count(&self) -> usize {
+        self.blocks.iter().map(|block| block.iter().count()).sum()
+    }
+
+    // Printers and codegen expect every block to hold at least one instruction
+    pub fn fill_empty(&mut self) {
+        for block_id in 0..self.blocks.blocks.len() {
+            if self.blocks.get(block_id).get_first_inst().is_none() {
+                let inst = self.insts.add(IType::Empty, None, Some(block_id));
+                self.blocks.get_mut(block_id).add_inst(inst, &IType::Empty);
+            }
+        }
+    }
+
+    // Block 0 holds the constants, main starts right after it
+    pub fn main_block(&self) -> usize {
+        1
+    }
+
+    // (name, start block) of every user defined function
+    pub fn functions(&self) -> impl Iterator<Item = (&str, usize)> + '_ {
+        self.func_map
+            .iter()
+            .map(|(&id, &block)| (self.idents[id].as_str(), block))
+    }
+
+    pub fn get_var(&self, var: usize) -> String {
+        self.idents
+            .get(var)
+            .expect("Ident should exist")
+            .to_string()
+    }
+
+    pub fn graph(&self) -> String {
+        self.graph_with(&GraphOptions::default())
+    }
+
+    pub fn graph_with(&self, options: &GraphOptions) -> String {
+        self.write_graph(options, |block| match options.view {
+            GraphView::Cfg => block.generate_node(&self.insts, &self.blocks, &self.idents),
+            GraphView::Dominators => format!("bb{0} [shape=box, label=\"BB{0}\"];", block.id()),
+        })
+    }
+
+    // One cluster per function, `node` renders the node of a single block
+    pub(crate) fn write_graph(
+        &self,
+        options: &GraphOptions,
+        node: impl Fn(&Block) -> String,
+    ) -> String {
+        let mut data = "digraph G {\n".to_string();
+        let mut edges = Vec::new();
+
+        for (name, entry) in self.sections() {
+            if entry == 0 && !options.consts {
+                continue;
+            }
+
+            data += format!(
+                "\tsubgraph cluster_bb{} {{\n\t\tlabel=\"{}\";\n",
+                entry, name
+            )
+            .as_str();
+            for block in self.blocks.iter() {
+                if self.owner(block.id()) != entry {
+                    continue;
+                }
+                data += format!("\t\t{}\n", node(block)).as_str();
+
+                match options.view {
+                    GraphView::Cfg => edges.extend(block.generate_edges(&self.insts, &self.blocks)),
+                    GraphView::Dominators if options.consts || block.dom() != Some(0) => {
+                        edges.extend(block.generate_dom_edge())
+                    }
+                    GraphView::Dominators => {}
+                }
+            }
+            data += "\t}\n";
+        }
+
+        for edge in edges {
+            data += format!("\t{}\n", edge).as_str();
+        }
+        data += "}";
+        data
+    }
+
+    // (name, entry block) of the constants, main and every function
+    fn sections(&self) -> Vec<(String, usize)> {
+        let mut sections = vec![
+            ("const".to_string(), 0),
+            ("main".to_string(), self.main_block()),
+        ];
+        for (name, block) in self.functions() {
+            sections.push((name.to_string(), block));
+        }
+        sections
+    }
+
+    pub fn generate_graph(&self, output_file_path: &str) {
+        std::fs::write(output_file_path, self.graph()).expect("Unable to write file");
+    }
+
+    // Textual IR, read back by `ir_parser::parse_ir`
+    pub fn instructions(&self) -> String {
+        self.write_ir(|block| {
+            block
+                .iter()
+                .map(|&inst| format!("  {}", self.ir_inst(inst)))
+                .collect()
+        })
+    }
+
+    // Lays out the const block, main and every function with block headers,
+    // `lines` renders the body of each block
+    pub(crate) fn write_ir(&self, lines: impl Fn(&Block) -> Vec<String>) -> String {
+        self.sections()
+            .iter()
+            .map(|(name, entry)| {
+                let header = if *entry > self.main_block() {
+                    format!("function {}:", name)
+                } else {
+                    format!("{}:", name)
+                };
+                let mut data = vec![header];
+                for block in self.blocks.iter() {
+                    if self.owner(block.id()) == *entry {
+                        data.push(self.ir_block_header(block));
+                        data.extend(lines(block));
+                    }
+                }
+                data.join("\n")
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    // Entry block of the function a block belongs to
+    pub(crate) fn owner(&self, mut block: usize) -> usize {
+        while let Some(dom) = self.blocks.get(block).dom() {
+            if dom == 0 {
+                break;
+            }
+            block = dom;
+        }
+        block
+    }
+
+    fn ir_block_header(&self, block: &Block) -> String {
+        let mut data = format!("bb{}:", block.id());
+        if let Some(dom) = block.dom() {
+            data += format!(" dom bb{}", dom).as_str();
+        }
+        if !block.get_preds().is_empty() {
+            let preds = block
+                .get_preds()
+                .iter()
+                .map(|pred| format!("bb{}", pred))
+                .collect::<Vec<_>>();
+            data += format!(" preds {}", preds.join(", ")).as_str();
+        }
+        data += match block.get_next() {
+            (Some(fall_through), Some(follow)) => format!(" -> bb{}, bb{}", fall_through, follow),
+            (Some(fall_through), None) => format!(" -> bb{}", fall_through),
+            (None, Some(follow)) => format!(" -> _, bb{}", follow),
+            (None, None) => "".to_string(),
+        }
+        .as_str();
+        data
+    }
+
+    pub fn ir_inst(&self, inst: usize) -> String {
+        let itype = self.insts.get(inst).itype();
+        let itype_str = match itype {
+            IType::Const(val) => match self.undef_var(inst) {
+                Some(var) => format!("undef {}", var),
+                None => format!("const #{}", val),
+            },
+            IType::Add { inst1, inst2 } => format!("add ({}) ({})", inst1.0, inst2.0),
+            IType::Sub { inst1, inst2 } => format!("sub ({}) ({})", inst1.0, inst2.0),
+            IType::Mul { inst1, inst2 } => format!("mul ({}) ({})", inst1.0, inst2.0),
+            IType::Div { inst1, inst2 } => format!("div ({}) ({})", inst1.0, inst2.0),
+            IType::Cmp { inst1, inst2 } => format!("cmp ({}) ({})", inst1.0, inst2.0),
+            IType::Phi { ref insts, var } => format!(
+                "({}) phi {}",
+                self.idents[var],
+                insts
+                    .iter()
+                    .map(|inst| format!("({})", inst.0))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            IType::End => "end".to_string(),
+            IType::Bra { block } => format!("bra bb{}", block),
+            IType::Bne { inst, block }
+            | IType::Beq { inst, block }
+            | IType::Ble { inst, block }
+            | IType::Blt { inst, block }
+            | IType::Bge { inst, block }
+            | IType::Bgt { inst, block } => match block {
+                Some(block) => format!("{} ({}) bb{}", itype.name(), inst, block),
+                None => format!("{} ({})", itype.name(), inst),
+            },
+            IType::Jsr { block } => format!("jsr bb{}", block),
+            IType::Ret { inst } => match inst {
+                Some(inst) => format!("ret ({})", inst.0),
+                None => "ret".to_string(),
+            },
+            IType::GetPar { .. } => itype.mnemonic(),
+            IType::SetPar { inst, .. } => format!("{} ({})", itype.mnemonic(), inst.0),
+            IType::Load { var } => format!("load {}", self.idents[var]),
+            IType::Store { var, inst } => format!("store {} ({})", self.idents[var], inst.0),
+            IType::Read => "read".to_string(),
+            IType::Write { inst } => format!("write ({})", inst.0),
+            IType::WriteNL => "writeNL".to_string(),
+            IType::Empty => "empty".to_string(),
+        };
+
+        format!("{}: {}", inst, itype_str)
+    }
+
+    pub fn generate_instructions(&self, output_file_path: &str) {
+        std::fs::write(output_file_path, self.instructions()).expect("Unable to write file");
+    }
+
+    pub fn generate_dlx(&self) -> Result<DlxProgram, CodegenError> {
+        let funcs = self
+            .functions()
+            .map(|(name, block)| (name.to_string(), block))
+            .collect();
+
+        CodeGen::new(&self.insts, &self.blocks, funcs).generate()
+    }
+
+    pub fn interpreter(&self) -> Interpreter<'_> {
+        let undef = self
+            .undef_map
+            .iter()
+            .map(|(&var, &inst)| (inst, var))
+            .collect();
+
+        Interpreter::new(&self.insts, &self.blocks, undef)
+    }
+}
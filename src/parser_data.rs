@@ -1,11 +1,4 @@
-#![allow(dead_code)]
-use std::{
-    collections::{BTreeMap, VecDeque},
-    fmt::Debug,
-    io::BufRead,
-};
-
-use crate::tokenizer::Tokenizer;
+use std::{collections::BTreeMap, fmt::Debug};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum OpType {
@@ -16,10 +9,10 @@ pub enum OpType {
 }
 pub struct InvalidITypeError;
 
-impl TryFrom<IType> for OpType {
+impl TryFrom<&IType> for OpType {
     type Error = InvalidITypeError;
 
-    fn try_from(value: IType) -> Result<Self, Self::Error> {
+    fn try_from(value: &IType) -> Result<Self, Self::Error> {
         match value {
             IType::Add { .. } => Ok(OpType::Add),
             IType::Sub { .. } => Ok(OpType::Sub),
@@ -30,7 +23,7 @@ impl TryFrom<IType> for OpType {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone)]
 pub enum IType {
     Const(isize),
 
@@ -55,9 +48,9 @@ pub enum IType {
         inst2: (usize, Option<usize>),
     },
 
+    // One operand per predecessor, in the order of `Block::get_preds`
     Phi {
-        inst1: (usize, Option<usize>),
-        inst2: (usize, Option<usize>),
+        insts: Vec<(usize, Option<usize>)>,
         var: usize,
     },
 
@@ -97,16 +90,22 @@ pub enum IType {
     Ret {
         inst: Option<(usize, Option<usize>)>,
     },
-    GetPar1,
-    GetPar2,
-    GetPar3,
-    SetPar1 {
-        inst: (usize, Option<usize>),
+    // Parameter `arg` of the current function, counted from 0
+    GetPar {
+        arg: usize,
     },
-    SetPar2 {
+    // Argument `arg` of the next call, counted from 0
+    SetPar {
+        arg: usize,
         inst: (usize, Option<usize>),
     },
-    SetPar3 {
+
+    // Globals kept in memory across calls
+    Load {
+        var: usize,
+    },
+    Store {
+        var: usize,
         inst: (usize, Option<usize>),
     },
 
@@ -118,37 +117,64 @@ pub enum IType {
     WriteNL,
 
     // Placeholder
-    Assignment {
-        var: usize,
-        dep_var: Option<usize>,
-    },
     Empty,
 }
 
 impl IType {
-    pub fn branch_block(&self, block: usize) -> Self {
+    // Opcode as written in the textual IR
+    pub fn name(&self) -> &'static str {
         match self {
-            &IType::Beq { inst, .. } => IType::Beq {
+            IType::Const(_) => "const",
+            IType::Add { .. } => "add",
+            IType::Sub { .. } => "sub",
+            IType::Mul { .. } => "mul",
+            IType::Div { .. } => "div",
+            IType::Cmp { .. } => "cmp",
+            IType::Phi { .. } => "phi",
+            IType::End => "end",
+            IType::Bra { .. } => "bra",
+            IType::Bne { .. } => "bne",
+            IType::Beq { .. } => "beq",
+            IType::Ble { .. } => "ble",
+            IType::Blt { .. } => "blt",
+            IType::Bge { .. } => "bge",
+            IType::Bgt { .. } => "bgt",
+            IType::Jsr { .. } => "jsr",
+            IType::Ret { .. } => "ret",
+            IType::GetPar { .. } => "getpar",
+            IType::SetPar { .. } => "setpar",
+            IType::Load { .. } => "load",
+            IType::Store { .. } => "store",
+            IType::Read => "read",
+            IType::Write { .. } => "write",
+            IType::WriteNL => "writeNL",
+            IType::Empty => "empty",
+        }
+    }
+
+    pub fn branch_block(&self, block: usize) -> Self {
+        match *self {
+            IType::Beq { inst, .. } => IType::Beq {
                 inst,
                 block: Some(block),
             },
-            &IType::Bne { inst, .. } => IType::Bne {
+            IType::Bne { inst, .. } => IType::Bne {
                 inst,
                 block: Some(block),
             },
-            &IType::Bgt { inst, .. } => IType::Bgt {
+            IType::Bgt { inst, .. } => IType::Bgt {
                 inst,
                 block: Some(block),
             },
-            &IType::Bge { inst, .. } => IType::Bge {
+            IType::Bge { inst, .. } => IType::Bge {
                 inst,
                 block: Some(block),
             },
-            &IType::Blt { inst, .. } => IType::Blt {
+            IType::Blt { inst, .. } => IType::Blt {
                 inst,
                 block: Some(block),
             },
-            &IType::Ble { inst, .. } => IType::Ble {
+            IType::Ble { inst, .. } => IType::Ble {
                 inst,
                 block: Some(block),
             },
@@ -156,189 +182,68 @@ impl IType {
         }
     }
 
-    pub fn into(self, var: usize, phi: usize) -> IType {
+    // Opcode with the argument number, `getpar1` for the first parameter
+    pub fn mnemonic(&self) -> String {
         match self {
-            IType::Phi {
-                inst1,
-                inst2,
-                var: v,
-            } => {
-                let inst1 = if inst1.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst1
-                };
-
-                let inst2 = if inst2.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst2
-                };
-
-                IType::Phi {
-                    inst1,
-                    inst2,
-                    var: v,
-                }
-            }
-            IType::Add { inst1, inst2 } => {
-                let inst1 = if inst1.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst1
-                };
-
-                let inst2 = if inst2.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst2
-                };
-
-                IType::Add { inst1, inst2 }
+            IType::GetPar { arg } | IType::SetPar { arg, .. } => {
+                format!("{}{}", self.name(), arg + 1)
             }
-            IType::Sub { inst1, inst2 } => {
-                let inst1 = if inst1.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst1
-                };
-
-                let inst2 = if inst2.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst2
-                };
-
-                IType::Sub { inst1, inst2 }
-            }
-            IType::Mul { inst1, inst2 } => {
-                let inst1 = if inst1.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst1
-                };
-
-                let inst2 = if inst2.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst2
-                };
-
-                IType::Mul { inst1, inst2 }
-            }
-            IType::Div { inst1, inst2 } => {
-                let inst1 = if inst1.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst1
-                };
-
-                let inst2 = if inst2.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst2
-                };
-
-                IType::Div { inst1, inst2 }
-            }
-            IType::Cmp { inst1, inst2 } => {
-                let inst1 = if inst1.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst1
-                };
-
-                let inst2 = if inst2.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst2
-                };
+            _ => self.name().to_string(),
+        }
+    }
 
-                IType::Cmp { inst1, inst2 }
-            }
-            IType::Ret { inst } => {
-                let inst = if inst.map(|e| e.1) == Some(Some(var)) {
-                    Some((phi, Some(var)))
-                } else {
-                    inst
-                };
-                IType::Ret { inst }
-            }
-            IType::SetPar1 { inst } => {
-                let inst = if inst.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst
-                };
-                IType::SetPar1 { inst }
-            }
-            IType::SetPar2 { inst } => {
-                let inst = if inst.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst
-                };
-                IType::SetPar2 { inst }
-            }
-            IType::SetPar3 { inst } => {
-                let inst = if inst.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst
-                };
-                IType::SetPar3 { inst }
-            }
-            IType::Write { inst } => {
-                let inst = if inst.1 == Some(var) {
-                    (phi, Some(var))
-                } else {
-                    inst
-                };
-                IType::Write { inst }
+    // Instructions read by this one
+    pub fn operands(&self) -> Vec<usize> {
+        match *self {
+            IType::Phi { ref insts, .. } => insts.iter().map(|inst| inst.0).collect(),
+            IType::Add { inst1, inst2 }
+            | IType::Sub { inst1, inst2 }
+            | IType::Mul { inst1, inst2 }
+            | IType::Div { inst1, inst2 }
+            | IType::Cmp { inst1, inst2 } => vec![inst1.0, inst2.0],
+            IType::Bne { inst, .. }
+            | IType::Beq { inst, .. }
+            | IType::Ble { inst, .. }
+            | IType::Blt { inst, .. }
+            | IType::Bge { inst, .. }
+            | IType::Bgt { inst, .. } => vec![inst],
+            IType::Ret { inst } => inst.map(|inst| inst.0).into_iter().collect(),
+            IType::SetPar { inst, .. } | IType::Store { inst, .. } | IType::Write { inst } => {
+                vec![inst.0]
             }
-            itype @ _ => itype,
+            _ => vec![],
         }
     }
 
-    fn update_phi(self, inst: usize, frame_status: FrameStatus) -> IType {
-        match self {
-            IType::Phi {
-                inst1, inst2, var, ..
-            } => match frame_status {
-                FrameStatus::FallThrough => IType::Phi {
-                    inst1: (inst, inst1.1),
-                    inst2,
-                    var,
-                },
-                FrameStatus::Follow => IType::Phi {
-                    inst1,
-                    inst2: (inst, inst2.1),
-                    var,
-                },
-            },
-            // IType::Phi {
-            //     inst1: (inst, inst1.1),
-            //     inst2,
-            //     var,
-            // },
-            _ => unreachable!(),
-        }
+    // Whether removing this instruction would change observable behaviour
+    pub fn has_side_effects(&self) -> bool {
+        !matches!(
+            self,
+            IType::Const(_)
+                | IType::Add { .. }
+                | IType::Sub { .. }
+                | IType::Mul { .. }
+                | IType::Cmp { .. }
+                | IType::Phi { .. }
+                | IType::GetPar { .. }
+                | IType::Load { .. }
+        )
     }
 
     pub fn update_inst(self, from_inst: usize, to_inst: usize) -> IType {
         match self {
-            IType::Phi { inst1, inst2, var } => {
-                let inst1 = if inst1.0 == from_inst {
-                    (to_inst, inst1.1)
-                } else {
-                    inst1
-                };
-                let inst2 = if inst2.0 == from_inst {
-                    (to_inst, inst2.1)
-                } else {
-                    inst2
-                };
-                IType::Phi { inst1, inst2, var }
+            IType::Phi { insts, var } => {
+                let insts = insts
+                    .into_iter()
+                    .map(|inst| {
+                        if inst.0 == from_inst {
+                            (to_inst, inst.1)
+                        } else {
+                            inst
+                        }
+                    })
+                    .collect();
+                IType::Phi { insts, var }
             }
             IType::Add { inst1, inst2 } => {
                 let inst1 = if inst1.0 == from_inst {
@@ -415,29 +320,21 @@ impl IType {
                 });
                 IType::Ret { inst }
             }
-            IType::SetPar1 { inst } => {
-                let inst = if inst.0 == from_inst {
-                    (to_inst, inst.1)
-                } else {
-                    inst
-                };
-                IType::SetPar1 { inst }
-            }
-            IType::SetPar2 { inst } => {
+            IType::SetPar { arg, inst } => {
                 let inst = if inst.0 == from_inst {
                     (to_inst, inst.1)
                 } else {
                     inst
                 };
-                IType::SetPar2 { inst }
+                IType::SetPar { arg, inst }
             }
-            IType::SetPar3 { inst } => {
+            IType::Store { var, inst } => {
                 let inst = if inst.0 == from_inst {
                     (to_inst, inst.1)
                 } else {
                     inst
                 };
-                IType::SetPar3 { inst }
+                IType::Store { var, inst }
             }
             IType::Write { inst } => {
                 let inst = if inst.0 == from_inst {
@@ -447,7 +344,7 @@ impl IType {
                 };
                 IType::Write { inst }
             }
-            itype @ _ => itype,
+            itype => itype,
         }
     }
 }
@@ -517,14 +414,11 @@ impl PartialEq for IType {
                     false
                 }
             }
-            IType::Phi { inst1, inst2, var } => {
-                if let IType::Phi {
-                    inst1: i1,
-                    inst2: i2,
-                    var: v,
-                } = other
-                {
-                    inst1 == i1 && inst2 == i2 && var == v
+            IType::Phi { insts, var } => {
+                if let IType::Phi { insts: i, var: v } = other {
+                    insts.len() == i.len()
+                        && insts.iter().zip(i).all(|(inst, i)| inst.0 == i.0)
+                        && var == v
                 } else {
                     false
                 }
@@ -593,26 +487,18 @@ impl PartialEq for IType {
                     false
                 }
             }
-            IType::GetPar1 => matches!(other, IType::GetPar1),
-            IType::GetPar2 => matches!(other, IType::GetPar2),
-            IType::GetPar3 => matches!(other, IType::GetPar3),
-            IType::SetPar1 { inst } => {
-                if let IType::SetPar1 { inst: i } = other {
-                    inst.0 == i.0
+            IType::GetPar { arg } => matches!(other, IType::GetPar { arg: a } if arg == a),
+            IType::SetPar { arg, inst } => {
+                if let IType::SetPar { arg: a, inst: i } = other {
+                    arg == a && inst.0 == i.0
                 } else {
                     false
                 }
             }
-            IType::SetPar2 { inst } => {
-                if let IType::SetPar2 { inst: i } = other {
-                    inst.0 == i.0
-                } else {
-                    false
-                }
-            }
-            IType::SetPar3 { inst } => {
-                if let IType::SetPar3 { inst: i } = other {
-                    inst.0 == i.0
+            IType::Load { var } => matches!(other, IType::Load { var: v } if var == v),
+            IType::Store { var, inst } => {
+                if let IType::Store { var: v, inst: i } = other {
+                    var == v && inst.0 == i.0
                 } else {
                     false
                 }
@@ -626,20 +512,7 @@ impl PartialEq for IType {
                 }
             }
             IType::WriteNL => matches!(other, IType::WriteNL),
-            IType::Assignment { var, .. } => {
-                if let IType::Assignment { var: v, .. } = other {
-                    var == v
-                } else {
-                    false
-                }
-            }
-            IType::Empty => {
-                if let IType::Empty = other {
-                    true
-                } else {
-                    false
-                }
-            }
+            IType::Empty => matches!(other, IType::Empty),
         }
     }
 }
@@ -671,155 +544,128 @@ impl Inst {
     }
 
     pub fn itype(&self) -> IType {
-        self.itype
+        self.itype.clone()
     }
 
     pub fn block(&self) -> Option<usize> {
         self.block
     }
 
-    pub fn set_fall_through(&mut self, block: usize) {
-        if let IType::Phi {
-            inst1, inst2, var, ..
-        } = self.itype
-        {
-            self.itype = IType::Phi {
-                inst1: (block, inst1.1),
-                inst2,
-                var,
-            }
-        } else {
-            unreachable!()
+    pub fn set_jsr_target(&mut self, block: usize) {
+        if let IType::Jsr { .. } = self.itype {
+            self.itype = IType::Jsr { block };
         }
     }
 
-    pub fn set_follow(&mut self, block: usize) {
-        if let IType::Phi {
-            inst1, inst2, var, ..
-        } = self.itype
-        {
-            self.itype = IType::Phi {
-                inst1,
-                inst2: (block, inst2.1),
-                var,
-            }
-        } else {
-            unreachable!()
+    // Operands for predecessors not seen yet point at the phi itself
+    pub fn set_phi_operand(&mut self, pred_idx: usize, inst: usize) {
+        let id = self.id;
+        let IType::Phi { insts, var } = &mut self.itype else {
+            unreachable!("Should be a phi")
+        };
+        if insts.len() <= pred_idx {
+            insts.resize(pred_idx + 1, (id, Some(*var)));
         }
+        insts[pred_idx].0 = inst;
     }
 
-    pub fn update_phi(&mut self, var: usize, phi: usize) {
-        self.itype = self.itype.into(var, phi);
+    pub fn get_phi_operand(&self, pred_idx: usize) -> usize {
+        match &self.itype {
+            IType::Phi { insts, .. } => insts[pred_idx].0,
+            _ => unreachable!(),
+        }
     }
 
-    fn generate_graph<R: BufRead>(
-        &self,
-        inst_list: &InstList,
-        block_list: &BlockList,
-        tokenizer: &Tokenizer<R>,
-    ) -> String {
-        let itype_str = match self.itype {
+    pub fn generate_graph(&self, block_list: &BlockList, idents: &[String]) -> String {
+        let itype_str = match self.itype() {
             IType::Const(val) => format!("const #{}", val),
-            IType::Add { inst1, inst2 } => format!("add ({}) ({})", inst1.0, inst2.0),
-            IType::Sub { inst1, inst2 } => format!("sub ({}) ({})", inst1.0, inst2.0),
-            IType::Mul { inst1, inst2 } => format!("mul ({}) ({})", inst1.0, inst2.0),
-            IType::Div { inst1, inst2 } => format!("div ({}) ({})", inst1.0, inst2.0),
-            IType::Cmp { inst1, inst2 } => format!("cmp ({}) ({})", inst1.0, inst2.0),
-            IType::Phi { inst1, inst2, var } => {
-                format!(
-                    "({}) phi ({}) ({})",
-                    tokenizer.get_var(var),
-                    inst1.0,
-                    inst2.0
-                )
-            }
+            IType::Add { inst1, inst2 }
+            | IType::Sub { inst1, inst2 }
+            | IType::Mul { inst1, inst2 }
+            | IType::Div { inst1, inst2 }
+            | IType::Cmp { inst1, inst2 } => format!(
+                "{} {} {}",
+                self.itype.name(),
+                operand_label(inst1, idents),
+                operand_label(inst2, idents)
+            ),
+            IType::Phi { insts, var } => format!(
+                "({}) phi {}",
+                idents[var],
+                insts
+                    .into_iter()
+                    .map(|inst| operand_label(inst, idents))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
             IType::End => "end".to_string(),
             IType::Bra { block } => {
-                format!(
-                    "bra ({})",
-                    block_list.get(block).get_first_inst(inst_list).unwrap()
-                )
+                format!("bra ({})", block_list.get(block).get_first_inst().unwrap())
             }
             IType::Bne { inst, block } => format!(
                 "bne ({}) ({})",
                 inst,
-                block_list
-                    .get(block.unwrap())
-                    .get_first_inst(inst_list)
-                    .unwrap()
+                block_list.get(block.unwrap()).get_first_inst().unwrap()
             ),
             IType::Beq { inst, block } => format!(
                 "beq ({}) ({})",
                 inst,
-                block_list
-                    .get(block.unwrap())
-                    .get_first_inst(inst_list)
-                    .unwrap()
+                block_list.get(block.unwrap()).get_first_inst().unwrap()
             ),
             IType::Ble { inst, block } => format!(
                 "ble ({}) ({})",
                 inst,
-                block_list
-                    .get(block.unwrap())
-                    .get_first_inst(inst_list)
-                    .unwrap()
+                block_list.get(block.unwrap()).get_first_inst().unwrap()
             ),
             IType::Blt { inst, block } => format!(
                 "blt ({}) ({})",
                 inst,
-                block_list
-                    .get(block.unwrap())
-                    .get_first_inst(inst_list)
-                    .unwrap()
+                block_list.get(block.unwrap()).get_first_inst().unwrap()
             ),
             IType::Bge { inst, block } => format!(
                 "bge ({}) ({})",
                 inst,
-                block_list
-                    .get(block.unwrap())
-                    .get_first_inst(inst_list)
-                    .unwrap()
+                block_list.get(block.unwrap()).get_first_inst().unwrap()
             ),
             IType::Bgt { inst, block } => format!(
                 "bgt ({}) ({})",
                 inst,
-                block_list
-                    .get(block.unwrap())
-                    .get_first_inst(inst_list)
-                    .unwrap()
+                block_list.get(block.unwrap()).get_first_inst().unwrap()
             ),
             IType::Jsr { block } => {
-                format!(
-                    "jsr {}",
-                    block_list.get(block).get_first_inst(inst_list).unwrap()
-                )
+                format!("jsr {}", block_list.get(block).get_first_inst().unwrap())
             }
             IType::Ret { inst } => match inst {
-                Some(inst) => format!("ret ({})", inst.0),
+                Some(inst) => format!("ret {}", operand_label(inst, idents)),
                 None => "ret".to_string(),
             },
-            IType::GetPar1 => "getpar1".to_string(),
-            IType::GetPar2 => "getpar2".to_string(),
-            IType::GetPar3 => "getpar3".to_string(),
-            IType::SetPar1 { inst } => format!("setpar1 ({})", inst.0),
-            IType::SetPar2 { inst } => format!("setpar2 ({})", inst.0),
-            IType::SetPar3 { inst } => format!("setpar3 ({})", inst.0),
+            IType::GetPar { .. } => self.itype.mnemonic(),
+            IType::SetPar { inst, .. } => {
+                format!("{} {}", self.itype.mnemonic(), operand_label(inst, idents))
+            }
+            IType::Load { var } => format!("load {}", idents[var]),
+            IType::Store { var, inst } => {
+                format!("store {} {}", idents[var], operand_label(inst, idents))
+            }
             IType::Read => "read".to_string(),
-            IType::Write { inst } => format!("write ({})", inst.0),
+            IType::Write { inst } => format!("write {}", operand_label(inst, idents)),
             IType::WriteNL => "writeNL".to_string(),
             IType::Empty => r"\<empty\>".to_string(),
-            IType::Assignment { .. } => unimplemented!(),
         };
 
         format!("{}: {}", self.id, itype_str)
     }
 
-    pub fn propagate_phi(&mut self, inst: usize, frame_status: FrameStatus) {
-        self.itype = self.itype.update_phi(inst, frame_status);
+    fn update_inst(&mut self, from_inst: usize, to_inst: usize) {
+        self.itype = self.itype().update_inst(from_inst, to_inst);
     }
+}
 
-    fn update_inst(&mut self, from_inst: usize, to_inst: usize) {
-        self.itype = self.itype.update_inst(from_inst, to_inst);
+// `(3:a)` when the value was read through variable `a`
+fn operand_label(inst: (usize, Option<usize>), idents: &[String]) -> String {
+    match inst.1 {
+        Some(var) => format!("({}:{})", inst.0, idents[var]),
+        None => format!("({})", inst.0),
     }
 }
 
@@ -829,10 +675,15 @@ pub struct Block {
     dom: Option<usize>,
     pub insts: Vec<usize>,
     next: (Option<usize>, Option<usize>),
-    // inst, opt<var>
+    // ordered by phi operand
+    preds: Vec<usize>,
+    // Definitions made in this block: inst, opt<var>
     pub var_map: BTreeMap<usize, Option<(usize, Option<usize>)>>,
     op_map: BTreeMap<OpType, usize>,
-    phis: BTreeMap<usize, usize>,
+    // Set once all predecessors are known
+    sealed: bool,
+    // var -> phi, operands are filled in when the block is sealed
+    incomplete_phis: BTreeMap<usize, usize>,
 }
 
 impl Block {
@@ -842,9 +693,11 @@ impl Block {
             dom: None,
             insts: Vec::new(),
             next: (None, None),
+            preds: Vec::new(),
             var_map: BTreeMap::new(),
             op_map: BTreeMap::new(),
-            phis: BTreeMap::new(),
+            sealed: false,
+            incomplete_phis: BTreeMap::new(),
         }
     }
 
@@ -854,41 +707,83 @@ impl Block {
             dom: Some(prev.id),
             insts: Vec::new(),
             next: (None, None),
-            var_map: prev.var_map.clone(),
+            preds: Vec::new(),
+            var_map: BTreeMap::new(),
             op_map: prev.op_map.clone(),
-            phis: BTreeMap::new(),
+            sealed: false,
+            incomplete_phis: BTreeMap::new(),
         }
     }
 
     pub fn get_var_inst(&self, id: usize) -> Option<usize> {
-        self.var_map
-            .get(&id)
-            .expect("Var should exist")
-            .map(|inst| inst.0.clone())
+        self.var_map.get(&id).copied().flatten().map(|inst| inst.0)
     }
 
-    pub fn get_dom_inst(&self, itype: IType) -> Option<usize> {
-        self.op_map
-            .get(&OpType::try_from(itype).ok()?)
-            .map(|inst| inst.clone())
+    pub fn get_dom_inst(&self, itype: &IType) -> Option<usize> {
+        self.op_map.get(&OpType::try_from(itype).ok()?).copied()
     }
 
     pub fn get_last_inst(&self) -> Option<usize> {
         self.insts.last().cloned()
     }
 
-    pub fn get_first_inst(&self, inst_list: &InstList) -> Option<usize> {
-        self.insts
-            .iter()
-            .filter(|&inst_id| !matches!(inst_list.get(*inst_id).itype(), IType::Assignment { .. }))
-            .next()
-            .cloned()
+    pub fn get_first_inst(&self) -> Option<usize> {
+        self.insts.first().copied()
     }
 
     pub fn get_next(&self) -> (Option<usize>, Option<usize>) {
         self.next
     }
 
+    pub fn get_preds(&self) -> &[usize] {
+        &self.preds
+    }
+
+    pub fn add_pred(&mut self, block: usize) {
+        self.preds.push(block);
+    }
+
+    pub fn get_terminator(&self, inst_list: &InstList) -> Option<usize> {
+        self.insts
+            .iter()
+            .rev()
+            .find(|&&inst_id| !matches!(inst_list.get(inst_id).itype(), IType::Empty))
+            .copied()
+    }
+
+    pub fn get_succs(&self, inst_list: &InstList) -> Vec<usize> {
+        let terminator = self
+            .get_terminator(inst_list)
+            .map(|inst| inst_list.get(inst).itype());
+
+        match terminator {
+            Some(IType::Ret { .. } | IType::End) => vec![],
+            Some(IType::Bra { block }) => vec![block],
+            Some(
+                IType::Beq { block, .. }
+                | IType::Bne { block, .. }
+                | IType::Ble { block, .. }
+                | IType::Blt { block, .. }
+                | IType::Bge { block, .. }
+                | IType::Bgt { block, .. },
+            ) => self
+                .next
+                .0
+                .into_iter()
+                .chain(block.or(self.next.1))
+                .collect(),
+            _ => self.next.0.into_iter().collect(),
+        }
+    }
+
+    pub fn get_phis(&self, inst_list: &InstList) -> Vec<usize> {
+        self.insts
+            .iter()
+            .filter(|&&inst_id| matches!(inst_list.get(inst_id).itype(), IType::Phi { .. }))
+            .copied()
+            .collect()
+    }
+
     pub fn assign_var(&mut self, var: usize, inst: usize, dep_var: Option<usize>) {
         self.var_map.insert(var, Some((inst, dep_var)));
     }
@@ -897,7 +792,7 @@ impl Block {
         self.var_map.insert(var, None);
     }
 
-    pub fn add_inst(&mut self, inst: usize, itype: IType) -> usize {
+    pub fn add_inst(&mut self, inst: usize, itype: &IType) -> usize {
         self.insts.push(inst);
         if let Ok(optype) = OpType::try_from(itype) {
             self.op_map.insert(optype, inst);
@@ -922,60 +817,92 @@ impl Block {
         self.dom
     }
 
-    pub fn get_phi(&self, var: usize) -> Option<usize> {
-        self.phis.get(&var).copied()
+    pub fn is_sealed(&self) -> bool {
+        self.sealed
+    }
+
+    // Marks the block sealed and hands back the phis that still need operands
+    pub fn seal(&mut self) -> BTreeMap<usize, usize> {
+        self.sealed = true;
+        std::mem::take(&mut self.incomplete_phis)
     }
 
-    pub fn add_phi(&mut self, var: usize, phi: usize, dep_var: Option<usize>, assignment: usize) {
-        self.phis.insert(var, phi);
-        self.var_map.insert(var, Some((phi, dep_var)));
-        self.insts.insert(0, assignment);
-        self.insts.insert(0, phi);
+    pub fn add_incomplete_phi(&mut self, var: usize, phi: usize) {
+        self.incomplete_phis.insert(var, phi);
     }
 
-    pub fn generate_graph<R: BufRead>(
+    // Phis stay grouped at the top of the block
+    pub fn add_phi(&mut self, phi: usize, inst_list: &InstList) {
+        let idx = self
+            .insts
+            .iter()
+            .take_while(|&&inst_id| matches!(inst_list.get(inst_id).itype(), IType::Phi { .. }))
+            .count();
+        self.insts.insert(idx, phi);
+    }
+
+    pub fn rename_var_defs(&mut self, from_inst: usize, to_inst: usize) {
+        for def in self.var_map.values_mut().flatten() {
+            if def.0 == from_inst {
+                def.0 = to_inst;
+            }
+        }
+    }
+
+    pub fn generate_node(
         &self,
         inst_list: &InstList,
         block_list: &BlockList,
-        tokenizer: &Tokenizer<R>,
+        idents: &[String],
     ) -> String {
-        let inst_data = inst_list.generate_graph(&self.insts, inst_list, block_list, tokenizer);
-        let mut next_data = String::new();
+        let inst_data = inst_list.generate_graph(&self.insts, block_list, idents);
+
+        format!(
+            "bb{0} [shape=record, label=\"<b>BB{} | {{ {} }}\"];",
+            self.id, inst_data
+        )
+    }
 
-        if !matches!(
+    // Control flow edges leaving this block, back edges into a dominating
+    // block are drawn bold
+    pub fn generate_edges(&self, inst_list: &InstList, block_list: &BlockList) -> Vec<String> {
+        if matches!(
             inst_list.get(self.get_last_inst().unwrap()).itype(),
             IType::Ret { .. }
         ) {
-            next_data += match self.next {
-            (Some(fall_through), Some(follow)) => format!(
-                "\tbb{}:s -> bb{}:n [label=\"fall-through\"];\n\tbb{0}:s -> bb{}:n [label=\"branch\"];\n",
-                self.id, fall_through, follow
-            ),
-            (Some(fall_through), None) => format!("\tbb{}:s -> bb{}:n ;\n", self.id, fall_through),
-            _ => "".to_string(),
-        }
-        .as_str();
+            return vec![];
         }
 
-        next_data += match self.dom {
-            Some(dom) => format!(
-                "\tbb{}:b -> bb{}:b [color=blue, style=dotted, label=\"dom\"];\n",
-                dom, self.id
-            ),
-            None => "".to_string(),
-        }
-        .as_str();
+        let edges = match self.next {
+            (Some(fall_through), Some(follow)) => {
+                vec![(fall_through, "fall-through"), (follow, "branch")]
+            }
+            (Some(fall_through), None) => vec![(fall_through, "")],
+            _ => vec![],
+        };
 
-        format!(
-            "\tbb{0} [shape=record, label=\"<b>BB{} | {{ {} }}\"];\n{}\n",
-            self.id, inst_data, next_data
-        )
+        edges
+            .into_iter()
+            .map(|(block, label)| {
+                let mut attrs = Vec::new();
+                if !label.is_empty() {
+                    attrs.push(format!("label=\"{}\"", label));
+                }
+                if block_list.dominates(block, self.id) {
+                    attrs.push("color=red, style=bold".to_string());
+                }
+                if attrs.is_empty() {
+                    format!("bb{}:s -> bb{}:n;", self.id, block)
+                } else {
+                    format!("bb{}:s -> bb{}:n [{}];", self.id, block, attrs.join(", "))
+                }
+            })
+            .collect()
     }
 
-    pub fn resolve_phis(&mut self) {
-        for &inst in self.phis.values().rev() {
-            self.insts.insert(0, inst);
-        }
+    pub fn generate_dom_edge(&self) -> Option<String> {
+        self.dom
+            .map(|dom| format!("bb{}:s -> bb{}:n [color=blue, style=dotted];", dom, self.id))
     }
 
     pub fn delete_inst(&mut self, inst: usize) {
@@ -986,22 +913,9 @@ impl Block {
     pub fn iter(&self) -> std::slice::Iter<'_, usize> {
         self.insts.iter()
     }
-
-    pub fn update_var(&mut self, renamed_var: usize, phi: usize) {
-        for key in self.var_map.clone().keys() {
-            if self
-                .var_map
-                .get(key)
-                .unwrap()
-                .is_some_and(|val| val.1.is_some_and(|var| var == renamed_var))
-            {
-                self.var_map.insert(*key, Some((phi, Some(renamed_var))));
-            }
-        }
-    }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct InstList {
     pub instructions: Vec<Inst>,
     pub inst_count: usize,
@@ -1035,63 +949,51 @@ impl InstList {
             .expect("Instruction should exist")
     }
 
-    pub fn get_matching_inst(&self, itype: IType, start: usize) -> Option<usize> {
+    pub fn get_matching_inst(&self, itype: &IType, start: usize) -> Option<usize> {
         self.find_inst(itype, Some(start))
     }
 
-    fn find_inst(&self, itype: IType, idx: Option<usize>) -> Option<usize> {
+    fn find_inst(&self, itype: &IType, idx: Option<usize>) -> Option<usize> {
         let inst = self.get(idx?);
-        if itype == inst.itype() {
+        if *itype == inst.itype {
             return idx;
         }
         self.find_inst(itype, inst.dom())
     }
 
-    pub fn generate_graph<R: BufRead>(
+    pub fn generate_graph(
         &self,
         insts: &[usize],
-        inst_list: &InstList,
         block_list: &BlockList,
-        tokenizer: &Tokenizer<R>,
+        idents: &[String],
     ) -> String {
         if insts.is_empty() {
             return r"\<empty\>".to_string();
         }
         insts
-            .into_iter()
-            .filter_map(|&inst_id| {
-                let inst = self.get(inst_id);
-                if matches!(inst.itype(), IType::Assignment { .. }) {
-                    None
-                } else {
-                    Some(inst.generate_graph(inst_list, block_list, tokenizer))
-                }
-            })
-            // .map(|&inst_id| self.get(inst_id).generate_graph(block_list, tokenizer))
+            .iter()
+            .map(|&inst_id| self.get(inst_id).generate_graph(block_list, idents))
             .collect::<Vec<_>>()
             .join(" | ")
     }
 
-    pub fn generate_instructions<R: BufRead>(
+    pub fn generate_instructions(
         &self,
         insts: &[usize],
-        inst_list: &InstList,
         block_list: &BlockList,
-        tokenizer: &Tokenizer<R>,
+        idents: &[String],
     ) -> String {
         if insts.is_empty() {
             return r"\<empty\>".to_string();
         }
         insts
-            .into_iter()
+            .iter()
             .filter_map(|&inst_id| {
                 let inst = self.get(inst_id);
-                if matches!(inst.itype(), IType::Assignment { .. })
-                    || matches!(inst.itype(), IType::Empty)
-                {
+                if matches!(inst.itype(), IType::Empty) {
                     None
                 } else {
-                    Some(inst.generate_graph(inst_list, block_list, tokenizer))
+                    Some(inst.generate_graph(block_list, idents))
                 }
             })
             .collect::<Vec<_>>()
@@ -1109,7 +1011,7 @@ impl InstList {
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct BlockList {
     pub blocks: Vec<Block>,
     block_count: usize,
@@ -1151,9 +1053,16 @@ impl BlockList {
         self.blocks.iter()
     }
 
-    pub fn resolve_phis(&mut self) {
-        for block in self.blocks.iter_mut() {
-            block.resolve_phis();
+    // Whether every path to `block` goes through `dom`
+    pub fn dominates(&self, dom: usize, mut block: usize) -> bool {
+        loop {
+            if block == dom {
+                return true;
+            }
+            match self.get(block).dom() {
+                Some(next) => block = next,
+                None => return false,
+            }
         }
     }
 }
@@ -1162,118 +1071,6 @@ impl BlockList {
 pub enum Operand {
     Const(isize),
     Inst(usize),
-    Var(usize),
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum FrameState {
-    Conditional,
-    While,
-}
-
-#[derive(Debug, PartialEq, Clone, Copy)]
-pub enum FrameStatus {
-    FallThrough,
-    Follow,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct BlockFrame {
-    pub state: Option<FrameState>,
-    pub follow: Option<usize>,
-    pub fall_through: Option<usize>,
-    pub join: Option<usize>,
-    pub root: Option<usize>,
-    pub status: Option<FrameStatus>,
-}
-
-impl BlockFrame {
-    pub fn new() -> Self {
-        BlockFrame {
-            state: None,
-            follow: None,
-            fall_through: None,
-            join: None,
-            root: None,
-            status: None,
-        }
-    }
-
-    pub fn conditional(&mut self) -> &mut Self {
-        self.state = Some(FrameState::Conditional);
-        self
-    }
-
-    pub fn while_loop(&mut self) -> &mut Self {
-        self.state = Some(FrameState::While);
-        self
-    }
-
-    pub fn follow(&mut self, follow: usize) -> &mut Self {
-        self.follow = Some(follow);
-        self
-    }
-
-    pub fn join(&mut self, join: usize) -> &mut Self {
-        self.join = Some(join);
-        self
-    }
-
-    pub fn fall_through(&mut self, fall_through: usize) -> &mut Self {
-        self.fall_through = Some(fall_through);
-        self
-    }
-
-    pub fn root(&mut self, root: usize) -> &mut Self {
-        self.root = Some(root);
-        self
-    }
-
-    pub fn status(&mut self, status: FrameStatus) -> &mut Self {
-        self.status = Some(status);
-        self
-    }
-
-    pub fn is_fall_through(&self) -> bool {
-        self.status == Some(FrameStatus::FallThrough)
-    }
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub struct BlockState {
-    pub curr: usize,
-    stack: VecDeque<BlockFrame>,
-}
-
-impl BlockState {
-    pub fn new(curr: usize) -> BlockState {
-        BlockState {
-            curr,
-            stack: VecDeque::new(),
-        }
-    }
-
-    pub fn curr(&self) -> usize {
-        self.curr
-    }
-
-    pub fn is_empty(&self) -> bool {
-        self.stack.is_empty()
-    }
-
-    pub fn front(&self) -> &BlockFrame {
-        self.stack.front().unwrap()
-    }
-
-    pub fn front_mut(&mut self) -> &mut BlockFrame {
-        self.stack.front_mut().unwrap()
-    }
-
-    pub fn push(&mut self, blocks: BlockFrame) {
-        self.stack.push_front(blocks);
-    }
-
-    pub fn pop(&mut self) -> Option<BlockFrame> {
-        self.stack.pop_front()
-    }
+    // The variable and the instruction it held when read
+    Var(usize, usize),
 }
@@ -0,0 +1,454 @@
+use std::{
+    collections::BTreeSet,
+    time::{Duration, Instant},
+};
+
+use crate::{module::Module, parser_data::IType, snapshot::Snapshot};
+
+// Cleanup passes selected by optimization level
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Passes {
+    pub cse: bool,
+    pub phi_removal: bool,
+    pub dce: bool,
+}
+
+impl Passes {
+    pub fn level(level: usize) -> Passes {
+        Passes {
+            cse: level >= 1,
+            phi_removal: level >= 1,
+            dce: level >= 2,
+        }
+    }
+
+    // Pass names in the order they run
+    pub fn pipeline(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        if self.cse {
+            names.push("cse");
+        }
+        if self.phi_removal {
+            names.push("phi-removal");
+        }
+        // Phi removal renames uses, which can expose new common subexpressions
+        if self.cse {
+            names.push("cse");
+        }
+        if self.dce {
+            names.push("dce");
+        }
+        names
+    }
+}
+
+impl Default for Passes {
+    fn default() -> Self {
+        Passes::level(1)
+    }
+}
+
+pub trait Pass {
+    fn name(&self) -> &'static str;
+
+    // Returns whether the module changed
+    fn run(&mut self, module: &mut Module) -> bool;
+}
+
+pub const PASS_NAMES: [&str; 3] = ["cse", "phi-removal", "dce"];
+
+pub fn pass_by_name(name: &str) -> Option<Box<dyn Pass>> {
+    match name {
+        "cse" => Some(Box::new(CommonSubexpressionElimination)),
+        "phi-removal" => Some(Box::new(PhiRemoval)),
+        "dce" => Some(Box::new(DeadCodeElimination)),
+        _ => None,
+    }
+}
+
+pub struct CommonSubexpressionElimination;
+
+impl Pass for CommonSubexpressionElimination {
+    fn name(&self) -> &'static str {
+        "cse"
+    }
+
+    fn run(&mut self, module: &mut Module) -> bool {
+        let mut changed = false;
+
+        for inst_id in (0..module.insts.instructions.len()).rev() {
+            let inst = module.insts.get(inst_id);
+            if let (Some(dom_inst), Some(block)) = (inst.dom(), inst.block()) {
+                if let Some(cse_inst) = module.insts.get_matching_inst(&inst.itype(), dom_inst) {
+                    module.insts.rename_inst(inst_id, cse_inst);
+                    module.blocks.get_mut(block).delete_inst(inst_id);
+                    module.insts.remove_block(inst_id);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+pub struct PhiRemoval;
+
+impl Pass for PhiRemoval {
+    fn name(&self) -> &'static str {
+        "phi-removal"
+    }
+
+    fn run(&mut self, module: &mut Module) -> bool {
+        let mut changed = false;
+
+        for inst_id in (0..module.insts.instructions.len()).rev() {
+            let itype = module.insts.get(inst_id).itype();
+            if let (IType::Phi { insts, .. }, Some(block)) =
+                (itype, module.insts.get(inst_id).block())
+            {
+                // CSE can leave every operand naming the same value, or the
+                // phi itself along a back edge
+                let mut ops = insts.iter().map(|inst| inst.0).filter(|&op| op != inst_id);
+                let Some(first) = ops.next() else {
+                    continue;
+                };
+                if ops.all(|op| op == first) {
+                    module.insts.rename_inst(inst_id, first);
+                    module.blocks.get_mut(block).delete_inst(inst_id);
+                    module.insts.remove_block(inst_id);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+pub struct DeadCodeElimination;
+
+impl Pass for DeadCodeElimination {
+    fn name(&self) -> &'static str {
+        "dce"
+    }
+
+    fn run(&mut self, module: &mut Module) -> bool {
+        let mut live = BTreeSet::new();
+        let mut worklist = module
+            .insts
+            .instructions
+            .iter()
+            .filter(|inst| inst.block().is_some() && inst.itype().has_side_effects())
+            .map(|inst| inst.id())
+            .collect::<Vec<_>>();
+
+        while let Some(inst_id) = worklist.pop() {
+            if live.insert(inst_id) {
+                worklist.extend(module.insts.get(inst_id).itype().operands());
+            }
+        }
+
+        let mut changed = false;
+        for inst_id in 0..module.insts.instructions.len() {
+            if let Some(block) = module.insts.get(inst_id).block() {
+                if !live.contains(&inst_id) {
+                    module.blocks.get_mut(block).delete_inst(inst_id);
+                    module.insts.remove_block(inst_id);
+                    changed = true;
+                }
+            }
+        }
+
+        changed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DumpFormat {
+    Dot,
+    Ssa,
+}
+
+impl DumpFormat {
+    pub fn from_name(name: &str) -> Option<DumpFormat> {
+        match name {
+            "dot" => Some(DumpFormat::Dot),
+            "ssa" => Some(DumpFormat::Ssa),
+            _ => None,
+        }
+    }
+
+    pub fn render(&self, module: &Module) -> String {
+        match self {
+            DumpFormat::Dot => module.graph(),
+            DumpFormat::Ssa => module.instructions(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PassStats {
+    pub name: &'static str,
+    pub duration: Duration,
+    pub insts_before: usize,
+    pub insts_after: usize,
+    pub changed: bool,
+}
+
+impl PassStats {
+    pub fn inst_delta(&self) -> isize {
+        self.insts_after as isize - self.insts_before as isize
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dump {
+    pub pass: &'static str,
+    // Position of the pass run in `PassManager::stats`
+    pub run: usize,
+    pub format: DumpFormat,
+    pub data: String,
+}
+
+// Runs an ordered list of passes over a module
+pub struct PassManager {
+    passes: Vec<Box<dyn Pass>>,
+    // Upper bound on pipeline repetitions, 1 runs it once
+    max_iterations: usize,
+    dump_after: Vec<(String, DumpFormat)>,
+    record_snapshots: bool,
+    stats: Vec<PassStats>,
+    dumps: Vec<Dump>,
+    // The lowered module followed by the state after every pass run
+    snapshots: Vec<Snapshot>,
+}
+
+impl PassManager {
+    pub fn new() -> PassManager {
+        PassManager {
+            passes: Vec::new(),
+            max_iterations: 1,
+            dump_after: Vec::new(),
+            record_snapshots: false,
+            stats: Vec::new(),
+            dumps: Vec::new(),
+            snapshots: Vec::new(),
+        }
+    }
+
+    // None if a name is unknown
+    pub fn from_names<S: AsRef<str>>(names: &[S]) -> Option<PassManager> {
+        names.iter().try_fold(PassManager::new(), |manager, name| {
+            Some(manager.pass(pass_by_name(name.as_ref())?))
+        })
+    }
+
+    pub fn pass(mut self, pass: Box<dyn Pass>) -> Self {
+        self.passes.push(pass);
+        self
+    }
+
+    // Repeat the pipeline until no pass changes the module
+    pub fn fixpoint(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations.max(1);
+        self
+    }
+
+    pub fn dump_after(mut self, pass: &str, format: DumpFormat) -> Self {
+        self.dump_after.push((pass.to_string(), format));
+        self
+    }
+
+    pub fn record_snapshots(mut self, record: bool) -> Self {
+        self.record_snapshots = record;
+        self
+    }
+
+    pub fn stats(&self) -> &[PassStats] {
+        &self.stats
+    }
+
+    pub fn dumps(&self) -> &[Dump] {
+        &self.dumps
+    }
+
+    pub fn snapshots(&self) -> &[Snapshot] {
+        &self.snapshots
+    }
+
+    pub fn run(&mut self, module: &mut Module) {
+        if self.record_snapshots && self.snapshots.is_empty() {
+            self.snapshots.push(Snapshot::new("parse", module));
+        }
+
+        for _ in 0..self.max_iterations {
+            let mut changed = false;
+
+            for pass in self.passes.iter_mut() {
+                let insts_before = module.live_inst_count();
+                let start = Instant::now();
+                let pass_changed = pass.run(module);
+                let duration = start.elapsed();
+                // Passes can empty blocks, which the printers and codegen don't expect
+                module.fill_empty();
+
+                self.stats.push(PassStats {
+                    name: pass.name(),
+                    duration,
+                    insts_before,
+                    insts_after: module.live_inst_count(),
+                    changed: pass_changed,
+                });
+                changed |= pass_changed;
+
+                if self.record_snapshots {
+                    self.snapshots.push(Snapshot::new(pass.name(), module));
+                }
+
+                for (_, format) in self
+                    .dump_after
+                    .iter()
+                    .filter(|(name, _)| name == pass.name())
+                {
+                    self.dumps.push(Dump {
+                        pass: pass.name(),
+                        run: self.stats.len() - 1,
+                        format: *format,
+                        data: format.render(module),
+                    });
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    pub fn report(&self) -> String {
+        let mut data = format!(
+            "{:<16}{:>12}{:>10}{:>10}{:>8}\n",
+            "pass", "time (us)", "before", "after", "delta"
+        );
+        for stats in self.stats.iter() {
+            data += format!(
+                "{:<16}{:>12}{:>10}{:>10}{:>+8}\n",
+                stats.name,
+                stats.duration.as_micros(),
+                stats.insts_before,
+                stats.insts_after,
+                stats.inst_delta()
+            )
+            .as_str();
+        }
+        data
+    }
+}
+
+impl Default for PassManager {
+    fn default() -> Self {
+        PassManager::new()
+    }
+}
+
+impl From<Passes> for PassManager {
+    fn from(passes: Passes) -> Self {
+        PassManager::from_names(&passes.pipeline()).expect("Pass names should be known")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{lower::Lowerer, parser::Parser};
+
+    use super::*;
+
+    fn lower(input: &[u8]) -> Module {
+        let mut parser = Parser::new(input);
+        let ast = parser.computation().expect("Should parse");
+
+        Lowerer::new(parser.idents())
+            .computation(&ast)
+            .expect("Should lower")
+    }
+
+    const INPUT: &[u8] = b"
+main
+var a, b, c;
+{
+    let a <- call InputNum();
+    let b <- a * 2;
+    if a > 0 then
+        let c <- a * 2
+    else
+        let c <- a * 2
+    fi;
+    call OutputNum(c)
+}
+.
+";
+
+    #[test]
+    fn stats() {
+        let mut module = lower(INPUT);
+        let before = module.live_inst_count();
+
+        let mut manager = PassManager::from(Passes::level(2));
+        manager.run(&mut module);
+
+        let names = manager.stats().iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(names, vec!["cse", "phi-removal", "cse", "dce"]);
+        assert_eq!(manager.stats()[0].insts_before, before);
+        assert!(manager.stats()[0].inst_delta() < 0);
+        assert_eq!(
+            manager.stats().last().expect("Should exist").insts_after,
+            module.live_inst_count()
+        );
+    }
+
+    #[test]
+    fn fixpoint() {
+        let mut module = lower(INPUT);
+
+        // Phi removal first needs another round once CSE has merged the operands
+        let mut manager = PassManager::from_names(&["phi-removal", "cse"])
+            .expect("Should exist")
+            .fixpoint(10);
+        manager.run(&mut module);
+
+        assert!(manager.stats().len() > 2);
+        assert!(!manager.stats().iter().rev().take(2).any(|s| s.changed));
+        assert!(module
+            .insts()
+            .instructions
+            .iter()
+            .all(|inst| inst.block().is_none() || !matches!(inst.itype(), IType::Phi { .. })));
+    }
+
+    #[test]
+    fn dumps() {
+        let mut module = lower(INPUT);
+
+        let mut manager = PassManager::from(Passes::default())
+            .dump_after("cse", DumpFormat::Ssa)
+            .dump_after("phi-removal", DumpFormat::Dot);
+        manager.run(&mut module);
+
+        let dumps = manager
+            .dumps()
+            .iter()
+            .map(|dump| (dump.pass, dump.run, dump.format))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            dumps,
+            vec![
+                ("cse", 0, DumpFormat::Ssa),
+                ("phi-removal", 1, DumpFormat::Dot),
+                ("cse", 2, DumpFormat::Ssa),
+            ]
+        );
+        assert_eq!(manager.dumps()[2].data, module.instructions());
+        assert!(PassManager::from_names(&["cse", "licm"]).is_none());
+    }
+}
@@ -0,0 +1,599 @@
+use std::collections::{btree_map::Entry, BTreeMap};
+
+use crate::{
+    ast_data::{
+        Call, Computation, Cond, CondKind, Expr, ExprKind, FuncDecl, Stmt, StmtKind, VarDecl,
+    },
+    diagnostics::{DiagnosticKind, Diagnostics},
+    tokenizer_data::{
+        Span, PREDEFINED_INPUTNUM_ID, PREDEFINED_OUTPUTNEWLINE_ID, PREDEFINED_OUTPUTNUM_ID,
+    },
+    uninit,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Signature {
+    params: usize,
+    is_void: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SymbolKind {
+    Global,
+    Param,
+    Local,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Symbol {
+    kind: SymbolKind,
+    span: Span,
+}
+
+// Globals first, then the params and locals of the function being checked
+#[derive(Debug, Default)]
+struct SymbolTable {
+    // var ident -> declaration
+    scopes: Vec<BTreeMap<usize, Symbol>>,
+}
+
+impl SymbolTable {
+    fn push(&mut self) {
+        self.scopes.push(BTreeMap::new());
+    }
+
+    fn pop(&mut self) {
+        self.scopes.pop();
+    }
+
+    // The earlier declaration if `var` is already declared in the innermost scope
+    fn declare(&mut self, var: usize, symbol: Symbol) -> Option<Symbol> {
+        let scope = self.scopes.last_mut().expect("Scope should exist");
+        match scope.get(&var) {
+            Some(&prev) => Some(prev),
+            None => {
+                scope.insert(var, symbol);
+                None
+            }
+        }
+    }
+
+    fn lookup(&self, var: usize) -> Option<Symbol> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&var))
+            .copied()
+    }
+}
+
+// Checks every call against the declared functions, so that `Lowerer` only
+// sees programs it can lower
+#[derive(Debug)]
+pub struct Checker<'a> {
+    idents: &'a [String],
+    // func ident -> signature
+    funcs: BTreeMap<usize, Signature>,
+    symbols: SymbolTable,
+    // Loops enclosing the statement being checked
+    loops: usize,
+    // Name and voidness of the function being checked, None in main
+    func: Option<(usize, bool)>,
+    diagnostics: Diagnostics,
+}
+
+impl<'a> Checker<'a> {
+    pub fn new(idents: &'a [String]) -> Checker<'a> {
+        let funcs = [
+            (PREDEFINED_INPUTNUM_ID, 0, false),
+            (PREDEFINED_OUTPUTNUM_ID, 1, true),
+            (PREDEFINED_OUTPUTNEWLINE_ID, 0, true),
+        ]
+        .into_iter()
+        .map(|(func, params, is_void)| (func, Signature { params, is_void }))
+        .collect();
+
+        Checker {
+            idents,
+            funcs,
+            symbols: SymbolTable::default(),
+            loops: 0,
+            func: None,
+            diagnostics: Diagnostics::new(),
+        }
+    }
+
+    fn name(&self, ident: usize) -> &str {
+        self.idents.get(ident).expect("Ident should exist")
+    }
+
+    fn error(&mut self, message: String, span: Span) {
+        self.diagnostics
+            .push(DiagnosticKind::Semantic, &message, Some(span));
+    }
+
+    fn declare(&mut self, decl: &VarDecl, kind: SymbolKind) {
+        let outer = self.symbols.lookup(decl.var);
+        let symbol = Symbol {
+            kind,
+            span: decl.span,
+        };
+
+        if let Some(prev) = self.symbols.declare(decl.var, symbol) {
+            let message = format!(
+                "`{}` is already declared at {}:{}",
+                self.name(decl.var),
+                prev.span.line,
+                prev.span.col
+            );
+            self.error(message, decl.span);
+        } else if kind == SymbolKind::Param
+            && outer.is_some_and(|outer| outer.kind == SymbolKind::Global)
+        {
+            let message = format!("parameter `{}` shadows a global", self.name(decl.var));
+            self.error(message, decl.span);
+        }
+    }
+
+    fn var(&mut self, var: usize, span: Span) {
+        if self.symbols.lookup(var).is_none() {
+            let message = format!("undeclared variable `{}`", self.name(var));
+            self.error(message, span);
+        }
+    }
+
+    fn call(&mut self, call: &Call) -> Option<Signature> {
+        for arg in call.args.iter() {
+            self.value(arg);
+        }
+
+        let Some(&signature) = self.funcs.get(&call.func) else {
+            let message = format!("undefined function `{}`", self.name(call.func));
+            self.error(message, call.span);
+            return None;
+        };
+
+        if call.args.len() != signature.params {
+            let message = format!(
+                "`{}` takes {} argument{} but {} {} given",
+                self.name(call.func),
+                signature.params,
+                if signature.params == 1 { "" } else { "s" },
+                call.args.len(),
+                if call.args.len() == 1 { "was" } else { "were" },
+            );
+            self.error(message, call.span);
+        }
+
+        Some(signature)
+    }
+
+    // An expression whose result is used
+    fn value(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Num(_) => {}
+            ExprKind::Var(var) => self.var(*var, expr.span),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.value(lhs);
+                self.value(rhs);
+            }
+            ExprKind::Call(call) => {
+                if self.call(call).is_some_and(|signature| signature.is_void) {
+                    let message =
+                        format!("void function `{}` used as a value", self.name(call.func));
+                    self.error(message, call.span);
+                }
+            }
+        }
+    }
+
+    fn cond(&mut self, cond: &Cond) {
+        match &cond.kind {
+            CondKind::Relation(relation) => {
+                self.value(&relation.lhs);
+                self.value(&relation.rhs);
+            }
+            CondKind::Not(cond) => self.cond(cond),
+            CondKind::Logical { lhs, rhs, .. } => {
+                self.cond(lhs);
+                self.cond(rhs);
+            }
+        }
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Assign { var, expr } => {
+                self.value(expr);
+                self.var(*var, stmt.span);
+            }
+            StmtKind::Call(call) => {
+                self.call(call);
+            }
+            StmtKind::If {
+                cond,
+                then_body,
+                elsifs,
+                else_body,
+            } => {
+                self.cond(cond);
+                self.stat_sequence(then_body);
+                for (cond, body) in elsifs {
+                    self.cond(cond);
+                    self.stat_sequence(body);
+                }
+                if let Some(else_body) = else_body {
+                    self.stat_sequence(else_body);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.cond(cond);
+                self.loop_body(body);
+            }
+            StmtKind::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => {
+                self.value(from);
+                self.var(*var, stmt.span);
+                self.value(to);
+                if let Some(step) = step {
+                    self.value(step);
+                }
+                self.loop_body(body);
+            }
+            StmtKind::Repeat { body, cond } => {
+                self.loop_body(body);
+                self.cond(cond);
+            }
+            StmtKind::Break | StmtKind::Continue if self.loops == 0 => {
+                let keyword = if stmt.kind == StmtKind::Break {
+                    "break"
+                } else {
+                    "continue"
+                };
+                self.error(format!("`{}` outside of a loop", keyword), stmt.span);
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+            StmtKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.value(expr);
+                }
+                match (self.func, expr) {
+                    (Some((func, true)), Some(_)) => {
+                        let message =
+                            format!("void function `{}` cannot return a value", self.name(func));
+                        self.error(message, stmt.span);
+                    }
+                    (Some((func, false)), None) => {
+                        let message = format!("function `{}` must return a value", self.name(func));
+                        self.error(message, stmt.span);
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn stat_sequence(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.statement(stmt);
+        }
+    }
+
+    fn loop_body(&mut self, body: &[Stmt]) {
+        self.loops += 1;
+        self.stat_sequence(body);
+        self.loops -= 1;
+    }
+
+    fn func_decl(&mut self, func: &FuncDecl) {
+        self.symbols.push();
+        for param in func.params.iter() {
+            self.declare(param, SymbolKind::Param);
+        }
+        for decl in func.vars.iter() {
+            self.declare(decl, SymbolKind::Local);
+        }
+
+        self.func = Some((func.name, func.is_void));
+        self.stat_sequence(&func.body);
+        self.func = None;
+        self.symbols.pop();
+        if !func.is_void && !returns(&func.body) {
+            let message = format!(
+                "function `{}` can reach its end without returning a value",
+                self.name(func.name)
+            );
+            self.error(message, func.span);
+        }
+    }
+
+    pub fn check(mut self, ast: &Computation) -> Diagnostics {
+        self.symbols.push();
+        for decl in ast.vars.iter() {
+            self.declare(decl, SymbolKind::Global);
+        }
+
+        // Collected up front so functions can call ones declared after them
+        for func in ast.funcs.iter() {
+            let signature = Signature {
+                params: func.params.len(),
+                is_void: func.is_void,
+            };
+            if let Entry::Vacant(entry) = self.funcs.entry(func.name) {
+                entry.insert(signature);
+                continue;
+            }
+            let message = format!("function `{}` is already declared", self.name(func.name));
+            self.error(message, func.span);
+        }
+
+        for func in ast.funcs.iter() {
+            self.func_decl(func);
+        }
+        self.stat_sequence(&ast.body);
+
+        // Only meaningful once every variable resolves
+        if self.diagnostics.is_empty() {
+            uninit::check(ast, self.idents, &mut self.diagnostics);
+        }
+
+        self.diagnostics
+    }
+}
+
+// Whether every path through `stmts` ends in a return
+fn returns(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::If {
+            then_body,
+            elsifs,
+            else_body: Some(else_body),
+            ..
+        } => {
+            returns(then_body) && elsifs.iter().all(|(_, body)| returns(body)) && returns(else_body)
+        }
+        // The body runs at least once, unless it can leave the loop first
+        StmtKind::Repeat { body, .. } => returns(body) && !leaves_loop(body),
+        _ => false,
+    })
+}
+
+// Whether a `break` or `continue` in `stmts` leaves the loop around them
+fn leaves_loop(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|stmt| match &stmt.kind {
+        StmtKind::Break | StmtKind::Continue => true,
+        StmtKind::If {
+            then_body,
+            elsifs,
+            else_body,
+            ..
+        } => {
+            leaves_loop(then_body)
+                || elsifs.iter().any(|(_, body)| leaves_loop(body))
+                || else_body.as_deref().is_some_and(leaves_loop)
+        }
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::Parser;
+
+    use super::*;
+
+    fn check(input: &[u8]) -> Vec<(String, Span)> {
+        let mut parser = Parser::new(input);
+        let ast = parser.computation().expect("Should parse");
+
+        Checker::new(parser.idents())
+            .check(&ast)
+            .iter()
+            .map(|diagnostic| {
+                assert_eq!(diagnostic.kind, DiagnosticKind::Semantic);
+                (
+                    diagnostic.message.clone(),
+                    diagnostic.span.expect("Should have a span"),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn valid() {
+        assert!(check(
+            b"main var x; function f(a, b); { if a < b then return a else return b fi };
+              void function g(); { call OutputNum(call f(1, 2)) };
+              { let x <- call InputNum(); call f(x, x); call g; call OutputNewLine() }."
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn forward_reference() {
+        assert!(check(
+            b"main function f(a); { return call g(a) }; function g(a); { return call f(a) };
+              { call OutputNum(call f(1)) }."
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn duplicate_declarations() {
+        assert_eq!(
+            check(
+                b"main var x, y, x;\nfunction f(a, a); var a; { return a };\n\
+                  function f(); { return 1 }; { let x <- call f(1, 2) }."
+            ),
+            vec![
+                (
+                    "`x` is already declared at 1:10".to_string(),
+                    Span { line: 1, col: 16 }
+                ),
+                (
+                    "function `f` is already declared".to_string(),
+                    Span { line: 3, col: 1 }
+                ),
+                (
+                    "`a` is already declared at 2:12".to_string(),
+                    Span { line: 2, col: 15 }
+                ),
+                (
+                    "`a` is already declared at 2:12".to_string(),
+                    Span { line: 2, col: 23 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn undeclared_variable() {
+        assert_eq!(
+            check(b"main var x; function f(a); var b; { return a + b + x + y };\n{ let z <- x }."),
+            vec![
+                (
+                    "undeclared variable `y`".to_string(),
+                    Span { line: 1, col: 56 }
+                ),
+                (
+                    "undeclared variable `z`".to_string(),
+                    Span { line: 2, col: 3 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn shadowed_global() {
+        assert_eq!(
+            check(
+                b"main var x, y; function f(x); var y; { let y <- x; return y };
+                  { let x <- call f(1) }."
+            ),
+            vec![(
+                "parameter `x` shadows a global".to_string(),
+                Span { line: 1, col: 27 }
+            )]
+        );
+    }
+
+    #[test]
+    fn undefined_function() {
+        assert_eq!(
+            check(b"main var x; {\n  let x <- call f(1) + call x }."),
+            vec![
+                (
+                    "undefined function `f`".to_string(),
+                    Span { line: 2, col: 12 }
+                ),
+                (
+                    "undefined function `x`".to_string(),
+                    Span { line: 2, col: 24 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn arity() {
+        assert_eq!(
+            check(b"main function f(a, b); { return a };\n{ call OutputNum; call f(1) }."),
+            vec![
+                (
+                    "`OutputNum` takes 1 argument but 0 were given".to_string(),
+                    Span { line: 2, col: 3 }
+                ),
+                (
+                    "`f` takes 2 arguments but 1 was given".to_string(),
+                    Span { line: 2, col: 19 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn void_value() {
+        assert_eq!(
+            check(b"main var x; void function f(); { };\n{ let x <- call f() * 2 }."),
+            vec![(
+                "void function `f` used as a value".to_string(),
+                Span { line: 2, col: 12 }
+            )]
+        );
+    }
+
+    #[test]
+    fn loop_exits() {
+        assert_eq!(
+            check(
+                b"main var i; {\n  for i <- 0 to 9 do if i > 5 then break fi od;\n\
+                  repeat continue until i > 0;\n  break }."
+            ),
+            vec![(
+                "`break` outside of a loop".to_string(),
+                Span { line: 4, col: 3 }
+            )]
+        );
+    }
+
+    #[test]
+    fn missing_return() {
+        assert_eq!(
+            check(
+                b"main function f(a); { if a > 0 then return a fi };\n\
+                  function g(a); { while a > 0 do return a od }; { call OutputNewLine }."
+            ),
+            vec![
+                (
+                    "function `f` can reach its end without returning a value".to_string(),
+                    Span { line: 1, col: 6 }
+                ),
+                (
+                    "function `g` can reach its end without returning a value".to_string(),
+                    Span { line: 2, col: 1 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn repeat_returns() {
+        assert_eq!(
+            check(
+                b"main function f(a); { repeat return a until a > 0 };\n\
+                  function g(a); { repeat if a > 0 then break fi; return a until a > 0 };
+                  { call OutputNum(call f(1) + call g(1)) }."
+            ),
+            vec![(
+                "function `g` can reach its end without returning a value".to_string(),
+                Span { line: 2, col: 1 }
+            )]
+        );
+    }
+
+    #[test]
+    fn return_values() {
+        assert_eq!(
+            check(
+                b"main function f(a); { if a > 0 then return fi; return a };\n\
+                  void function g(); { return 1 };\n{ call g; return }."
+            ),
+            vec![
+                (
+                    "function `f` must return a value".to_string(),
+                    Span { line: 1, col: 37 }
+                ),
+                (
+                    "void function `g` cannot return a value".to_string(),
+                    Span { line: 2, col: 22 }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    module::{GraphOptions, Module},
+    parser_data::IType,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    Added,
+    Removed,
+    // Same instruction, different operands
+    Renamed,
+}
+
+impl Change {
+    fn marker(&self) -> &'static str {
+        match self {
+            Change::Added => "+",
+            Change::Removed => "-",
+            Change::Renamed => "~",
+        }
+    }
+
+    fn colour(&self) -> &'static str {
+        match self {
+            Change::Added => "palegreen",
+            Change::Removed => "lightpink",
+            Change::Renamed => "khaki",
+        }
+    }
+}
+
+// IR state after lowering or after one pass run
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub name: &'static str,
+    pub module: Module,
+}
+
+impl Snapshot {
+    pub fn new(name: &'static str, module: &Module) -> Snapshot {
+        Snapshot {
+            name,
+            module: module.clone(),
+        }
+    }
+
+    // `main.01-cse.dot` for the first pass of `main`
+    pub fn file_name(&self, stem: &str, index: usize, extension: &str) -> String {
+        format!("{}.{:02}-{}.{}", stem, index, self.name, extension)
+    }
+
+    pub fn dot(&self, prev: Option<&Snapshot>) -> String {
+        let Some(prev) = prev else {
+            return self.module.graph();
+        };
+
+        let changes = diff(&prev.module, &self.module);
+
+        self.module.write_graph(&GraphOptions::default(), |block| {
+            let rows = merged_block(&prev.module, &self.module, block.id(), &changes)
+                .into_iter()
+                .filter(|(inst, _)| {
+                    // Fillers only matter when the block would be empty otherwise
+                    !matches!(self.module.inst(*inst).itype(), IType::Empty)
+                        || block.iter().count() == 1
+                })
+                .map(|(inst, change)| {
+                    let module = match change {
+                        Some(Change::Removed) => &prev.module,
+                        _ => &self.module,
+                    };
+                    let text = html_escape(&inst_text(module, inst));
+                    match change {
+                        Some(Change::Removed) => {
+                            format!(
+                                "<td bgcolor=\"{}\"><s>{}</s></td>",
+                                change.unwrap().colour(),
+                                text
+                            )
+                        }
+                        Some(change) => {
+                            format!("<td bgcolor=\"{}\">{}</td>", change.colour(), text)
+                        }
+                        None => format!("<td>{}</td>", text),
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let mut data = format!(
+                "bb{0} [shape=plain, label=<<table border=\"0\" cellborder=\"1\" cellspacing=\"0\"><tr><td port=\"b\" rowspan=\"{1}\">BB{0}</td>{2}</tr>",
+                block.id(),
+                rows.len(),
+                rows[0]
+            );
+            for row in rows.iter().skip(1) {
+                data += format!("<tr>{}</tr>", row).as_str();
+            }
+            data + "</table>>];"
+        })
+    }
+
+    pub fn ssa(&self, prev: Option<&Snapshot>) -> String {
+        let Some(prev) = prev else {
+            return self.module.instructions();
+        };
+
+        let changes = diff(&prev.module, &self.module);
+
+        // Same layout as the plain IR, with a marker column in front
+        self.module.write_ir(|block| {
+            merged_block(&prev.module, &self.module, block.id(), &changes)
+                .into_iter()
+                .map(|(inst, change)| {
+                    let module = match change {
+                        Some(Change::Removed) => &prev.module,
+                        _ => &self.module,
+                    };
+                    let marker = change.map_or(" ", |change| change.marker());
+                    format!("{} {}", marker, module.ir_inst(inst))
+                })
+                .collect()
+        })
+    }
+}
+
+fn is_live(module: &Module, inst: usize) -> bool {
+    inst < module.insts().instructions.len() && module.inst(inst).block().is_some()
+}
+
+// Instructions that differ between two states of the same module
+pub fn diff(prev: &Module, curr: &Module) -> BTreeMap<usize, Change> {
+    let mut changes = BTreeMap::new();
+
+    let count = curr.insts().instructions.len();
+    for inst in 0..count.max(prev.insts().instructions.len()) {
+        let change = match (is_live(prev, inst), is_live(curr, inst)) {
+            (false, true) => Change::Added,
+            (true, false) => Change::Removed,
+            (true, true)
+                if prev.inst(inst).itype().operands() != curr.inst(inst).itype().operands() =>
+            {
+                Change::Renamed
+            }
+            _ => continue,
+        };
+        changes.insert(inst, change);
+    }
+
+    changes
+}
+
+// Current instructions of a block with the removed ones put back after their
+// nearest surviving predecessor
+fn merged_block(
+    prev: &Module,
+    curr: &Module,
+    block: usize,
+    changes: &BTreeMap<usize, Change>,
+) -> Vec<(usize, Option<Change>)> {
+    let mut insts = curr
+        .block(block)
+        .iter()
+        .map(|&inst| (inst, changes.get(&inst).copied()))
+        .collect::<Vec<_>>();
+
+    if block >= prev.blocks().blocks.len() {
+        return insts;
+    }
+
+    let mut pos = 0;
+    for &inst in prev.block(block).iter() {
+        if changes.get(&inst) == Some(&Change::Removed) {
+            insts.insert(pos, (inst, Some(Change::Removed)));
+            pos += 1;
+        } else if let Some(idx) = insts.iter().position(|&(id, _)| id == inst) {
+            pos = idx + 1;
+        }
+    }
+
+    insts
+}
+
+fn inst_text(module: &Module, inst: usize) -> String {
+    module
+        .inst(inst)
+        .generate_graph(module.blocks(), module.idents())
+        .replace(r"\<", "<")
+        .replace(r"\>", ">")
+}
+
+fn html_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        lower::Lowerer,
+        parser::Parser,
+        passes::{PassManager, Passes},
+    };
+
+    use super::*;
+
+    fn snapshots(input: &[u8]) -> Vec<Snapshot> {
+        let mut parser = Parser::new(input);
+        let ast = parser.computation().expect("Should parse");
+        let mut module = Lowerer::new(parser.idents())
+            .computation(&ast)
+            .expect("Should lower");
+
+        let mut manager = PassManager::from(Passes::level(2)).record_snapshots(true);
+        manager.run(&mut module);
+        manager.snapshots().to_vec()
+    }
+
+    #[test]
+    fn names() {
+        let snapshots = snapshots(b"main { call OutputNum(1) }.");
+
+        let names = snapshots
+            .iter()
+            .enumerate()
+            .map(|(idx, snapshot)| snapshot.file_name("main", idx, "dot"))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![
+                "main.00-parse.dot",
+                "main.01-cse.dot",
+                "main.02-phi-removal.dot",
+                "main.03-cse.dot",
+                "main.04-dce.dot",
+            ]
+        );
+    }
+
+    #[test]
+    fn changes() {
+        let snapshots = snapshots(
+            b"
+main
+var a, b, c;
+{
+    let a <- call InputNum();
+    let b <- a * 2;
+    if a > 0 then
+        let c <- a * 2
+    else
+        let c <- a * 2
+    fi;
+    call OutputNum(c)
+}
+.
+",
+        );
+
+        // CSE drops the repeated muls and renames the phi operands
+        let changes = diff(&snapshots[0].module, &snapshots[1].module);
+        let removed = changes
+            .iter()
+            .filter(|(_, &change)| change == Change::Removed)
+            .map(|(&inst, _)| snapshots[0].module.inst(inst).itype())
+            .collect::<Vec<_>>();
+        assert_eq!(removed.len(), 2);
+        assert!(removed
+            .iter()
+            .all(|itype| matches!(itype, IType::Mul { .. })));
+        assert!(changes.iter().any(|(&inst, &change)| {
+            change == Change::Renamed
+                && matches!(snapshots[1].module.inst(inst).itype(), IType::Phi { .. })
+        }));
+
+        // Blocks emptied by CSE get a filler
+        assert!(changes.values().any(|&change| change == Change::Added));
+
+        let ssa = snapshots[1].ssa(Some(&snapshots[0]));
+        assert_eq!(ssa.lines().filter(|line| line.starts_with('-')).count(), 2);
+        assert!(ssa
+            .lines()
+            .any(|line| line.starts_with("~") && line.contains("phi")));
+
+        let dot = snapshots[1].dot(Some(&snapshots[0]));
+        assert!(dot.contains("bgcolor=\"lightpink\"><s>"));
+        assert!(dot.contains("bgcolor=\"khaki\""));
+
+        // Nothing left to change after the second CSE
+        assert!(diff(&snapshots[2].module, &snapshots[3].module).is_empty());
+        assert_eq!(snapshots[0].dot(None), snapshots[0].module.graph());
+    }
+}
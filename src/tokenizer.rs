@@ -2,44 +2,45 @@ use std::io::BufRead;
 
 use crate::{
     input::Input,
-    tokenizer_data::{RelOp, Token},
+    tokenizer_data::{RelOp, Span, Token, PREDEFINED_IDENTS},
 };
 
 // Characters
-const ASCII_ZERO: u8 = '0' as u8;
-const ASCII_NINE: u8 = '9' as u8;
-const ASCII_LOWER_A: u8 = 'a' as u8;
-const ASCII_LOWER_Z: u8 = 'z' as u8;
-const ASCII_UPPER_A: u8 = 'A' as u8;
-const ASCII_UPPER_Z: u8 = 'Z' as u8;
+const ASCII_ZERO: u8 = b'0';
+const ASCII_NINE: u8 = b'9';
+const ASCII_LOWER_A: u8 = b'a';
+const ASCII_LOWER_Z: u8 = b'z';
+const ASCII_UPPER_A: u8 = b'A';
+const ASCII_UPPER_Z: u8 = b'Z';
 
 // Symbols
-const ASCII_COMMA: u8 = ',' as u8;
-const ASCII_SEMICOLON: u8 = ';' as u8;
-const ASCII_PERIOD: u8 = '.' as u8;
+const ASCII_COMMA: u8 = b',';
+const ASCII_SEMICOLON: u8 = b';';
+const ASCII_PERIOD: u8 = b'.';
 
 // Operators
-const ASCII_PLUS: u8 = '+' as u8;
-const ASCII_MINUS: u8 = '-' as u8;
-const ASCII_ASTERISK: u8 = '*' as u8;
-const ASCII_FORWARD_SLASH: u8 = '/' as u8;
-const ASCII_EQUAL: u8 = '=' as u8;
-const ASCII_BANG: u8 = '!' as u8;
-const ASCII_LEFT_ARROW: u8 = '<' as u8;
-const ASCII_RIGHT_ARROW: u8 = '>' as u8;
+const ASCII_PLUS: u8 = b'+';
+const ASCII_MINUS: u8 = b'-';
+const ASCII_ASTERISK: u8 = b'*';
+const ASCII_FORWARD_SLASH: u8 = b'/';
+const ASCII_EQUAL: u8 = b'=';
+const ASCII_BANG: u8 = b'!';
+const ASCII_LEFT_ARROW: u8 = b'<';
+const ASCII_RIGHT_ARROW: u8 = b'>';
 
 // Delimiters
-const ASCII_SPACE: u8 = ' ' as u8;
-const ASCII_NEWLINE: u8 = '\n' as u8;
-const ASCII_OPEN_PAREN: u8 = '(' as u8;
-const ASCII_CLOSE_PAREN: u8 = ')' as u8;
-const ASCII_OPEN_BRACE: u8 = '{' as u8;
-const ASCII_CLOSE_BRACE: u8 = '}' as u8;
+const ASCII_SPACE: u8 = b' ';
+const ASCII_NEWLINE: u8 = b'\n';
+const ASCII_OPEN_PAREN: u8 = b'(';
+const ASCII_CLOSE_PAREN: u8 = b')';
+const ASCII_OPEN_BRACE: u8 = b'{';
+const ASCII_CLOSE_BRACE: u8 = b'}';
 
 #[derive(Debug, PartialEq)]
 pub struct Tokenizer<R: BufRead> {
     input: Input<R>,
     curr: Option<Token>,
+    span: Span,
     idents: Vec<String>,
 }
 
@@ -48,11 +49,8 @@ impl<R: BufRead> Tokenizer<R> {
         let mut res = Tokenizer {
             input: Input::new(input),
             curr: None,
-            idents: vec![
-                "InputNum".to_string(),
-                "OutputNum".to_string(),
-                "OutputNewLine".to_string(),
-            ],
+            span: Span::default(),
+            idents: PREDEFINED_IDENTS.iter().map(|s| s.to_string()).collect(),
         };
         res.next();
         res
@@ -62,6 +60,15 @@ impl<R: BufRead> Tokenizer<R> {
         self.curr
     }
 
+    // Position of the peeked token
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn idents(&self) -> &[String] {
+        &self.idents
+    }
+
     pub fn get_var(&self, var: usize) -> String {
         self.idents
             .get(var)
@@ -69,8 +76,10 @@ impl<R: BufRead> Tokenizer<R> {
             .to_string()
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn next(&mut self) -> Option<Token> {
         let old = self.curr;
+        self.span = self.input.span();
 
         if let Some(token) = self.input.peek() {
             match token {
@@ -171,18 +180,14 @@ impl<R: BufRead> Tokenizer<R> {
                     let mut res = String::new();
                     res.push(letter as char);
 
-                    loop {
-                        if let letter @ Some(
-                            ASCII_UPPER_A..=ASCII_UPPER_Z
-                            | ASCII_LOWER_A..=ASCII_LOWER_Z
-                            | ASCII_ZERO..=ASCII_NINE,
-                        ) = self.input.peek()
-                        {
-                            self.input.next();
-                            res.push(letter.expect("Should not fail") as char);
-                        } else {
-                            break;
-                        }
+                    while let letter @ Some(
+                        ASCII_UPPER_A..=ASCII_UPPER_Z
+                        | ASCII_LOWER_A..=ASCII_LOWER_Z
+                        | ASCII_ZERO..=ASCII_NINE,
+                    ) = self.input.peek()
+                    {
+                        self.input.next();
+                        res.push(letter.expect("Should not fail") as char);
                     }
 
                     self.curr = Some(match res.as_str() {
@@ -195,14 +200,25 @@ impl<R: BufRead> Tokenizer<R> {
                         "fi" => Token::Fi,
                         "then" => Token::Then,
                         "else" => Token::Else,
+                        "elsif" => Token::Elsif,
                         "while" => Token::While,
                         "do" => Token::Do,
                         "od" => Token::Od,
+                        "for" => Token::For,
+                        "to" => Token::To,
+                        "step" => Token::Step,
+                        "repeat" => Token::Repeat,
+                        "until" => Token::Until,
+                        "break" => Token::Break,
+                        "continue" => Token::Continue,
+                        "and" => Token::And,
+                        "or" => Token::Or,
+                        "not" => Token::Not,
                         "void" => Token::Void,
                         "function" => Token::Function,
                         "return" => Token::Return,
-                        identifier => Token::Ident(
-                            match self.idents.iter().position(|e| e == identifier) {
+                        identifier => {
+                            Token::Ident(match self.idents.iter().position(|e| e == identifier) {
                                 Some(id) => id,
                                 None => {
                                     let res = self.idents.len();
@@ -210,25 +226,17 @@ impl<R: BufRead> Tokenizer<R> {
 
                                     res
                                 }
-                            }
-                            .try_into()
-                            .expect("Should not overflow"),
-                        ),
+                            })
+                        }
                     });
                 }
                 num @ (ASCII_ZERO..=ASCII_NINE) => {
                     self.input.next();
                     let mut res = (num - ASCII_ZERO) as isize;
 
-                    loop {
-                        match self.input.peek() {
-                            num @ Some(ASCII_ZERO..=ASCII_NINE) => {
-                                self.input.next();
-                                res = res * 10
-                                    + (num.expect("Should not fail") - ASCII_ZERO) as isize;
-                            }
-                            _ => break,
-                        }
+                    while let num @ Some(ASCII_ZERO..=ASCII_NINE) = self.input.peek() {
+                        self.input.next();
+                        res = res * 10 + (num.expect("Should not fail") - ASCII_ZERO) as isize;
                     }
 
                     self.curr = Some(Token::Number(res));
@@ -1,6 +1,15 @@
 pub const PREDEFINED_INPUTNUM_ID: usize = 0;
 pub const PREDEFINED_OUTPUTNUM_ID: usize = 1;
 pub const PREDEFINED_OUTPUTNEWLINE_ID: usize = 2;
+// Indexed by the ids above
+pub const PREDEFINED_IDENTS: [&str; 3] = ["InputNum", "OutputNum", "OutputNewLine"];
+
+// 1-based source position of a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Span {
+    pub line: usize,
+    pub col: usize,
+}
 
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RelOp {
@@ -12,6 +21,31 @@ pub enum RelOp {
     GreaterThanOrEqual,
 }
 
+impl RelOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            RelOp::Equal => "==",
+            RelOp::NotEqual => "!=",
+            RelOp::LessThan => "<",
+            RelOp::LessThanOrEqual => "<=",
+            RelOp::GreaterThan => ">",
+            RelOp::GreaterThanOrEqual => ">=",
+        }
+    }
+
+    // Holds exactly when `self` does not
+    pub fn negate(&self) -> RelOp {
+        match self {
+            RelOp::Equal => RelOp::NotEqual,
+            RelOp::NotEqual => RelOp::Equal,
+            RelOp::LessThan => RelOp::GreaterThanOrEqual,
+            RelOp::LessThanOrEqual => RelOp::GreaterThan,
+            RelOp::GreaterThan => RelOp::LessThanOrEqual,
+            RelOp::GreaterThanOrEqual => RelOp::LessThan,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Token {
     // Operators
@@ -35,9 +69,20 @@ pub enum Token {
     Fi,
     Then,
     Else,
+    Elsif,
     While,
     Do,
     Od,
+    For,
+    To,
+    Step,
+    Repeat,
+    Until,
+    Break,
+    Continue,
+    And,
+    Or,
+    Not,
     Void,
     Function,
     Return,
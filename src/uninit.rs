@@ -0,0 +1,477 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::{
+    ast_data::{Call, Computation, Cond, CondKind, Expr, ExprKind, Stmt, StmtKind},
+    diagnostics::{DiagnosticKind, Diagnostics},
+    effects::{self, Effects},
+    tokenizer_data::Span,
+};
+
+// Variables assigned on every path (`must`) and on some path (`may`) to a
+// program point
+#[derive(Debug, Clone, PartialEq)]
+struct State {
+    must: BTreeSet<usize>,
+    may: BTreeSet<usize>,
+    // false after a return
+    reachable: bool,
+}
+
+impl State {
+    fn new() -> State {
+        State {
+            must: BTreeSet::new(),
+            may: BTreeSet::new(),
+            reachable: true,
+        }
+    }
+
+    // The state after a `break`, `continue` or `return`
+    fn unreachable() -> State {
+        State {
+            reachable: false,
+            ..State::new()
+        }
+    }
+
+    fn assign(&mut self, var: usize) {
+        self.must.insert(var);
+        self.may.insert(var);
+    }
+
+    // Where two paths meet
+    fn join(self, other: State) -> State {
+        match (self.reachable, other.reachable) {
+            (true, true) => State {
+                must: self.must.intersection(&other.must).copied().collect(),
+                may: self.may.union(&other.may).copied().collect(),
+                reachable: true,
+            },
+            (true, false) => self,
+            _ => other,
+        }
+    }
+}
+
+// Definite assignment over the control flow of main and each function body
+struct Analysis<'a> {
+    idents: &'a [String],
+    effects: &'a BTreeMap<usize, Effects>,
+    // Variables that start out uninitialized: main's globals or a function's
+    // locals. Params are set by the caller and globals are assumed to be set
+    // when a function runs
+    tracked: BTreeSet<usize>,
+    // Whether callees can assign the tracked variables
+    is_main: bool,
+    // Loop bodies are walked quietly until their entry state settles
+    report: bool,
+    // States at the `break`s and `continue`s of the enclosing loops,
+    // innermost last
+    loops: Vec<(State, State)>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl Analysis<'_> {
+    fn var(&mut self, state: &State, var: usize, span: Span) {
+        if !self.report || !state.reachable || !self.tracked.contains(&var) {
+            return;
+        }
+
+        let how = if state.must.contains(&var) {
+            return;
+        } else if state.may.contains(&var) {
+            "possibly"
+        } else {
+            "definitely"
+        };
+        let message = format!("`{}` is {} uninitialized", self.idents[var], how);
+        self.diagnostics
+            .push(DiagnosticKind::Warning, &message, Some(span));
+    }
+
+    fn call(&mut self, state: &mut State, call: &Call) {
+        for arg in call.args.iter() {
+            self.expression(state, arg);
+        }
+
+        // The callee assigns its must writes and may assign its other writes
+        if let (true, Some(effects)) = (self.is_main, self.effects.get(&call.func)) {
+            state.may.extend(effects.writes.intersection(&self.tracked));
+            state
+                .must
+                .extend(effects.must_writes.intersection(&self.tracked));
+        }
+    }
+
+    fn expression(&mut self, state: &mut State, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Num(_) => {}
+            ExprKind::Var(var) => self.var(state, *var, expr.span),
+            ExprKind::Binary { lhs, rhs, .. } => {
+                self.expression(state, lhs);
+                self.expression(state, rhs);
+            }
+            ExprKind::Call(call) => self.call(state, call),
+        }
+    }
+
+    // Short-circuiting can skip `rhs`, so the calls in it may assign their
+    // writes but must assign nothing
+    fn cond(&mut self, state: &mut State, cond: &Cond) {
+        match &cond.kind {
+            CondKind::Relation(relation) => {
+                self.expression(state, &relation.lhs);
+                self.expression(state, &relation.rhs);
+            }
+            CondKind::Not(cond) => self.cond(state, cond),
+            CondKind::Logical { lhs, rhs, .. } => {
+                self.cond(state, lhs);
+                let mut rhs_state = state.clone();
+                self.cond(&mut rhs_state, rhs);
+                state.may.extend(rhs_state.may);
+            }
+        }
+    }
+
+    // The states at the end of the body, joined with its `continue`s, and at
+    // its `break`s
+    fn loop_body(&mut self, state: State, body: &[Stmt]) -> (State, State) {
+        self.loops
+            .push((State::unreachable(), State::unreachable()));
+        let end = self.stat_sequence(state, body);
+        let (breaks, continues) = self.loops.pop().expect("Loop should be pushed");
+
+        (end.join(continues), breaks)
+    }
+
+    // Assignments later in the body reach the start of the loop through the
+    // back edge. `back` walks one iteration from the loop entry to the end of
+    // the latch, the entry is settled quietly before the reporting pass
+    fn r#loop(
+        &mut self,
+        state: State,
+        mut back: impl FnMut(&mut Self, State) -> (State, State),
+    ) -> (State, State) {
+        let report = std::mem::replace(&mut self.report, false);
+        let mut entry = state;
+        loop {
+            let (latch, _) = back(self, entry.clone());
+
+            let mut next = entry.clone();
+            if latch.reachable {
+                next.may.extend(latch.may);
+            }
+            if next == entry {
+                break;
+            }
+            entry = next;
+        }
+        self.report = report;
+
+        back(self, entry)
+    }
+
+    fn r#while(&mut self, state: State, cond: &Cond, body: &[Stmt]) -> State {
+        let mut exit = State::unreachable();
+        let (_, breaks) = self.r#loop(state, |analysis, mut state| {
+            analysis.cond(&mut state, cond);
+            exit = state.clone();
+            analysis.loop_body(state, body)
+        });
+        exit.join(breaks)
+    }
+
+    fn r#for(
+        &mut self,
+        mut state: State,
+        var: usize,
+        (from, to, step): (&Expr, &Expr, Option<&Expr>),
+        body: &[Stmt],
+    ) -> State {
+        self.expression(&mut state, from);
+        state.assign(var);
+        self.expression(&mut state, to);
+        if let Some(step) = step {
+            self.expression(&mut state, step);
+        }
+
+        let mut exit = State::unreachable();
+        let (_, breaks) = self.r#loop(state, |analysis, state| {
+            exit = state.clone();
+            analysis.loop_body(state, body)
+        });
+        exit.join(breaks)
+    }
+
+    fn repeat(&mut self, state: State, body: &[Stmt], cond: &Cond) -> State {
+        let (exit, breaks) = self.r#loop(state, |analysis, state| {
+            let (mut latch, breaks) = analysis.loop_body(state, body);
+            analysis.cond(&mut latch, cond);
+            (latch, breaks)
+        });
+        exit.join(breaks)
+    }
+
+    fn statement(&mut self, mut state: State, stmt: &Stmt) -> State {
+        match &stmt.kind {
+            StmtKind::Assign { var, expr } => {
+                self.expression(&mut state, expr);
+                state.assign(*var);
+                state
+            }
+            StmtKind::Call(call) => {
+                self.call(&mut state, call);
+                state
+            }
+            StmtKind::If {
+                cond,
+                then_body,
+                elsifs,
+                else_body,
+            } => {
+                self.cond(&mut state, cond);
+                let mut then_state = self.stat_sequence(state.clone(), then_body);
+                // Each `elsif` condition runs once the ones before it failed
+                for (cond, body) in elsifs {
+                    self.cond(&mut state, cond);
+                    then_state = then_state.join(self.stat_sequence(state.clone(), body));
+                }
+                let else_state = match else_body {
+                    Some(else_body) => self.stat_sequence(state, else_body),
+                    None => state,
+                };
+                then_state.join(else_state)
+            }
+            StmtKind::While { cond, body } => self.r#while(state, cond, body),
+            StmtKind::For {
+                var,
+                from,
+                to,
+                step,
+                body,
+            } => self.r#for(state, *var, (from, to, step.as_ref()), body),
+            StmtKind::Repeat { body, cond } => self.repeat(state, body, cond),
+            StmtKind::Break => {
+                let (breaks, _) = self.loops.last_mut().expect("Break should be in a loop");
+                *breaks = std::mem::replace(breaks, State::unreachable()).join(state);
+                State::unreachable()
+            }
+            StmtKind::Continue => {
+                let (_, continues) = self.loops.last_mut().expect("Continue should be in a loop");
+                *continues = std::mem::replace(continues, State::unreachable()).join(state);
+                State::unreachable()
+            }
+            StmtKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(&mut state, expr);
+                }
+                state.reachable = false;
+                state
+            }
+        }
+    }
+
+    fn stat_sequence(&mut self, mut state: State, stmts: &[Stmt]) -> State {
+        for stmt in stmts {
+            state = self.statement(state, stmt);
+        }
+        state
+    }
+}
+
+// Warns about reads of variables that are not assigned on every path to them
+pub fn check(ast: &Computation, idents: &[String], diagnostics: &mut Diagnostics) {
+    let effects = effects::global_effects(ast);
+    let mut analysis = Analysis {
+        idents,
+        effects: &effects,
+        tracked: BTreeSet::new(),
+        is_main: false,
+        report: true,
+        loops: Vec::new(),
+        diagnostics,
+    };
+
+    for func in ast.funcs.iter() {
+        analysis.tracked = func.vars.iter().map(|decl| decl.var).collect();
+        analysis.stat_sequence(State::new(), &func.body);
+    }
+
+    analysis.tracked = ast.vars.iter().map(|decl| decl.var).collect();
+    analysis.is_main = true;
+    analysis.stat_sequence(State::new(), &ast.body);
+}
+
+#[cfg(test)]
+mod test {
+    use crate::parser::Parser;
+
+    use super::*;
+
+    fn check(input: &[u8]) -> Vec<(String, Span)> {
+        let mut parser = Parser::new(input);
+        let ast = parser.computation().expect("Should parse");
+
+        let mut diagnostics = Diagnostics::new();
+        super::check(&ast, parser.idents(), &mut diagnostics);
+        diagnostics
+            .iter()
+            .map(|diagnostic| {
+                assert_eq!(diagnostic.kind, DiagnosticKind::Warning);
+                (
+                    diagnostic.message.clone(),
+                    diagnostic.span.expect("Should have a span"),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn straight_line() {
+        assert_eq!(
+            check(b"main var x, y; {\n  let x <- y + 1;\n  call OutputNum(x + y) }."),
+            vec![
+                (
+                    "`y` is definitely uninitialized".to_string(),
+                    Span { line: 2, col: 12 }
+                ),
+                (
+                    "`y` is definitely uninitialized".to_string(),
+                    Span { line: 3, col: 22 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn branches() {
+        assert_eq!(
+            check(
+                b"main var a, b, c; {\n  let a <- call InputNum();\n\
+                  if a > 0 then let b <- 1; let c <- 1 else let c <- 2 fi;\n\
+                  call OutputNum(b + c) }."
+            ),
+            vec![(
+                "`b` is possibly uninitialized".to_string(),
+                Span { line: 4, col: 16 }
+            )]
+        );
+    }
+
+    #[test]
+    fn elsif_chain() {
+        assert_eq!(
+            check(
+                b"main var a, b, c; {\n  let a <- call InputNum();\n\
+                  if a > 0 then let b <- 1; let c <- 1 elsif a < 0 then let c <- 2 \
+                  else let b <- 3; let c <- 3 fi;\n\
+                  call OutputNum(b + c) }."
+            ),
+            vec![(
+                "`b` is possibly uninitialized".to_string(),
+                Span { line: 4, col: 16 }
+            )]
+        );
+    }
+
+    #[test]
+    fn loops() {
+        assert_eq!(
+            check(
+                b"main var i, x, y; {\n  let i <- 0;\n\
+                  while i < 10 do\n    if i > 0 then call OutputNum(x) fi;\n\
+                  let x <- i; let i <- i + 1 od;\n  call OutputNum(x) }."
+            ),
+            vec![
+                (
+                    "`x` is possibly uninitialized".to_string(),
+                    Span { line: 4, col: 34 }
+                ),
+                (
+                    "`x` is possibly uninitialized".to_string(),
+                    Span { line: 6, col: 18 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn loop_exits() {
+        assert_eq!(
+            check(
+                b"main var i, x, y; {\n\
+                  for i <- 1 to 3 do if i == 2 then let x <- i; break fi; continue; let y <- 1 od;\n\
+                  call OutputNum(x + y);\n\
+                  repeat let x <- 1; let y <- x until x > 0;\n  call OutputNum(x + y) }."
+            ),
+            vec![
+                (
+                    "`x` is possibly uninitialized".to_string(),
+                    Span { line: 3, col: 16 }
+                ),
+                (
+                    "`y` is definitely uninitialized".to_string(),
+                    Span { line: 3, col: 20 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(
+            check(
+                b"main var g, h;\nvoid function set(); { let g <- 1 };\n\
+                  function f(a); var b; { if a > 0 then return b fi; let b <- a; return b + g };\n\
+                  { call set(); call OutputNum(call f(g) + h) }."
+            ),
+            vec![
+                (
+                    "`b` is definitely uninitialized".to_string(),
+                    Span { line: 3, col: 46 }
+                ),
+                (
+                    "`h` is definitely uninitialized".to_string(),
+                    Span { line: 4, col: 42 }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn short_circuit_calls() {
+        assert_eq!(
+            check(
+                b"main var x;\nfunction setx(); { let x <- 1; return 1 };\n\
+                  { if 1 > 2 and call setx() > 0 then call OutputNewLine() fi;\n\
+                  call OutputNum(x) }."
+            ),
+            vec![(
+                "`x` is possibly uninitialized".to_string(),
+                Span { line: 4, col: 16 }
+            )]
+        );
+    }
+
+    #[test]
+    fn call_writes() {
+        assert_eq!(
+            check(
+                b"main var a, b, c, d;\n\
+                  void function f(n); { let a <- n; if n > 0 then let b <- n fi; call g(n) };\n\
+                  void function g(n); { repeat let c <- n until n > 0; while n > 0 do let d <- n od };\n\
+                  { call f(1); call OutputNum(a + b + c + d) }."
+            ),
+            vec![
+                (
+                    "`b` is possibly uninitialized".to_string(),
+                    Span { line: 4, col: 33 }
+                ),
+                (
+                    "`d` is possibly uninitialized".to_string(),
+                    Span { line: 4, col: 41 }
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+// Compiles every `tests/programs/<name>.txt` and compares the graph, the
+// textual IR and the interpreter output with `<name>.dot`, `<name>.ssa` and
+// `<name>.out`. `<name>.in` is used as stdin when present.
+//
+// Run with `BLESS=1 cargo test --test golden` to rewrite the expected files.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use project1::{compile, parse_ir, CompileOptions, Module, Passes};
+
+const STEP_LIMIT: usize = 1_000_000;
+// Unchanged lines shown around each difference
+const CONTEXT: usize = 2;
+
+fn programs() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut programs = fs::read_dir(&dir)
+        .expect("Programs directory should exist")
+        .map(|entry| entry.expect("Should read entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect::<Vec<_>>();
+    programs.sort();
+    programs
+}
+
+fn run(module: &Module, stdin: &[u8]) -> String {
+    let mut stdout = Vec::new();
+    let res = module
+        .interpreter()
+        .step_limit(STEP_LIMIT)
+        .run(stdin, &mut stdout);
+
+    let mut data = String::from_utf8_lossy(&stdout).to_string();
+    if let Err(err) = res {
+        data += format!("\nerror: {:?}", err).as_str();
+    }
+    data + "\n"
+}
+
+// Line diff of `expected` against `actual` built from their longest common
+// subsequence
+fn diff(expected: &str, actual: &str) -> String {
+    let expected = expected.lines().collect::<Vec<_>>();
+    let actual = actual.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the common length of expected[i..] and actual[j..]
+    let mut lcs = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            lcs[i][j] = if expected[i] == actual[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut lines = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            lines.push((' ', expected[i]));
+            i += 1;
+            j += 1;
+        } else if i < expected.len() && (j == actual.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            lines.push(('-', expected[i]));
+            i += 1;
+        } else {
+            lines.push(('+', actual[j]));
+            j += 1;
+        }
+    }
+
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, (marker, _))| *marker != ' ')
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+
+    let mut data = String::new();
+    let mut last = None;
+    for (idx, (marker, line)) in lines.iter().enumerate() {
+        let near = changed
+            .iter()
+            .any(|&change| idx + CONTEXT >= change && idx <= change + CONTEXT);
+        if !near {
+            continue;
+        }
+        if last.is_some_and(|last| last + 1 != idx) {
+            data += "  ...\n";
+        }
+        data += format!("{} {}\n", marker, line).as_str();
+        last = Some(idx);
+    }
+    data
+}
+
+#[test]
+fn golden() {
+    let bless = std::env::var_os("BLESS").is_some();
+    let mut failures = Vec::new();
+
+    for program in programs() {
+        let source = fs::read(&program).expect("Should read program");
+        let stdin = fs::read(program.with_extension("in")).unwrap_or_default();
+
+        let module = match compile(&source, &CompileOptions::default()) {
+            Ok(module) => module,
+            Err(diagnostics) => {
+                failures.push(format!("{}:\n{}", program.display(), diagnostics));
+                continue;
+            }
+        };
+
+        let outputs = [
+            ("dot", module.graph() + "\n"),
+            ("ssa", module.instructions() + "\n"),
+            ("out", run(&module, &stdin)),
+        ];
+
+        for (extension, actual) in outputs {
+            let path = program.with_extension(extension);
+            if bless {
+                fs::write(&path, actual).expect("Should write expected file");
+                continue;
+            }
+
+            match fs::read_to_string(&path) {
+                Ok(expected) if expected == actual => {}
+                Ok(expected) => failures.push(format!(
+                    "{} differs (- expected, + actual):\n{}",
+                    path.display(),
+                    diff(&expected, &actual)
+                )),
+                Err(_) => failures.push(format!("{} is missing", path.display())),
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "\n{}\nRun with BLESS=1 to accept the new output",
+        failures.join("\n")
+    );
+}
+
+// The textual IR of every program at every level parses back to the same IR
+#[test]
+fn round_trip() {
+    let mut failures = Vec::new();
+
+    for program in programs() {
+        let source = fs::read(&program).expect("Should read program");
+        for level in 0..=2 {
+            let options = CompileOptions {
+                passes: Passes::level(level),
+                ..CompileOptions::default()
+            };
+            let module = compile(&source, &options).unwrap_or_else(|diagnostics| {
+                panic!("{} -O{}:\n{}", program.display(), level, diagnostics)
+            });
+
+            let ssa = module.instructions();
+            match parse_ir(&ssa) {
+                Ok(parsed) if parsed.instructions() == ssa => {}
+                Ok(parsed) => failures.push(format!(
+                    "{} -O{} prints differently after parsing (- printed, + reparsed):\n{}",
+                    program.display(),
+                    level,
+                    diff(&ssa, &parsed.instructions())
+                )),
+                Err(diagnostics) => failures.push(format!(
+                    "{} -O{} does not parse:\n{}",
+                    program.display(),
+                    level,
+                    diagnostics
+                )),
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn diff_context() {
+    let expected = "a\nb\nc\nd\ne\nf\ng\nh\ni\n";
+    let actual = "a\nB\nc\nd\ne\nf\ng\nh\ni\nj\n";
+
+    assert_eq!(
+        diff(expected, actual),
+        "  a\n- b\n+ B\n  c\n  d\n  ...\n  h\n  i\n+ j\n"
+    );
+}